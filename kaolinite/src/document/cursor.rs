@@ -1,3 +1,4 @@
+use crate::document::Gravity;
 use crate::event::Status;
 use crate::utils::{tab_boundaries_backward, tab_boundaries_forward, width};
 use crate::{Document, Loc};
@@ -288,6 +289,12 @@ impl Document {
         self.load_to(self.offset.y + self.size.h);
     }
 
+    /// Move the view so that the cursor is in the middle of it
+    pub fn center_cursor(&mut self) {
+        self.offset.y = self.cursor.loc.y.saturating_sub(self.size.h / 2);
+        self.load_to(self.offset.y + self.size.h);
+    }
+
     /// Move the view down
     pub fn scroll_down(&mut self) {
         self.offset.y += 1;
@@ -423,23 +430,38 @@ impl Document {
         self.cursor.selection_end = self.cursor.loc;
     }
 
-    /// Create a new alternative cursor
+    /// Create a new alternative cursor (or remove the one already there)
     pub fn new_cursor(&mut self, loc: Loc) {
         if let Some(idx) = self.has_cursor(loc) {
-            self.secondary_cursors.remove(idx);
+            let id = self.secondary_cursors.remove(idx);
+            self.remove_marker(id);
         } else if self.out_of_range(loc.x, loc.y).is_ok() {
-            self.secondary_cursors.push(loc);
+            let id = self.add_marker(loc, Gravity::Right);
+            self.secondary_cursors.push(id);
         }
     }
 
     /// Clear all secondary cursors
     pub fn clear_cursors(&mut self) {
-        self.secondary_cursors.clear();
+        for id in std::mem::take(&mut self.secondary_cursors) {
+            self.remove_marker(id);
+        }
+    }
+
+    /// Find where all the secondary cursors currently are
+    #[must_use]
+    pub fn cursors(&self) -> Vec<Loc> {
+        self.secondary_cursors
+            .iter()
+            .filter_map(|id| self.marker_loc(*id))
+            .collect()
     }
 
     /// Determine if there is a secondary cursor at a certain position
     #[must_use]
     pub fn has_cursor(&self, loc: Loc) -> Option<usize> {
-        self.secondary_cursors.iter().position(|c| *c == loc)
+        self.secondary_cursors
+            .iter()
+            .position(|id| self.marker_loc(*id) == Some(loc))
     }
 }
@@ -1,11 +1,11 @@
-use crate::document::Cursor;
+use crate::document::{Cursor, Markers};
 use crate::event::{Error, EventMgmt, Result};
 use crate::map::{form_map, CharMap};
 use crate::utils::get_absolute_path;
 use crate::{Document, Loc, Size};
 use ropey::Rope;
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Read};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 
 /// A document info struct to store information about the file it represents
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -16,6 +16,12 @@ pub struct DocumentInfo {
     pub eol: bool,
     /// Contains the number of lines buffered into the document
     pub loaded_to: usize,
+    /// The line ending to save the file with (None keeps the line endings as they are)
+    pub line_ending: Option<String>,
+    /// Whether to save the file with a byte order mark (None keeps it as it is)
+    pub bom: Option<bool>,
+    /// Whether to save the file with a line ending at the end (None keeps it as it is)
+    pub final_newline: Option<bool>,
 }
 
 impl Document {
@@ -41,8 +47,12 @@ impl Document {
                 loaded_to: 1,
                 eol: false,
                 read_only: false,
+                line_ending: None,
+                bom: None,
+                final_newline: None,
             },
             secondary_cursors: vec![],
+            markers: Markers::default(),
         }
     }
 
@@ -67,6 +77,9 @@ impl Document {
                     .to_string()
                     .is_empty(),
                 read_only: false,
+                line_ending: None,
+                bom: None,
+                final_newline: None,
             },
             file,
             lines: vec![],
@@ -82,6 +95,7 @@ impl Document {
             old_cursor: 0,
             in_redo: false,
             secondary_cursors: vec![],
+            markers: Markers::default(),
         })
     }
 
@@ -93,8 +107,7 @@ impl Document {
         if self.info.read_only {
             Err(Error::ReadOnlyFile)
         } else if let Some(file_name) = &self.file_name {
-            self.file
-                .write_to(BufWriter::new(File::create(file_name)?))?;
+            self.write_file(file_name)?;
             self.event_mgmt.disk_write(&self.take_snapshot());
             Ok(())
         } else {
@@ -110,10 +123,56 @@ impl Document {
         if self.info.read_only {
             Err(Error::ReadOnlyFile)
         } else {
+            self.write_file(file_name)?;
+            Ok(())
+        }
+    }
+
+    /// Write the document out to a file
+    fn write_file(&self, file_name: &str) -> Result<()> {
+        let info = &self.info;
+        if info.line_ending.is_none() && info.bom.is_none() && info.final_newline.is_none() {
             self.file
                 .write_to(BufWriter::new(File::create(file_name)?))?;
-            Ok(())
+        } else {
+            let mut writer = BufWriter::new(File::create(file_name)?);
+            writer.write_all(self.output().as_bytes())?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// The text of the document as it will be saved to the disk
+    /// (with the line ending, byte order mark and final line ending settings applied)
+    #[must_use]
+    pub fn output(&self) -> String {
+        let mut text = self.file.to_string();
+        let has_bom = text.starts_with('\u{feff}');
+        match self.info.bom {
+            Some(true) if !has_bom => text.insert(0, '\u{feff}'),
+            Some(false) if has_bom => {
+                text.remove(0);
+            }
+            _ => (),
+        }
+        if let Some(ending) = &self.info.line_ending {
+            text = text.replace("\r\n", "\n").replace('\r', "\n");
+            if ending != "\n" {
+                text = text.replace('\n', ending);
+            }
         }
+        let ending = self.info.line_ending.as_deref().unwrap_or("\n");
+        match self.info.final_newline {
+            Some(true) if !text.is_empty() && !text.ends_with(['\n', '\r']) => {
+                text.push_str(ending);
+            }
+            Some(false) => {
+                let trimmed = text.trim_end_matches(['\n', '\r']).len();
+                text.truncate(trimmed);
+            }
+            _ => (),
+        }
+        text
     }
 
     /// Load lines in this document up to a specified index.
@@ -142,6 +201,37 @@ impl Document {
             self.info.loaded_to = to;
         }
     }
+
+    /// Replace the entire contents of the document (e.g. for buffers whose text is generated).
+    /// This clears the undo history, as the old contents can no longer be returned to.
+    pub fn set_text(&mut self, text: &str) {
+        // (the text is given a final newline, unless it already has one)
+        self.file = if text.ends_with('\n') {
+            Rope::from_str(text)
+        } else {
+            Rope::from_str(&format!("{text}\n"))
+        };
+        self.lines.clear();
+        self.dbl_map = CharMap::default();
+        self.tab_map = CharMap::default();
+        self.info.loaded_to = 0;
+        self.info.eol = false;
+        self.load_to(self.len_lines());
+        self.event_mgmt = EventMgmt {
+            force_not_with_disk: self.event_mgmt.force_not_with_disk,
+            ..EventMgmt::default()
+        };
+        // Keep the cursor and markers (such as those anchoring decorations) within the new text
+        self.clear_cursors();
+        let last = self.len_lines().saturating_sub(1);
+        for marker in self.markers.items.values_mut() {
+            let y = marker.loc.y.min(last);
+            let len = self.lines.get(y).map_or(0, |line| line.chars().count());
+            marker.loc = Loc::at(marker.loc.x.min(len), y);
+        }
+        let y = self.loc().y.min(last);
+        self.move_to(&Loc::at(0, y));
+    }
 }
 
 pub fn load_rope_from_reader<T: Read + BufRead>(mut reader: T) -> Rope {
@@ -1,4 +1,4 @@
-use crate::event::{Error, Result};
+use crate::event::{Error, Event, Result};
 use crate::utils::trim;
 use crate::{Document, Loc};
 
@@ -64,6 +64,33 @@ impl Document {
         Ok(())
     }
 
+    /// Remove the whitespace from the end of every line (keeping the cursor on the same line)
+    /// # Errors
+    /// When a line fails to be edited
+    pub fn trim_trailing_whitespace(&mut self) -> Result<()> {
+        if self.info.read_only {
+            return Ok(());
+        }
+        self.load_to(self.len_lines());
+        let cursor = self.char_loc();
+        for y in 0..self.len_lines() {
+            let line = self.line(y).unwrap_or_default();
+            let x = line.trim_end().chars().count();
+            if x < line.chars().count() {
+                let whitespace: String = line.chars().skip(x).collect();
+                self.delete(x.., y)?;
+                self.markers
+                    .shift(&Event::Delete(Loc::at(x, y), whitespace));
+            }
+        }
+        let len = self.line(cursor.y).map_or(0, |line| line.chars().count());
+        self.move_to(&Loc {
+            x: cursor.x.min(len),
+            y: cursor.y,
+        });
+        Ok(())
+    }
+
     /// Select a line at a location
     pub fn select_line_at(&mut self, y: usize) {
         let len = self.line(y).unwrap_or_default().chars().count();
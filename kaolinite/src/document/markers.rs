@@ -0,0 +1,126 @@
+use crate::event::Event;
+use crate::{Document, Loc};
+use std::collections::BTreeMap;
+
+/// Identifies a marker within a document
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct MarkerId(pub usize);
+
+/// Determines which way a marker moves when text is inserted exactly where it is
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Gravity {
+    /// Stay before the inserted text
+    #[default]
+    Left,
+    /// Move along to after the inserted text
+    Right,
+}
+
+/// A location in the document that keeps up with edits
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Marker {
+    pub loc: Loc,
+    pub gravity: Gravity,
+}
+
+impl Marker {
+    /// Move this marker to account for an editing event
+    pub fn shift(&mut self, ev: &Event) {
+        let loc = &mut self.loc;
+        let right = self.gravity == Gravity::Right;
+        match ev {
+            Event::Insert(at, st) => {
+                if loc.y == at.y && (loc.x > at.x || loc.x == at.x && right) {
+                    loc.x += st.chars().count();
+                }
+            }
+            Event::Delete(at, st) => {
+                let end = at.x + st.chars().count();
+                if loc.y == at.y && loc.x >= end {
+                    loc.x -= end - at.x;
+                } else if loc.y == at.y && loc.x > at.x {
+                    loc.x = at.x;
+                }
+            }
+            Event::InsertLine(y, _) => {
+                if loc.y >= *y {
+                    loc.y += 1;
+                }
+            }
+            Event::DeleteLine(y, _) => {
+                if loc.y > *y {
+                    loc.y -= 1;
+                } else if loc.y == *y {
+                    loc.x = 0;
+                }
+            }
+            Event::SplitDown(at) => {
+                if loc.y == at.y && (loc.x > at.x || loc.x == at.x && right) {
+                    *loc = Loc::at(loc.x - at.x, loc.y + 1);
+                } else if loc.y > at.y {
+                    loc.y += 1;
+                }
+            }
+            Event::SpliceUp(at) => {
+                if loc.y == at.y + 1 {
+                    *loc = Loc::at(loc.x + at.x, at.y);
+                } else if loc.y > at.y + 1 {
+                    loc.y -= 1;
+                }
+            }
+        }
+    }
+}
+
+/// Stores all the markers within a document
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Markers {
+    pub items: BTreeMap<MarkerId, Marker>,
+    next_id: usize,
+}
+
+impl Markers {
+    /// Move all markers to account for an editing event
+    pub fn shift(&mut self, ev: &Event) {
+        for marker in self.items.values_mut() {
+            marker.shift(ev);
+        }
+    }
+
+    /// Put markers back to where they were in a snapshot (markers made since are left alone)
+    pub fn restore(&mut self, snapshot: &Markers) {
+        for (id, marker) in &mut self.items {
+            if let Some(old) = snapshot.items.get(id) {
+                marker.loc = old.loc;
+            }
+        }
+    }
+}
+
+impl Document {
+    /// Add a marker at a location, which will move with any edits made to the document
+    pub fn add_marker(&mut self, loc: Loc, gravity: Gravity) -> MarkerId {
+        self.markers.next_id += 1;
+        let id = MarkerId(self.markers.next_id);
+        self.markers.items.insert(id, Marker { loc, gravity });
+        id
+    }
+
+    /// Find out where a marker currently is
+    #[must_use]
+    pub fn marker_loc(&self, id: MarkerId) -> Option<Loc> {
+        self.markers.items.get(&id).map(|marker| marker.loc)
+    }
+
+    /// Move a marker somewhere else
+    pub fn move_marker(&mut self, id: MarkerId, loc: Loc) {
+        if let Some(marker) = self.markers.items.get_mut(&id) {
+            marker.loc = loc;
+        }
+    }
+
+    /// Remove a marker, returning where it was (if it existed)
+    pub fn remove_marker(&mut self, id: MarkerId) -> Option<Loc> {
+        self.markers.items.remove(&id).map(|marker| marker.loc)
+    }
+}
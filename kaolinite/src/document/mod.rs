@@ -10,10 +10,12 @@ pub mod cursor;
 pub mod disk;
 pub mod editing;
 pub mod lines;
+pub mod markers;
 pub mod words;
 
 pub use cursor::Cursor;
 pub use disk::DocumentInfo;
+pub use markers::{Gravity, Marker, MarkerId, Markers};
 
 /// A document struct manages a file.
 /// It has tools to read, write and traverse a document.
@@ -50,8 +52,10 @@ pub struct Document {
     pub in_redo: bool,
     /// The number of spaces a tab should be rendered as
     pub tab_width: usize,
-    /// Secondary cursor (for multi-cursors)
-    pub secondary_cursors: Vec<Loc>,
+    /// Secondary cursors (for multi-cursors), as markers so they move along with edits
+    pub secondary_cursors: Vec<MarkerId>,
+    /// Locations that move along with edits (see `Document::add_marker`)
+    pub markers: Markers,
 }
 
 impl Document {
@@ -115,14 +119,33 @@ impl Document {
     /// # Errors
     /// Returns an error if there is a problem with the specified operation.
     pub fn forth(&mut self, ev: Event) -> Result<()> {
+        // Work out how markers will move before the document is changed
+        let shift = self.marker_shift(&ev);
         // Perform the event
-        match ev {
+        let result = match ev {
             Event::Insert(loc, ch) => self.insert(&loc, &ch),
             Event::Delete(loc, st) => self.delete_with_tab(&loc, &st),
             Event::InsertLine(loc, st) => self.insert_line(loc, st),
             Event::DeleteLine(loc, _) => self.delete_line(loc),
             Event::SplitDown(loc) => self.split_down(&loc),
             Event::SpliceUp(loc) => self.splice_up(loc.y),
+        };
+        // Keep markers up to date (leaving them alone should the event fail)
+        if result.is_ok() {
+            self.markers.shift(&shift);
+        }
+        result
+    }
+
+    /// Find the event that markers should be moved by to account for an event
+    /// (this is done before the event is performed, while the document is still as it was)
+    fn marker_shift(&self, ev: &Event) -> Event {
+        if let Event::SpliceUp(loc) = ev {
+            // The line below will be joined onto the end of this line
+            let length = self.line(loc.y).map_or(loc.x, |line| line.chars().count());
+            Event::SpliceUp(Loc::at(length, loc.y))
+        } else {
+            ev.clone()
         }
     }
 
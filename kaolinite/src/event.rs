@@ -1,5 +1,9 @@
 /// event.rs - manages editing events and provides tools for error handling
-use crate::{document::Cursor, utils::Loc, Document};
+use crate::{
+    document::{Cursor, Markers},
+    utils::Loc,
+    Document,
+};
 use error_set::error_set;
 use ropey::Rope;
 
@@ -8,6 +12,7 @@ use ropey::Rope;
 pub struct Snapshot {
     pub content: Rope,
     pub cursor: Cursor,
+    pub markers: Markers,
 }
 
 /// Represents an editing event.
@@ -110,12 +115,14 @@ impl Document {
         Snapshot {
             content: self.file.clone(),
             cursor: self.cursor,
+            markers: self.markers.clone(),
         }
     }
 
     pub fn apply_snapshot(&mut self, snapshot: Snapshot) {
         self.file = snapshot.content;
         self.cursor = snapshot.cursor;
+        self.markers.restore(&snapshot.markers);
         self.char_ptr = self.character_idx(&snapshot.cursor.loc);
         self.reload_lines();
         self.bring_cursor_in_viewport();
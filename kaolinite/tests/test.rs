@@ -328,6 +328,35 @@ fn document_disks() {
     assert_eq!(result, st!("\n"));
 }
 
+#[test]
+fn document_output() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.set_text("a  \n\tb\t\nc");
+    doc.move_to(&Loc { x: 3, y: 0 });
+    // Untouched by default
+    assert_eq!(doc.output(), st!("a  \n\tb\t\nc\n"));
+    // Line endings
+    doc.info.line_ending = Some(st!("\r\n"));
+    assert_eq!(doc.output(), st!("a  \r\n\tb\t\r\nc\r\n"));
+    // Final line endings
+    doc.info.final_newline = Some(false);
+    assert_eq!(doc.output(), st!("a  \r\n\tb\t\r\nc"));
+    doc.info.line_ending = None;
+    doc.info.final_newline = Some(true);
+    assert_eq!(doc.output(), st!("a  \n\tb\t\nc\n"));
+    // Byte order marks
+    doc.info.bom = Some(true);
+    assert_eq!(doc.output(), st!("\u{feff}a  \n\tb\t\nc\n"));
+    doc.info.bom = Some(false);
+    assert_eq!(doc.output(), st!("a  \n\tb\t\nc\n"));
+    // Trailing whitespace
+    doc.trim_trailing_whitespace().unwrap();
+    assert_eq!(doc.line(0), Some(st!("a")));
+    assert_eq!(doc.line(1), Some(st!("\tb")));
+    assert_eq!(doc.char_loc(), Loc { x: 1, y: 0 });
+    assert_eq!(doc.output(), st!("a\n\tb\nc\n"));
+}
+
 #[test]
 fn document_insertion() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
@@ -735,6 +764,14 @@ fn document_scrolling() {
     doc.scroll_up();
     assert_eq!(doc.offset.y, 0);
     assert_eq!(doc.info.loaded_to, 11);
+    // Centering the cursor
+    doc.move_to(&Loc { x: 0, y: 50 });
+    doc.center_cursor();
+    assert_eq!(doc.offset.y, 45);
+    assert!(doc.info.loaded_to >= 55);
+    doc.move_to(&Loc { x: 0, y: 3 });
+    doc.center_cursor();
+    assert_eq!(doc.offset.y, 0);
 }
 
 #[test]
@@ -841,6 +878,128 @@ fn document_splitting_splicing() {
     assert_eq!(doc.dbl_map.get(5), None);
 }
 
+#[test]
+fn document_markers() {
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
+    doc.load_to(100);
+    let left = doc.add_marker(Loc { x: 5, y: 2 }, Gravity::Left);
+    let right = doc.add_marker(Loc { x: 5, y: 2 }, Gravity::Right);
+    let below = doc.add_marker(Loc { x: 2, y: 4 }, Gravity::Left);
+    // Insertion
+    doc.exe(Event::Insert(Loc { x: 5, y: 2 }, st!("你a")));
+    assert_eq!(doc.marker_loc(left), Some(Loc { x: 5, y: 2 }));
+    assert_eq!(doc.marker_loc(right), Some(Loc { x: 7, y: 2 }));
+    doc.exe(Event::Insert(Loc { x: 0, y: 2 }, st!("b")));
+    assert_eq!(doc.marker_loc(left), Some(Loc { x: 6, y: 2 }));
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 2, y: 4 }));
+    // Deletion
+    doc.exe(Event::Delete(Loc { x: 0, y: 2 }, st!("b")));
+    assert_eq!(doc.marker_loc(left), Some(Loc { x: 5, y: 2 }));
+    doc.exe(Event::Delete(Loc { x: 4, y: 2 }, st!("h你")));
+    assert_eq!(doc.marker_loc(left), Some(Loc { x: 4, y: 2 }));
+    assert_eq!(doc.marker_loc(right), Some(Loc { x: 5, y: 2 }));
+    assert_eq!(doc.line(2), Some(st!("    aello")));
+    // Lines
+    doc.exe(Event::InsertLine(1, st!("new")));
+    assert_eq!(doc.marker_loc(right), Some(Loc { x: 5, y: 3 }));
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 2, y: 5 }));
+    doc.exe(Event::DeleteLine(1, st!("new")));
+    assert_eq!(doc.marker_loc(right), Some(Loc { x: 5, y: 2 }));
+    doc.exe(Event::DeleteLine(2, st!("    aello")));
+    assert_eq!(doc.marker_loc(right), Some(Loc { x: 0, y: 2 }));
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 2, y: 3 }));
+    // Splitting and splicing
+    doc.exe(Event::SplitDown(Loc { x: 1, y: 3 }));
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 1, y: 4 }));
+    doc.exe(Event::SpliceUp(Loc { x: 1, y: 3 }));
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 2, y: 3 }));
+    assert_eq!(doc.line(3), Some(st!("hello你world好hello")));
+    // Failed events leave markers alone
+    doc.exe(Event::Insert(Loc { x: 0, y: 1000 }, st!("a")));
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 2, y: 3 }));
+    // Undo and redo
+    doc.commit();
+    doc.exe(Event::Insert(Loc { x: 0, y: 3 }, st!("abc")));
+    doc.commit();
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 5, y: 3 }));
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 2, y: 3 }));
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.marker_loc(below), Some(Loc { x: 5, y: 3 }));
+    // Removal
+    assert_eq!(doc.remove_marker(below), Some(Loc { x: 5, y: 3 }));
+    assert_eq!(doc.marker_loc(below), None);
+    assert_eq!(doc.remove_marker(below), None);
+}
+
+#[test]
+fn document_secondary_cursors() {
+    let mut doc = Document::new(Size::is(100, 10));
+    assert!(doc
+        .exe(Event::Insert(Loc { x: 0, y: 0 }, st!("one two")))
+        .is_ok());
+    assert!(doc.exe(Event::SplitDown(Loc { x: 3, y: 0 })).is_ok());
+    doc.new_cursor(Loc { x: 1, y: 1 });
+    doc.new_cursor(Loc { x: 2, y: 0 });
+    assert_eq!(doc.has_cursor(Loc { x: 2, y: 0 }), Some(1));
+    // Cursors move along with edits
+    assert!(doc
+        .exe(Event::Insert(Loc { x: 0, y: 0 }, st!("ab")))
+        .is_ok());
+    assert!(doc.exe(Event::SplitDown(Loc { x: 0, y: 1 })).is_ok());
+    assert_eq!(doc.cursors(), vec![Loc { x: 1, y: 2 }, Loc { x: 4, y: 0 }]);
+    assert!(doc.exe(Event::SpliceUp(Loc { x: 0, y: 1 })).is_ok());
+    assert!(doc.exe(Event::Insert(Loc { x: 4, y: 0 }, st!("c"))).is_ok());
+    assert_eq!(doc.cursors(), vec![Loc { x: 1, y: 1 }, Loc { x: 5, y: 0 }]);
+    assert_eq!(doc.line(0), Some(st!("abonce")));
+    // Adding a cursor where there already is one removes it
+    doc.new_cursor(Loc { x: 5, y: 0 });
+    assert_eq!(doc.cursors(), vec![Loc { x: 1, y: 1 }]);
+    assert_eq!(doc.has_cursor(Loc { x: 5, y: 0 }), None);
+    doc.clear_cursors();
+    assert!(doc.cursors().is_empty());
+    assert!(doc.markers.items.is_empty());
+}
+
+#[test]
+fn document_set_text() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("old")));
+    doc.commit();
+    doc.move_to(&Loc { x: 2, y: 0 });
+    doc.set_text("a\n你好\n\ttab");
+    assert_eq!(doc.len_lines(), 3);
+    assert_eq!(doc.line(1), Some(st!("你好")));
+    assert_eq!(doc.line(2), Some(st!("\ttab")));
+    assert_eq!(doc.loc(), Loc { x: 0, y: 0 });
+    // Double width characters and tabs are accounted for
+    doc.move_to(&Loc { x: 1, y: 1 });
+    assert_eq!(doc.cursor.loc, Loc { x: 2, y: 1 });
+    // The old contents can't be undone back to
+    doc.undo().unwrap();
+    assert_eq!(doc.line(0), Some(st!("a")));
+    // The cursor and markers stay within the document
+    doc.move_to(&Loc { x: 0, y: 2 });
+    let past_end = doc.add_marker(Loc { x: 3, y: 2 }, Gravity::Left);
+    let past_line = doc.add_marker(Loc { x: 3, y: 1 }, Gravity::Left);
+    let start = doc.add_marker(Loc { x: 0, y: 0 }, Gravity::Left);
+    doc.set_text(
+        "only
+x",
+    );
+    assert_eq!(doc.len_lines(), 2);
+    assert_eq!(doc.loc(), Loc { x: 0, y: 1 });
+    assert_eq!(doc.marker_loc(past_end), Some(Loc { x: 1, y: 1 }));
+    assert_eq!(doc.marker_loc(past_line), Some(Loc { x: 1, y: 1 }));
+    assert_eq!(doc.marker_loc(start), Some(Loc { x: 0, y: 0 }));
+    // Text that already ends in a newline isn't given another
+    doc.set_text("one\ntwo\n");
+    assert_eq!(doc.len_lines(), 2);
+    assert_eq!(doc.line(1), Some(st!("two")));
+    doc.set_text("");
+    assert_eq!(doc.len_lines(), 1);
+}
+
 #[test]
 fn document_searching() {
     let mut doc = Document::open(Size::is(100, 1), "tests/data/unicode.txt").unwrap();
@@ -14,6 +14,11 @@ You can select between different models, including
 - claude - Anthropic's Claude
 ]]--
 
+capabilities {
+    process = true,
+    editor = true,
+}
+
 ai = {
     model = (ai or { model = "gemini" }).model, -- Gemini is free by default!
     key = (ai or { key = nil }).key, -- API key
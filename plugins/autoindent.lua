@@ -5,6 +5,10 @@ Helps you when programming by guessing where indentation should go
 and then automatically applying these guesses as you program
 ]]--
 
+capabilities {
+    editor = true,
+}
+
 autoindent = {}
 
 -- Determine if a line starts with a certain string
@@ -69,6 +73,13 @@ function autoindent:causes_dedent(y)
     return false
 end
 
+-- How many spaces make up an indent in the current document
+function autoindent:indent_size()
+    local settings = editor.document_settings
+    if settings == nil then return document.tab_width end
+    return settings.indent_size
+end
+
 -- Set an indent at a certain y index
 function autoindent:set_indent(y, new_indent)
     -- Handle awkward scenarios
@@ -91,8 +102,8 @@ function autoindent:set_indent(y, new_indent)
             new_line = string.rep("\t", indent_change) .. line
         else
             -- Insert Spaces
-            x = x + indent_change * document.tab_width
-            new_line = string.rep(" ", indent_change * document.tab_width) .. line
+            x = x + indent_change * autoindent:indent_size()
+            new_line = string.rep(" ", indent_change * autoindent:indent_size()) .. line
         end
     elseif indent_change < 0 then
         -- Remove indentation
@@ -102,8 +113,8 @@ function autoindent:set_indent(y, new_indent)
             new_line = line:gsub("\t", "", -indent_change)
         else
             -- Remove Spaces
-            x = x - -indent_change * document.tab_width
-            new_line = line:gsub(string.rep(" ", document.tab_width), "", -indent_change)
+            x = x - -indent_change * autoindent:indent_size()
+            new_line = line:gsub(string.rep(" ", autoindent:indent_size()), "", -indent_change)
         end
     else
         return
@@ -121,7 +132,7 @@ end
 function autoindent:get_indent(y)
     if y == nil then return nil end
     local line = editor:get_line_at(y)
-    return #(line:match("^\t+") or "") + #(line:match("^ +") or "") / document.tab_width
+    return #(line:match("^\t+") or "") + #(line:match("^ +") or "") / autoindent:indent_size()
 end
 
 -- Utilties for when moving lines around
@@ -206,7 +217,7 @@ function dedent_amount(y)
     if tabs then
         return 1
     else
-        return document.tab_width
+        return autoindent:indent_size()
     end
 end
 
@@ -4,6 +4,10 @@ Discord RPC v0.2
 For showing your use of the Ox editor to other users on Discord
 ]]--
 
+capabilities {
+    process = true,
+}
+
 -- Verify whether the dependencies are installed
 discord_rpc = {
     has_python = python_interop:installation() ~= nil,
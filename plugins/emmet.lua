@@ -4,6 +4,12 @@ Emmet v0.4
 Implementation of Emmet for Ox for rapid web development
 ]]--
 
+capabilities {
+    process = true,
+    editor = true,
+    fs = {plugin_path},
+}
+
 -- Verify whether the dependencies are installed
 emmet = {
     has_python = python_interop:installation() ~= nil,
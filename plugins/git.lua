@@ -10,6 +10,11 @@ A plug-in for git integration that provides features to:
  - Pull any changes upstream
 ]]--
 
+capabilities {
+    process = true,
+    editor = true,
+}
+
 git = {
     status = {},
     branch = "",
@@ -133,6 +138,21 @@ event_mapping["ctrl_s"] = function()
 end
 
 -- Export the git command
+command_completers["git"] = function(args)
+    if #args == 0 then
+        return {"commit", "push", "pull", "add", "reset", "stat", "diff", "checkout"}
+    elseif #args == 1 and args[1] == "checkout" then
+        local branches = {}
+        for branch in shell:output("git branch --format='%(refname:short)'"):gmatch("[^\n]+") do
+            table.insert(branches, branch)
+        end
+        return branches
+    elseif #args == 1 and args[1] ~= "commit" and args[1] ~= "push" and args[1] ~= "pull" then
+        return {"all"}
+    end
+    return {}
+end
+
 commands["git"] = function(args)
     -- Check if git is installed
     if not git:ready() then
@@ -140,7 +160,7 @@ commands["git"] = function(args)
     else
         local repo_path = git:repo_path()
         if args[1] == "commit" then
-            local message = editor:prompt("Message")
+            local message = editor:prompt("Message", "commit_message")
             editor:display_info("Committing with message: " .. message)
             if shell:run('git commit -S -m "' .. message .. '"') ~= 0 then
                 editor:display_error("Failed to commit")
@@ -4,6 +4,12 @@ Live HTML v0.2
 As you develop a website, you can view it in your browser without needing to refresh with every change
 ]]--
 
+capabilities {
+    process = true,
+    network = true,
+    fs = {plugin_path},
+}
+
 live_html = {
     has_python = python_interop:installation() ~= nil,
     has_flask_module = python_interop:has_module("flask"),
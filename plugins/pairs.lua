@@ -5,6 +5,10 @@ Automatically insert and delete brackets and quotes where appropriate
 Also helps when you want to pad out brackets and quotes with whitespace
 ]]--
 
+capabilities {
+    editor = true,
+}
+
 autopairs = {}
 
 -- The following pairs are in the form [start of pair][end of pair]
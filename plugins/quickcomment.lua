@@ -4,6 +4,10 @@ Quickcomment v0.3
 A plug-in to help you comment and uncomment lines quickly
 ]]--
 
+capabilities {
+    editor = true,
+}
+
 quickcomment = {}
 
 function quickcomment:comment(y)
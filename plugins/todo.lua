@@ -6,6 +6,10 @@ You can mark todos as done / not done by using the Ctrl + Enter key combination
 Todos are in the format "- [ ] Your Todo Name"
 ]]--
 
+capabilities {
+    editor = true,
+}
+
 -- Add language specific information for .todo files
 file_types["Todo"] = {
     icon = " ",
@@ -1,3 +1,7 @@
+capabilities {
+    network = true,
+}
+
 -- Get the contents of the latest Cargo.toml
 local cargo_latest = http.get("https://raw.githubusercontent.com/curlpipe/ox/refs/heads/master/Cargo.toml")
 -- Extract the version from the build file
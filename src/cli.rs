@@ -1,7 +1,9 @@
 /// Utilities for dealing with the command line interface of Ox
 use jargon_args::{Jargon, Key};
+use kaolinite::{Document, Loc};
 use std::io;
 use std::io::BufRead;
+use std::path::Path;
 
 /// Holds the version number of the crate
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -12,6 +14,12 @@ Ox: A lightweight and flexible text editor
 
 USAGE: ox [options] [files]
 
+FILES:
+  [path]                       : Open a file
+  [path]:[line]:[column]       : Open a file at a line (and optionally a column)
+  +[line] [path]               : Open the next file at a line
+  +/[pattern] [path]           : Open the next file at the first match of a regex
+
 OPTIONS:
   --help, -h                   : Show this help message
   --version, -v                : Show the version number
@@ -20,16 +28,37 @@ OPTIONS:
   --filetype [name], -f [name] : Set the file type of files opened
   --stdin                      : Reads file from the stdin
   --config-assist              : Activate the configuration assistant
+  --headless                   : Edit files without a terminal (use with --script or -e)
+  --script [path], -s [path]   : Run a lua script against the files, then save them
+  --eval [code], -e [code]     : Run some lua code against the files, then save them
+  --remote                     : Open the files in an editor that is already running
+                                 (with -e, the lua code runs in that editor instead)
+  --split                      : Open remote files in a split rather than a new tab
+  --wait                       : Wait for remote files to be closed before exiting
+  --session [name]             : Restore a named session, and save it again on exit
+                                 (given files, a fresh session is started with them)
+
+COMMANDS:
+  plugins [command]            : Install, update and remove plug-ins (see `ox plugins help`)
 
 EXAMPLES:
   ox
   ox test.txt
   ox test.txt test2.txt
+  ox src/main.rs:120:5
+  ox +120 src/main.rs +/TODO notes.txt
   ox /home/user/docs/test.txt
   ox -c config.lua test.txt
   ox -r -c ~/.config/.oxrc -f Lua my_file.lua
   tree | ox -r --stdin
-  ox --config-assist\
+  ox --headless --script edits.lua src/main.rs src/lib.rs
+  ox --headless -e 'editor:remove_line_at(1)' notes.txt
+  ox --remote --split src/main.rs:120
+  ox --remote -e 'return editor.document_name'
+  GIT_EDITOR='ox --remote --wait' git commit
+  ox --session notes
+  ox --config-assist
+  ox plugins install git\
 ";
 
 /// Read from the standard input
@@ -39,6 +68,105 @@ pub fn get_stdin() -> String {
     })
 }
 
+/// Where to move the cursor to in a file opened from the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Jump {
+    /// Go to a line and (optionally) a column, both counting from 1
+    Line(usize, Option<usize>),
+    /// Go to the first match of a regex
+    Search(String),
+}
+
+impl Jump {
+    /// Move the cursor in a document to this position (and put it in the middle of the view)
+    pub fn apply(&self, doc: &mut Document) {
+        match self {
+            Self::Line(line, column) => doc.move_to(&Loc {
+                x: column.unwrap_or(1).saturating_sub(1),
+                y: line.saturating_sub(1),
+            }),
+            Self::Search(pattern) => {
+                if let Some(mtch) = doc.next_match(pattern, 0) {
+                    doc.move_to(&mtch.loc);
+                }
+            }
+        }
+        doc.center_cursor();
+    }
+}
+
+/// A file to open from the command line
+pub struct FileArgument {
+    pub path: String,
+    pub jump: Option<Jump>,
+}
+
+/// Work out the files to open and where to go in them
+/// (`+line` and `+/pattern` arguments apply to the file after them)
+fn files_to_open(args: Vec<String>) -> Vec<FileArgument> {
+    let mut files = vec![];
+    let mut pending = None;
+    for arg in args.into_iter().filter(|o| o != "--") {
+        // A file that really exists is always opened as-is
+        if Path::new(&arg).exists() {
+            files.push(FileArgument {
+                path: arg,
+                jump: pending.take(),
+            });
+        } else if let Some(jump) = plus_jump(&arg) {
+            pending = Some(jump);
+        } else {
+            // (a position given with the file wins over one given before it)
+            let (path, jump) = split_position(&arg);
+            let before = pending.take();
+            files.push(FileArgument {
+                path,
+                jump: jump.or(before),
+            });
+        }
+    }
+    if pending.is_some() {
+        eprintln!("[WARNING] Ignoring a `+line` or `+/pattern` argument with no file after it");
+    }
+    files
+}
+
+/// Read a `+line` or `+/pattern` argument
+fn plus_jump(arg: &str) -> Option<Jump> {
+    let rest = arg.strip_prefix('+')?;
+    if let Some(pattern) = rest.strip_prefix('/') {
+        (!pattern.is_empty()).then(|| Jump::Search(pattern.to_string()))
+    } else {
+        rest.parse().ok().map(|line| Jump::Line(line, None))
+    }
+}
+
+/// Split a `path:line:column` or `path:line` argument (as printed by compilers and grep)
+fn split_position(arg: &str) -> (String, Option<Jump>) {
+    let number = |text: &str| {
+        if text.bytes().all(|b| b.is_ascii_digit()) {
+            text.parse::<usize>().ok()
+        } else {
+            None
+        }
+    };
+    // (grep leaves a colon on the end)
+    let trimmed = arg.strip_suffix(':').unwrap_or(arg);
+    if let Some((rest, last)) = trimmed.rsplit_once(':') {
+        if let Some(last) = number(last) {
+            if let Some((path, line)) = rest.rsplit_once(':') {
+                if let Some(line) = number(line).filter(|_| !path.is_empty()) {
+                    return (path.to_string(), Some(Jump::Line(line, Some(last))));
+                }
+            }
+            if !rest.is_empty() {
+                return (rest.to_string(), Some(Jump::Line(last, None)));
+            }
+        }
+    }
+    (arg.to_string(), None)
+}
+
 /// Flags for command line interface
 #[allow(clippy::struct_excessive_bools)]
 pub struct CommandLineInterfaceFlags {
@@ -47,6 +175,10 @@ pub struct CommandLineInterfaceFlags {
     pub read_only: bool,
     pub stdin: bool,
     pub config_assist: bool,
+    pub headless: bool,
+    pub remote: bool,
+    pub split: bool,
+    pub wait: bool,
 }
 
 /// Struct to help with starting ox
@@ -54,7 +186,15 @@ pub struct CommandLineInterface {
     pub flags: CommandLineInterfaceFlags,
     pub file_type: Option<String>,
     pub config_path: String,
-    pub to_open: Vec<String>,
+    pub to_open: Vec<FileArgument>,
+    /// A lua script to run against the files (when running headless)
+    pub script: Option<String>,
+    /// Lua code to run against the files (when running headless)
+    pub eval: Option<String>,
+    /// The name of the session to restore and save
+    pub session: Option<String>,
+    /// Arguments for the plug-in manager (when running `ox plugins ...`)
+    pub plugins: Option<Vec<String>>,
 }
 
 impl CommandLineInterface {
@@ -66,21 +206,41 @@ impl CommandLineInterface {
         // Define keys
         let filetype: Key = ["-f", "--filetype"].into();
         let config: Key = ["-c", "--config"].into();
+        let script: Key = ["-s", "--script"].into();
+        let eval: Key = ["-e", "--eval"].into();
+        let session: Key = "--session".into();
 
-        Self {
+        let mut cli = Self {
             flags: CommandLineInterfaceFlags {
                 help: j.contains(["-h", "--help"]),
                 version: j.contains(["-v", "--version"]),
                 read_only: j.contains(["-r", "--readonly"]),
                 stdin: j.contains("--stdin"),
                 config_assist: j.contains("--config-assist"),
+                headless: j.contains("--headless"),
+                remote: j.contains("--remote"),
+                split: j.contains("--split"),
+                wait: j.contains("--wait"),
             },
             file_type: j.option_arg::<String, Key>(filetype.clone()),
             config_path: j
                 .option_arg::<String, Key>(config.clone())
                 .unwrap_or_else(|| "~/.oxrc".to_string()),
-            to_open: j.finish().into_iter().filter(|o| o != "--").collect(),
+            script: j.option_arg::<String, Key>(script.clone()),
+            eval: j.option_arg::<String, Key>(eval.clone()),
+            session: j.option_arg::<String, Key>(session.clone()),
+            to_open: vec![],
+            plugins: None,
+        };
+
+        // A subcommand comes before any files (`ox ./plugins` opens a file called plugins)
+        let mut rest = j.finish();
+        if rest.first().map(String::as_str) == Some("plugins") {
+            cli.plugins = Some(rest.split_off(1));
+        } else {
+            cli.to_open = files_to_open(rest);
         }
+        cli
     }
 
     /// Handle options that won't need to start the editor
@@ -94,3 +254,64 @@ impl CommandLineInterface {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_are_split_from_paths() {
+        let cases = [
+            ("src/main.rs", "src/main.rs", None),
+            ("src/main.rs:12", "src/main.rs", Some(Jump::Line(12, None))),
+            (
+                "src/main.rs:12:4",
+                "src/main.rs",
+                Some(Jump::Line(12, Some(4))),
+            ),
+            // (grep leaves a colon on the end)
+            ("file:12:", "file", Some(Jump::Line(12, None))),
+            // (drive letters aren't mistaken for positions)
+            ("C:\\x.rs:3", "C:\\x.rs", Some(Jump::Line(3, None))),
+            ("C:\\x.rs", "C:\\x.rs", None),
+            (":12", ":12", None),
+            ("notes:abc", "notes:abc", None),
+        ];
+        for (arg, path, jump) in cases {
+            assert_eq!(split_position(arg), (path.to_string(), jump), "{arg}");
+        }
+    }
+
+    #[test]
+    fn jumps_apply_to_the_file_after_them() {
+        let dir = std::env::temp_dir().join(format!("ox-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // (a file that really exists is opened as-is, even if it looks like a position)
+        let real = dir.join("a:1").to_string_lossy().to_string();
+        std::fs::write(&real, "").unwrap();
+        let args = [
+            "+5",
+            "a.rs:3",
+            "b.rs",
+            "+/fn main",
+            &real,
+            "--",
+            "+7",
+            "c.rs",
+            "+9",
+        ];
+        let files = files_to_open(args.iter().map(ToString::to_string).collect());
+        let _ = std::fs::remove_dir_all(&dir);
+        let opened: Vec<(String, Option<Jump>)> =
+            files.into_iter().map(|f| (f.path, f.jump)).collect();
+        assert_eq!(
+            opened,
+            vec![
+                ("a.rs".to_string(), Some(Jump::Line(3, None))),
+                ("b.rs".to_string(), None),
+                (real, Some(Jump::Search("fn main".to_string()))),
+                ("c.rs".to_string(), Some(Jump::Line(7, None))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,496 @@
+/// The command line, for running the commands defined in the configuration file
+/// (commands can be given a range of lines, like `10,20 sort` or `% sort`)
+use crate::editor::{complete_path, Completions, Editor, LineEditor};
+use crate::error::{OxError, Result};
+use crate::ui::Feedback;
+use crate::{ged, handle_lua_error};
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::Loc;
+use mlua::{AnyUserData, Function, Lua, Table, Value};
+
+/// The kind of prompt history that commands are remembered under
+const HISTORY: &str = "command";
+
+/// Lines that a command should work on (1-based and inclusive)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineRange {
+    first: usize,
+    last: usize,
+}
+
+/// A command that has been typed in
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Command {
+    range: Option<LineRange>,
+    /// The name of the command (empty if only a range was given)
+    name: String,
+    arguments: Vec<String>,
+}
+
+/// A word typed into the command line, along with where it is (in characters)
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Word {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+/// Let the user type in a command, then run it
+pub fn open(editor: &AnyUserData, lua: &Lua) {
+    match read_command(editor, lua) {
+        Ok(input) => {
+            ged!(mut &editor).history.add_prompt_entry(HISTORY, &input);
+            run_command(editor, lua, &input);
+        }
+        Err(OxError::Cancelled) => (),
+        Err(err) => ged!(mut &editor).feedback = Feedback::Error(err.to_string()),
+    }
+}
+
+/// Run a command (as it would be typed into the command line)
+pub fn run_command(editor: &AnyUserData, lua: &Lua, input: &str) {
+    let (current, length) = ged!(&editor)
+        .try_doc()
+        .map_or((0, 0), |doc| (doc.char_loc().y + 1, doc.len_lines()));
+    let command = match parse(input, current, length) {
+        Ok(command) => command,
+        Err(msg) => {
+            ged!(mut &editor).feedback = Feedback::Error(msg);
+            return;
+        }
+    };
+    if command.name.is_empty() {
+        // A range on its own moves to the last line in it
+        if let Some(range) = command.range {
+            let mut editor = ged!(mut &editor);
+            if let Some(doc) = editor.try_doc_mut() {
+                doc.move_to(&Loc::at(0, range.last - 1));
+            }
+            editor.update_highlighter();
+        }
+        return;
+    }
+    let function = lua
+        .globals()
+        .get::<Table>("commands")
+        .and_then(|commands| commands.get::<Option<Function>>(command.name.as_str()));
+    let Ok(Some(function)) = function else {
+        let msg = format!("Command '{}' not found", command.name);
+        ged!(mut &editor).feedback = Feedback::Error(msg);
+        return;
+    };
+    // Commands are given their arguments, and the range (if there is one)
+    let range = command
+        .range
+        .map(|r| lua.create_table_from([("first", r.first), ("last", r.last)]))
+        .transpose();
+    let result = range.and_then(|range| function.call::<()>((command.arguments, range)));
+    handle_lua_error(&command.name, result, &mut ged!(mut &editor).feedback);
+}
+
+/// Let the user type in a command (with tab completion and history)
+fn read_command(editor: &AnyUserData, lua: &Lua) -> Result<String> {
+    let history = ged!(&editor).history.prompt_history(HISTORY);
+    let mut line = LineEditor::with_history(history);
+    let mut completions: Option<Completions> = None;
+    loop {
+        let hint = completions
+            .as_ref()
+            .map(Completions::hint)
+            .unwrap_or_default();
+        ged!(mut &editor).render_line_editor("Command", &line, &hint)?;
+        let Some((modifiers, code)) = ged!(mut &editor).prompt_key(lua, &mut line) else {
+            completions = None;
+            continue;
+        };
+        if !matches!(code, KCode::Tab | KCode::BackTab) {
+            completions = None;
+        }
+        match (modifiers, code) {
+            (KMod::NONE, KCode::Enter) => return Ok(line.text),
+            (KMod::NONE, KCode::Esc) => return Err(OxError::Cancelled),
+            // Complete the word before the cursor, then cycle through the candidates
+            (KMod::NONE, KCode::Tab) | (KMod::SHIFT, KCode::BackTab) => {
+                if let Some(completions) = &mut completions {
+                    completions.cycle(&mut line, code == KCode::Tab);
+                } else {
+                    let (start, current, candidates) =
+                        completions_for(editor, lua, line.before_cursor());
+                    let found = Completions::new(start, candidates, quote);
+                    completions = line.complete(found, &current, " ");
+                }
+            }
+            _ => {
+                line.handle_key(modifiers, code);
+            }
+        }
+    }
+}
+
+/// Find the candidates for the word at the end of some text
+/// (returns where the word starts, the word itself and the candidates for it)
+fn completions_for(editor: &AnyUserData, lua: &Lua, input: &str) -> (usize, String, Vec<String>) {
+    // (a range at the start doesn't change what can be completed)
+    let command = input.trim_start_matches(|c: char| c.is_ascii_digit() || " .,$%+-".contains(c));
+    let skipped = input.len() - command.len();
+    let (mut words, _) = split_words(command);
+    let len = command.chars().count();
+    if words.last().is_none_or(|word| word.end < len) {
+        words.push(Word {
+            text: String::new(),
+            start: len,
+            end: len,
+        });
+    }
+    let Some((word, before)) = words.split_last() else {
+        return (0, String::new(), vec![]);
+    };
+    let candidates = if let Some((name, arguments)) = before.split_first() {
+        let arguments = arguments.iter().map(|w| w.text.clone()).collect();
+        argument_completions(editor, lua, &name.text, arguments, &word.text)
+    } else {
+        command_names(lua)
+    };
+    let candidates = candidates
+        .into_iter()
+        .filter(|c| c.starts_with(&word.text))
+        .collect();
+    (skipped + word.start, word.text.clone(), candidates)
+}
+
+/// Find the names of all the commands that can be run
+fn command_names(lua: &Lua) -> Vec<String> {
+    let Ok(commands) = lua.globals().get::<Table>("commands") else {
+        return vec![];
+    };
+    let mut names: Vec<String> = commands
+        .pairs::<String, Value>()
+        .filter_map(|pair| pair.ok().map(|(name, _)| name))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Find the candidates for an argument of a command
+/// (commands can provide their own completer, otherwise paths are suggested)
+fn argument_completions(
+    editor: &AnyUserData,
+    lua: &Lua,
+    name: &str,
+    arguments: Vec<String>,
+    current: &str,
+) -> Vec<String> {
+    let completer = lua
+        .globals()
+        .get::<Table>("command_completers")
+        .and_then(|completers| completers.get::<Option<Function>>(name));
+    if let Ok(Some(completer)) = completer {
+        match completer.call::<Option<Vec<String>>>((arguments, current)) {
+            Ok(Some(mut candidates)) => {
+                candidates.sort();
+                candidates.dedup();
+                return candidates;
+            }
+            // (completers can return nil to fall back to suggesting paths)
+            Ok(None) => (),
+            Err(err) => {
+                ged!(mut &editor).feedback = Feedback::Error(err.to_string());
+                return vec![];
+            }
+        }
+    }
+    complete_path(current)
+}
+
+/// Quote a word if it needs it (so that it is read back in as the same word)
+fn quote(word: &str) -> String {
+    let special = |c: char| c.is_whitespace() || matches!(c, '\'' | '"' | '\\');
+    if !word.is_empty() && !word.contains(special) {
+        word.to_string()
+    } else if !word.contains('\'') {
+        format!("'{word}'")
+    } else {
+        format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Read a command (and the range before it)
+fn parse(input: &str, current: usize, length: usize) -> std::result::Result<Command, String> {
+    let (range, rest) = parse_range(input, current, length)?;
+    let (words, quote) = split_words(rest);
+    if let Some(quote) = quote {
+        return Err(format!("Missing closing {quote} in command"));
+    }
+    let mut words = words.into_iter().map(|word| word.text);
+    Ok(Command {
+        range,
+        name: words.next().unwrap_or_default(),
+        arguments: words.collect(),
+    })
+}
+
+/// Read the range at the start of a command, like `%`, `10`, `10,20` or `.,$-1`
+/// (returns the range, if there is one, and the rest of the command)
+#[allow(clippy::cast_possible_wrap)]
+fn parse_range(
+    input: &str,
+    current: usize,
+    length: usize,
+) -> std::result::Result<(Option<LineRange>, &str), String> {
+    let input = input.trim_start();
+    let (first, last, rest) = if let Some(rest) = input.strip_prefix('%') {
+        (Some(1), Some(length as i64), rest)
+    } else {
+        let (first, rest) = parse_address(input, current, length);
+        match rest.strip_prefix(',') {
+            // (a missing line on either side of the comma is the current line)
+            Some(rest) => {
+                let (last, rest) = parse_address(rest, current, length);
+                let current = Some(current as i64);
+                (first.or(current), last.or(current), rest)
+            }
+            None => (first, first, rest),
+        }
+    };
+    let (Some(first), Some(last)) = (first, last) else {
+        return Ok((None, rest));
+    };
+    for line in [first, last] {
+        if line < 1 || line > length as i64 {
+            return Err(format!("Line {line} is outside of the document"));
+        }
+    }
+    if first > last {
+        return Err("The range is backwards".to_string());
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let range = LineRange {
+        first: first as usize,
+        last: last as usize,
+    };
+    Ok((Some(range), rest))
+}
+
+/// Read a line number, like `10`, `.` (the current line), `$` (the last line) or `.+2`
+/// (an offset on its own counts from the current line)
+#[allow(clippy::cast_possible_wrap)]
+fn parse_address(input: &str, current: usize, length: usize) -> (Option<i64>, &str) {
+    // (numbers too big to fit are left too big, so they are caught as being out of range)
+    fn number(text: &str) -> (Option<i64>, &str) {
+        let len = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let (digits, rest) = text.split_at(len);
+        (digits.parse().ok().or((len > 0).then_some(i64::MAX)), rest)
+    }
+    let (mut line, mut rest) = match input.chars().next() {
+        Some('.') => (Some(current as i64), &input[1..]),
+        Some('$') => (Some(length as i64), &input[1..]),
+        _ => number(input),
+    };
+    while let Some(sign @ ('+' | '-')) = rest.chars().next() {
+        let (amount, after) = number(&rest[1..]);
+        let amount = amount.unwrap_or(1);
+        let base = line.unwrap_or(current as i64);
+        line = Some(if sign == '+' {
+            base.saturating_add(amount)
+        } else {
+            base.saturating_sub(amount)
+        });
+        rest = after;
+    }
+    (line, rest)
+}
+
+/// Split a command into words like a shell would
+/// (quotes and backslashes let words contain spaces, and the quote left open is returned)
+fn split_words(input: &str) -> (Vec<Word>, Option<char>) {
+    let len = input.chars().count();
+    let mut words = vec![];
+    let mut word: Option<Word> = None;
+    let mut quote = None;
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if quote.is_none() && c.is_whitespace() {
+            words.extend(word.take());
+            continue;
+        }
+        let current = word.get_or_insert_with(|| Word {
+            text: String::new(),
+            start: idx,
+            end: idx,
+        });
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), _) if open == c => quote = None,
+            // Backslashes escape anything outside of quotes, but only quotes and
+            // backslashes in double quotes (and nothing in single quotes)
+            (None, '\\') => {
+                if let Some((_, next)) = chars.next() {
+                    current.text.push(next);
+                }
+            }
+            (Some('"'), '\\') => match chars.peek() {
+                Some((_, next @ ('"' | '\\'))) => {
+                    current.text.push(*next);
+                    chars.next();
+                }
+                _ => current.text.push(c),
+            },
+            _ => current.text.push(c),
+        }
+        current.end = chars.peek().map_or(len, |(idx, _)| *idx);
+    }
+    words.extend(word);
+    (words, quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Just the text of the words in a command
+    fn words(input: &str) -> Vec<String> {
+        split_words(input).0.into_iter().map(|w| w.text).collect()
+    }
+
+    /// Read a range on line 5 of a 10 line document
+    fn range(input: &str) -> std::result::Result<Option<(usize, usize)>, String> {
+        parse_range(input, 5, 10).map(|(range, _)| range.map(|r| (r.first, r.last)))
+    }
+
+    #[test]
+    fn ranges_are_read() {
+        let cases = [
+            ("% sort", Some((1, 10))),
+            ("10", Some((10, 10))),
+            ("3,7 sort", Some((3, 7))),
+            (".", Some((5, 5))),
+            ("$", Some((10, 10))),
+            (".,$", Some((5, 10))),
+            (".,$-1", Some((5, 9))),
+            ("$-3,$", Some((7, 10))),
+            (".+2", Some((7, 7))),
+            ("-2,+", Some((3, 6))),
+            ("+2-1", Some((6, 6))),
+            (",$", Some((5, 10))),
+            ("3,", Some((3, 5))),
+            (",", Some((5, 5))),
+            ("  2,4", Some((2, 4))),
+            ("sort", None),
+            ("", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(range(input), Ok(expected), "reading '{input}'");
+        }
+    }
+
+    #[test]
+    fn the_rest_of_the_command_is_kept() {
+        assert_eq!(parse_range("% sort -r", 5, 10).unwrap().1, " sort -r");
+        assert_eq!(parse_range(".,$-1s", 5, 10).unwrap().1, "s");
+        assert_eq!(parse_range("sort", 5, 10).unwrap().1, "sort");
+    }
+
+    #[test]
+    fn backwards_ranges_are_rejected() {
+        for input in ["7,3", "$,1", ".,.-1", "+,-"] {
+            assert_eq!(
+                range(input),
+                Err("The range is backwards".to_string()),
+                "reading '{input}'"
+            );
+        }
+    }
+
+    #[test]
+    fn out_of_range_lines_are_rejected() {
+        let cases = [("0", 0), ("11", 11), ("$+1", 11), ("1-5", -4), ("1,11", 11)];
+        for (input, line) in cases {
+            assert_eq!(
+                range(input),
+                Err(format!("Line {line} is outside of the document")),
+                "reading '{input}'"
+            );
+        }
+        // (numbers too big for an i64 are still out of range rather than ignored)
+        assert!(range("99999999999999999999").is_err());
+        assert!(range("1,.+99999999999999999999").is_err());
+        // (the whole document can't be used if there is nothing in it)
+        assert!(parse_range("%", 1, 0).is_err());
+    }
+
+    #[test]
+    fn words_are_split_like_a_shell() {
+        let cases: [(&str, &[&str]); 12] = [
+            ("a b  c", &["a", "b", "c"]),
+            ("  padded  ", &["padded"]),
+            ("'a b' c", &["a b", "c"]),
+            ("\"a b\" c", &["a b", "c"]),
+            ("ab\"c d\"e", &["abc de"]),
+            ("\"it's\" 'say \"hi\"'", &["it's", "say \"hi\""]),
+            ("'' \"\"", &["", ""]),
+            ("a\\ b", &["a b"]),
+            ("\\'a\\\"", &["'a\""]),
+            ("\"a\\\"b\\\\c\\d\"", &["a\"b\\c\\d"]),
+            ("'a\\b' 'c\\'", &["a\\b", "c\\"]),
+            ("a\\", &["a"]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(words(input), expected, "splitting {input}");
+        }
+    }
+
+    #[test]
+    fn unclosed_quotes_are_reported() {
+        assert_eq!(split_words("a 'b c").1, Some('\''));
+        assert_eq!(split_words("a \"b 'c'").1, Some('"'));
+        assert_eq!(split_words("a \"b\\\"").1, Some('"'));
+        assert_eq!(split_words("a 'b' \"c\"").1, None);
+        assert!(parse("sort 'a", 1, 1).is_err());
+    }
+
+    #[test]
+    fn words_know_where_they_are() {
+        let (words, _) = split_words("ab  'c d' é\\ f");
+        let spans = words.iter().map(|w| (w.start, w.end)).collect::<Vec<_>>();
+        assert_eq!(spans, [(0, 2), (4, 9), (10, 14)]);
+    }
+
+    #[test]
+    fn quoted_words_are_read_back_the_same() {
+        let cases = [
+            "plain",
+            "",
+            "a b",
+            "it's",
+            "say \"hi\"",
+            "back\\slash",
+            "both ' and \"",
+            "\\\"'",
+            "tab\there",
+            "trailing\\",
+            "ends with '",
+        ];
+        for word in cases {
+            assert_eq!(
+                split_words(&quote(word)),
+                (
+                    vec![Word {
+                        text: word.to_string(),
+                        start: 0,
+                        end: quote(word).chars().count()
+                    }],
+                    None
+                ),
+                "quoting {word:?} as {}",
+                quote(word)
+            );
+        }
+        // (and a whole command of them)
+        let command = cases.map(quote).join(" ");
+        assert_eq!(words(&command), cases);
+        // (words are only quoted if they need it)
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), "\"it's\"");
+    }
+}
@@ -0,0 +1,336 @@
+//! Handles that let plug-ins work with any open buffer (not just the focused one)
+
+use crate::config::LuaLoc;
+use crate::editor::{Editor, FileContainer, Hook};
+use crate::events::fire_before;
+use crate::{config, ged, Feedback};
+use kaolinite::searching::Searcher;
+use kaolinite::Loc;
+use mlua::prelude::*;
+
+/// A handle to a buffer, which stays valid as splits are rearranged.
+///
+/// Handles are obtained from `editor:buffers()`, `editor:current_buffer()`,
+/// `editor:get_buffer(id)` and `editor:create_buffer({name, read_only, filetype})`:
+/// ```lua
+/// for _, buffer in ipairs(editor:buffers()) do
+///     if buffer.modified then buffer:save() end
+/// end
+/// local status = editor:create_buffer({name = "Git Status", read_only = true})
+/// status:set_lines({"M src/main.rs", "A src/new.rs"})
+/// status:map("enter", function(buffer) editor:open(buffer:get_line(buffer.cursor.y)) end)
+/// ```
+/// Scratch buffers (made with `create_buffer`) are never saved to the disk,
+/// so closing them doesn't prompt the user.
+/// Their key bindings run before the editor sees the key press, and can return false to let
+/// the editor handle the key press as usual.
+/// `buffer:set_key_context(name)` makes bindings from that `keymap` context active while
+/// the buffer is focused
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub id: usize,
+}
+
+impl Buffer {
+    /// Look at the buffer (fails if the buffer has been closed)
+    fn read<T>(self, lua: &Lua, action: impl FnOnce(&FileContainer) -> T) -> LuaResult<T> {
+        let editor = lua.globals().get::<LuaAnyUserData>("editor")?;
+        let mut editor = editor.borrow_mut::<Editor>()?;
+        let file = editor.buffer_mut(self.id).ok_or_else(closed)?;
+        Ok(action(file))
+    }
+
+    /// Run some code on the buffer as though it were focused (fails if the buffer has been closed)
+    fn with<T>(self, lua: &Lua, action: impl FnOnce(&mut Editor) -> T) -> LuaResult<T> {
+        let editor = lua.globals().get::<LuaAnyUserData>("editor")?;
+        let mut editor = editor.borrow_mut::<Editor>()?;
+        editor.with_buffer(self.id, action).ok_or_else(closed)
+    }
+}
+
+/// The error given when a plug-in tries to use a buffer that no longer exists
+fn closed() -> LuaError {
+    LuaError::external("this buffer has been closed")
+}
+
+/// Get the focused file container (for use within `Buffer::with`)
+fn focused(editor: &mut Editor) -> &mut FileContainer {
+    editor.files.get_mut(editor.ptr.clone()).unwrap()
+}
+
+/// Display any errors that occur when editing a buffer
+fn report(editor: &mut Editor, result: crate::error::Result<()>) {
+    if let Err(err) = result {
+        editor.feedback = Feedback::Error(err.to_string());
+    }
+}
+
+impl LuaUserData for Buffer {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("id", |_, buffer| Ok(buffer.id));
+        fields.add_field_method_get("open", |lua, buffer| Ok(buffer.read(lua, |_| ()).is_ok()));
+        fields.add_field_method_get("name", |lua, buffer| {
+            buffer.read(lua, FileContainer::display_path)
+        });
+        fields.add_field_method_get("file_name", |lua, buffer| {
+            buffer.read(lua, |file| file.doc.file_name.clone())
+        });
+        fields.add_field_method_get("file_type", |lua, buffer| {
+            buffer.read(lua, |file| file.file_type.as_ref().map(|t| t.name.clone()))
+        });
+        fields.add_field_method_get("settings", |lua, buffer| {
+            buffer.read(lua, |file| file.settings.clone())
+        });
+        fields.add_field_method_get("modified", |lua, buffer| {
+            buffer.read(lua, FileContainer::modified)
+        });
+        fields.add_field_method_get("read_only", |lua, buffer| {
+            buffer.read(lua, |file| file.doc.info.read_only)
+        });
+        fields.add_field_method_get("scratch", |lua, buffer| {
+            buffer.read(lua, |file| file.scratch.is_some())
+        });
+        fields.add_field_method_get("line_count", |lua, buffer| {
+            buffer.read(lua, |file| file.doc.len_lines())
+        });
+        fields.add_field_method_get("cursor", |lua, buffer| {
+            buffer.read(lua, |file| {
+                let loc = file.doc.char_loc();
+                LuaLoc {
+                    x: loc.x,
+                    y: loc.y + 1,
+                }
+            })
+        });
+        fields.add_field_method_get("cursors", |lua, buffer| {
+            buffer.read(lua, |file| {
+                file.doc
+                    .cursors()
+                    .iter()
+                    .map(|loc| LuaLoc {
+                        x: loc.x,
+                        y: loc.y + 1,
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        // Reading
+        methods.add_method("get_line", |lua, buffer, y: usize| {
+            buffer.with(lua, |editor| {
+                let doc = &mut focused(editor).doc;
+                doc.load_to(y);
+                doc.line(y.saturating_sub(1))
+            })
+        });
+        methods.add_method("get_lines", |lua, buffer, ()| {
+            buffer.with(lua, |editor| {
+                let doc = &mut focused(editor).doc;
+                doc.load_to(doc.len_lines());
+                doc.lines.clone()
+            })
+        });
+        methods.add_method("find", |lua, buffer, pattern: String| {
+            buffer.with(lua, |editor| {
+                let doc = &mut focused(editor).doc;
+                doc.load_to(doc.len_lines());
+                let mut searcher = Searcher::new(&pattern);
+                let mut result = vec![];
+                for (y, line) in doc.lines.iter().enumerate() {
+                    for found in searcher.lfinds(line) {
+                        let table = lua.create_table()?;
+                        table.set("x", found.loc.x)?;
+                        table.set("y", y + 1)?;
+                        table.set("text", found.text)?;
+                        result.push(table);
+                    }
+                }
+                Ok::<_, LuaError>(result)
+            })?
+        });
+        // Editing
+        methods.add_method(
+            "insert_at",
+            |lua, buffer, (text, x, y): (String, usize, usize)| {
+                buffer.with(lua, |editor| {
+                    let result = editor.insert_at(&text, Loc::at(x, y.saturating_sub(1)));
+                    report(editor, result);
+                })
+            },
+        );
+        methods.add_method("remove_at", |lua, buffer, (x, y): (usize, usize)| {
+            buffer.with(lua, |editor| {
+                let result = editor.remove_at(Loc::at(x, y.saturating_sub(1)));
+                report(editor, result);
+            })
+        });
+        methods.add_method(
+            "insert_line_at",
+            |lua, buffer, (text, y): (String, usize)| {
+                buffer.with(lua, |editor| {
+                    let result = editor.insert_line_at(&text, y.saturating_sub(1));
+                    report(editor, result);
+                })
+            },
+        );
+        methods.add_method("remove_line_at", |lua, buffer, y: usize| {
+            buffer.with(lua, |editor| {
+                let result = editor.remove_line_at(y.saturating_sub(1));
+                report(editor, result);
+            })
+        });
+        methods.add_method(
+            "replace_all",
+            |lua, buffer, (target, into): (String, String)| {
+                buffer.with(lua, |editor| {
+                    let doc = &mut focused(editor).doc;
+                    let location = doc.char_loc();
+                    doc.commit();
+                    doc.replace_all(&target, &into);
+                    doc.move_to(&location);
+                    let file = focused(editor);
+                    file.highlighter.run(&file.doc.lines);
+                })
+            },
+        );
+        methods.add_method("set_lines", |lua, buffer, lines: Vec<String>| {
+            buffer.with(lua, |editor| {
+                let file = focused(editor);
+                // Other buffers should be edited so that the changes can be undone
+                if file.scratch.is_none() {
+                    return Err(LuaError::external("only scratch buffers can be replaced"));
+                }
+                file.doc.set_text(&lines.join("\n"));
+                file.highlighter.run(&file.doc.lines);
+                Ok(())
+            })?
+        });
+        methods.add_method("undo", |lua, buffer, ()| {
+            buffer.with(lua, |editor| {
+                let result = editor.undo();
+                report(editor, result);
+                editor.update_highlighter();
+            })
+        });
+        methods.add_method("redo", |lua, buffer, ()| {
+            buffer.with(lua, |editor| {
+                let result = editor.redo();
+                report(editor, result);
+                editor.update_highlighter();
+            })
+        });
+        methods.add_method("commit", |lua, buffer, ()| {
+            buffer.with(lua, |editor| focused(editor).doc.commit())
+        });
+        methods.add_function("save", |lua, buffer: LuaUserDataRef<Self>| {
+            let file_name = buffer.read(lua, |file| file.doc.file_name.clone())?;
+            if fire_before(lua, "before_save", &file_name) {
+                buffer.with(lua, |editor| {
+                    let result = editor.save();
+                    report(editor, result);
+                })?;
+            }
+            Ok(())
+        });
+        // Cursors and appearance
+        methods.add_method("move_to", |lua, buffer, (x, y): (usize, usize)| {
+            buffer.with(lua, |editor| {
+                focused(editor)
+                    .doc
+                    .move_to(&Loc::at(x, y.saturating_sub(1)));
+                editor.update_highlighter();
+            })
+        });
+        methods.add_method("set_read_only", |lua, buffer, status: bool| {
+            buffer.with(lua, |editor| focused(editor).doc.info.read_only = status)
+        });
+        methods.add_method("set_file_type", |lua, buffer, name: String| {
+            buffer.with(lua, |editor| {
+                let file_type = config!(editor.config, document).file_types.get_name(&name);
+                if let Some(file_type) = file_type {
+                    // The file type's settings apply from now on
+                    focused(editor).file_type = Some(file_type.clone());
+                    editor.refresh_settings();
+                    let tab_width = focused(editor).settings.tab_width;
+                    let mut highlighter = file_type.get_highlighter(&editor.config, tab_width);
+                    let file = focused(editor);
+                    highlighter.run(&file.doc.lines);
+                    file.highlighter = highlighter;
+                    editor.hook(Hook::FileType(file_type.name));
+                } else {
+                    editor.feedback = Feedback::Error(format!("Invalid file type: {name}"));
+                }
+            })
+        });
+        methods.add_method(
+            "add_decoration",
+            |lua, buffer, (namespace, info): (String, LuaTable)| {
+                buffer.with(lua, |editor| {
+                    focused(editor).add_decoration(lua, &namespace, &info)
+                })?
+            },
+        );
+        methods.add_method(
+            "clear_decorations",
+            |lua, buffer, namespace: Option<String>| {
+                buffer.with(lua, |editor| {
+                    let file = focused(editor);
+                    file.decorations.clear(&mut file.doc, namespace.as_deref());
+                })
+            },
+        );
+        // Key bindings (for scratch buffers)
+        methods.add_method("map", |lua, buffer, (key, func): (String, LuaFunction)| {
+            buffer.with(lua, |editor| {
+                let task_manager = editor.config.task_manager.clone();
+                let Some(scratch) = &mut focused(editor).scratch else {
+                    return Err(LuaError::external("only scratch buffers have key bindings"));
+                };
+                let mut tm = task_manager.lock().unwrap();
+                let id = tm.register_callback(lua.create_registry_value(func)?);
+                if let Some(old) = scratch.keymaps.insert(key, id) {
+                    tm.remove_callback(lua, old);
+                }
+                Ok(())
+            })?
+        });
+        methods.add_method("unmap", |lua, buffer, key: String| {
+            buffer.with(lua, |editor| {
+                let scratch = focused(editor).scratch.as_mut();
+                if let Some(old) = scratch.and_then(|s| s.keymaps.remove(&key)) {
+                    let mut tm = editor.config.task_manager.lock().unwrap();
+                    tm.remove_callback(lua, old);
+                }
+            })
+        });
+        methods.add_method("set_key_context", |lua, buffer, context: Option<String>| {
+            buffer.with(lua, |editor| {
+                let Some(scratch) = &mut focused(editor).scratch else {
+                    return Err(LuaError::external("only scratch buffers have key bindings"));
+                };
+                scratch.context = context;
+                Ok(())
+            })?
+        });
+        // Management
+        methods.add_method("focus", |lua, buffer, ()| {
+            let editor = lua.globals().get::<LuaAnyUserData>("editor")?;
+            let focused = ged!(mut &editor).focus_buffer(buffer.id);
+            Ok(focused)
+        });
+        methods.add_method("close", |lua, buffer, ()| {
+            let editor = lua.globals().get::<LuaAnyUserData>("editor")?;
+            let mut editor = ged!(mut &editor);
+            match editor.close_buffer(buffer.id) {
+                Ok(closed) => Ok(closed),
+                Err(err) => {
+                    editor.feedback = Feedback::Error(err.to_string());
+                    Ok(false)
+                }
+            }
+        });
+    }
+}
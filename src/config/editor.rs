@@ -2,11 +2,15 @@
 use crate::cli::VERSION;
 #[cfg(not(target_os = "windows"))]
 use crate::config::runner::RunCommand;
-use crate::editor::{Editor, FileContainer, FileLayout};
+use crate::config::Buffer;
+use crate::editor::{lines_from_lua, Editor, FileContainer, FileLayout, FloatingWindow, Hook};
+use crate::events::fire_before;
 #[cfg(not(target_os = "windows"))]
 use crate::pty::Pty;
 use crate::ui::Feedback;
-use crate::{config, fatal_error, PLUGIN_BOOTSTRAP, PLUGIN_MANAGER, PLUGIN_NETWORKING, PLUGIN_RUN};
+use crate::{
+    config, fatal_error, ged, PLUGIN_BOOTSTRAP, PLUGIN_MANAGER, PLUGIN_NETWORKING, PLUGIN_RUN,
+};
 use kaolinite::utils::{get_absolute_path, get_cwd, get_file_ext, get_file_name};
 use kaolinite::Loc;
 use mlua::prelude::*;
@@ -55,6 +59,7 @@ impl LuaUserData for Editor {
         fields.add_field_method_get("current_document_id", |_, editor| {
             Ok(editor.files.get_atom(editor.ptr.clone()).map(|a| a.1))
         });
+        fields.add_field_method_get("task_status", |_, editor| Ok(editor.task_runner.status()));
         fields.add_field_method_get("document_count", |_, editor| {
             Ok(editor.files.get_all(editor.ptr.clone()).len())
         });
@@ -67,6 +72,12 @@ impl LuaUserData for Editor {
                 .clone()
                 .map_or("Unknown".to_string(), |ft| ft.name))
         });
+        fields.add_field_method_get("document_settings", |_, editor| {
+            Ok(editor
+                .files
+                .get(editor.ptr.clone())
+                .map(|file| file.settings.clone()))
+        });
         fields.add_field_method_get("file_name", |_, editor| {
             if let Some(doc) = editor.try_doc() {
                 Ok(Some(get_file_name(
@@ -95,10 +106,19 @@ impl LuaUserData for Editor {
             }
         });
         fields.add_field_method_get("cwd", |_, _| Ok(get_cwd()));
+        fields.add_field_method_get("project_config", |_, editor| {
+            Ok(editor.project_config.clone())
+        });
         fields.add_field_method_get("macro_recording", |_, editor| {
             Ok(editor.macro_man.recording)
         });
         fields.add_field_method_get("macro_playing", |_, editor| Ok(editor.macro_man.playing));
+        fields.add_field_method_get("recent_files", |_, editor| {
+            Ok(editor.history.recent_files(50))
+        });
+        fields.add_field_method_get("recent_projects", |_, editor| {
+            Ok(editor.history.recent_projects(50))
+        });
     }
 
     #[allow(clippy::too_many_lines)]
@@ -130,8 +150,13 @@ impl LuaUserData for Editor {
             if editor.load_config(&path, lua).is_some() {
                 editor.feedback = Feedback::Error("Failed to reload config".to_string());
             }
-            // Run plug-ins
-            let _ = lua.load(PLUGIN_RUN).exec();
+            // Run plug-ins (those that fail to load are reported, the rest carry on)
+            if let Err(err) = lua.load(PLUGIN_RUN).exec() {
+                editor.feedback = Feedback::Error(match err {
+                    LuaError::RuntimeError(msg) => msg,
+                    err => err.to_string(),
+                });
+            }
             // Attach plugin manager
             let _ = lua.load(PLUGIN_MANAGER).exec();
             Ok(())
@@ -150,11 +175,15 @@ impl LuaUserData for Editor {
             Ok(())
         });
         // Prompt the user
-        methods.add_method_mut("prompt", |_, editor, question: String| {
-            Ok(editor
-                .prompt(question)
-                .unwrap_or_else(|_| "error".to_string()))
-        });
+        // (prompts given a kind remember what was typed into them, for the up and down keys)
+        methods.add_method_mut(
+            "prompt",
+            |lua, editor, (question, kind): (String, Option<String>)| {
+                Ok(editor
+                    .prompt(lua, question, kind.as_deref())
+                    .unwrap_or_else(|_| "error".to_string()))
+            },
+        );
         // Edit commands (relative)
         methods.add_method_mut("insert", |_, editor, text: String| {
             editor.plugin_active = true;
@@ -378,89 +407,27 @@ impl LuaUserData for Editor {
         methods.add_method_mut(
             "insert_at",
             |_, editor, (text, x, y): (String, usize, usize)| {
-                if editor.try_doc().is_some() {
-                    editor.plugin_active = true;
-                    let y = y.saturating_sub(1);
-                    let location = editor.try_doc().unwrap().char_loc();
-                    if let Some(doc) = editor.try_doc_mut() {
-                        doc.move_to(&Loc { y, x });
-                    }
-                    for ch in text.chars() {
-                        if let Err(err) = editor.character(ch) {
-                            editor.feedback = Feedback::Error(err.to_string());
-                        }
-                    }
-                    if let Some(doc) = editor.try_doc_mut() {
-                        doc.move_to(&location);
-                    }
-                    editor.update_highlighter();
-                    editor.plugin_active = false;
+                if let Err(err) = editor.insert_at(&text, Loc::at(x, y.saturating_sub(1))) {
+                    editor.feedback = Feedback::Error(err.to_string());
                 }
                 Ok(())
             },
         );
         methods.add_method_mut("remove_at", |_, editor, (x, y): (usize, usize)| {
-            if editor.try_doc().is_some() {
-                editor.plugin_active = true;
-                let y = y.saturating_sub(1);
-                let location = editor.try_doc().unwrap().char_loc();
-                if let Some(doc) = editor.try_doc_mut() {
-                    doc.move_to(&Loc { y, x });
-                }
-                if let Err(err) = editor.delete() {
-                    editor.feedback = Feedback::Error(err.to_string());
-                }
-                if let Some(doc) = editor.try_doc_mut() {
-                    doc.move_to(&location);
-                }
-                editor.update_highlighter();
-                editor.plugin_active = false;
+            if let Err(err) = editor.remove_at(Loc::at(x, y.saturating_sub(1))) {
+                editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
         methods.add_method_mut("insert_line_at", |_, editor, (text, y): (String, usize)| {
-            if editor.try_doc().is_some() {
-                editor.plugin_active = true;
-                let y = y.saturating_sub(1);
-                let location = editor.try_doc().unwrap().char_loc();
-                if let Some(doc) = editor.try_doc_mut() {
-                    if y < doc.len_lines() {
-                        doc.move_to_y(y);
-                        doc.move_home();
-                        if let Err(err) = editor.enter() {
-                            editor.feedback = Feedback::Error(err.to_string());
-                        }
-                        editor.up();
-                    } else {
-                        doc.move_bottom();
-                        if let Err(err) = editor.enter() {
-                            editor.feedback = Feedback::Error(err.to_string());
-                        }
-                    }
-                    for ch in text.chars() {
-                        if let Err(err) = editor.character(ch) {
-                            editor.feedback = Feedback::Error(err.to_string());
-                        }
-                    }
-                }
-                editor.try_doc_mut().unwrap().move_to(&location);
-                editor.update_highlighter();
-                editor.plugin_active = false;
+            if let Err(err) = editor.insert_line_at(&text, y.saturating_sub(1)) {
+                editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
         methods.add_method_mut("remove_line_at", |_, editor, y: usize| {
-            if editor.try_doc().is_some() {
-                editor.plugin_active = true;
-                let y = y.saturating_sub(1);
-                let location = editor.try_doc().unwrap().char_loc();
-                editor.try_doc_mut().unwrap().move_to_y(y);
-                if let Err(err) = editor.delete_line() {
-                    editor.feedback = Feedback::Error(err.to_string());
-                }
-                editor.try_doc_mut().unwrap().move_to(&location);
-                editor.update_highlighter();
-                editor.plugin_active = false;
+            if let Err(err) = editor.remove_line_at(y.saturating_sub(1)) {
+                editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
@@ -544,33 +511,57 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
-        methods.add_method_mut("open", |_, editor, ()| {
-            if let Err(err) = editor.open_document() {
+        methods.add_method_mut("open", |lua, editor, ()| {
+            if let Err(err) = editor.open_document(lua) {
                 editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
-        methods.add_method_mut("save", |_, editor, ()| {
-            if let Err(err) = editor.save() {
-                editor.feedback = Feedback::Error(err.to_string());
+        // (these give plug-ins a chance to cancel beforehand, so the editor isn't borrowed yet)
+        methods.add_function("save", |lua, editor: LuaAnyUserData| {
+            let file_name = ged!(&editor).hook_file_name();
+            if fire_before(lua, "before_save", &file_name) {
+                let mut editor = ged!(mut &editor);
+                if let Err(err) = editor.save() {
+                    editor.feedback = Feedback::Error(err.to_string());
+                }
             }
             Ok(())
         });
-        methods.add_method_mut("save_as", |_, editor, ()| {
-            if let Err(err) = editor.save_as() {
-                editor.feedback = Feedback::Error(err.to_string());
+        methods.add_function("save_as", |lua, editor: LuaAnyUserData| {
+            let file_name = ged!(&editor).hook_file_name();
+            if fire_before(lua, "before_save", &file_name) {
+                let mut editor = ged!(mut &editor);
+                if let Err(err) = editor.save_as(lua) {
+                    editor.feedback = Feedback::Error(err.to_string());
+                }
             }
             Ok(())
         });
-        methods.add_method_mut("save_all", |_, editor, ()| {
+        methods.add_function("save_all", |lua, editor: LuaAnyUserData| {
+            let file_names: Vec<Option<String>> = ged!(&editor)
+                .files
+                .get_atom(ged!(&editor).ptr.clone())
+                .map(|(fcs, _)| fcs.iter().map(|fc| fc.doc.file_name.clone()).collect())
+                .unwrap_or_default();
+            for file_name in file_names {
+                if !fire_before(lua, "before_save", &file_name) {
+                    return Ok(());
+                }
+            }
+            let mut editor = ged!(mut &editor);
             if let Err(err) = editor.save_all() {
                 editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
-        methods.add_method_mut("quit", |_, editor, ()| {
-            if let Err(err) = editor.quit() {
-                editor.feedback = Feedback::Error(err.to_string());
+        methods.add_function("quit", |lua, editor: LuaAnyUserData| {
+            let file_name = ged!(&editor).hook_file_name();
+            if fire_before(lua, "before_close", &file_name) {
+                let mut editor = ged!(mut &editor);
+                if let Err(err) = editor.quit() {
+                    editor.feedback = Feedback::Error(err.to_string());
+                }
             }
             Ok(())
         });
@@ -601,6 +592,7 @@ impl LuaUserData for Editor {
                     .files
                     .open_up(editor.ptr.clone(), FileLayout::Atom(vec![fc], 0));
                 editor.cache_old_ptr(&editor.ptr.clone());
+                editor.hook(Hook::SplitOpen);
                 editor.update_cwd();
                 Ok(true)
             } else {
@@ -613,6 +605,7 @@ impl LuaUserData for Editor {
                     .files
                     .open_down(editor.ptr.clone(), FileLayout::Atom(vec![fc], 0));
                 editor.cache_old_ptr(&editor.ptr.clone());
+                editor.hook(Hook::SplitOpen);
                 editor.update_cwd();
                 Ok(true)
             } else {
@@ -625,6 +618,7 @@ impl LuaUserData for Editor {
                     .files
                     .open_left(editor.ptr.clone(), FileLayout::Atom(vec![fc], 0));
                 editor.cache_old_ptr(&editor.ptr.clone());
+                editor.hook(Hook::SplitOpen);
                 editor.update_cwd();
                 Ok(true)
             } else {
@@ -637,6 +631,7 @@ impl LuaUserData for Editor {
                     .files
                     .open_right(editor.ptr.clone(), FileLayout::Atom(vec![fc], 0));
                 editor.cache_old_ptr(&editor.ptr.clone());
+                editor.hook(Hook::SplitOpen);
                 editor.update_cwd();
                 Ok(true)
             } else {
@@ -748,12 +743,28 @@ impl LuaUserData for Editor {
             if let Some(actual_doc) = editor.try_doc() {
                 let doc = config!(editor.config, document);
                 if let Some(file_type) = doc.file_types.get_name(&name) {
-                    let mut highlighter = file_type.get_highlighter(&editor.config, 4);
-                    highlighter.run(&actual_doc.lines);
+                    let path = actual_doc
+                        .file_name
+                        .as_ref()
+                        .and_then(|n| get_absolute_path(n));
+                    // The file type's settings apply from now on
                     if let Some(file) = editor.files.get_mut(editor.ptr.clone()) {
+                        file.file_type = Some(file_type.clone());
+                    }
+                    editor.refresh_settings();
+                    if let Some(file) = editor.files.get_mut(editor.ptr.clone()) {
+                        let tab_width = file.settings.tab_width;
+                        let mut highlighter = file_type.get_highlighter(&editor.config, tab_width);
+                        highlighter.run(&file.doc.lines);
                         file.highlighter = highlighter;
-                        file.file_type = Some(file_type);
                     }
+                    // (the choice is kept for the next time the file is opened)
+                    if let Some(path) = path {
+                        editor
+                            .history
+                            .set_file_type(&path, Some(file_type.name.clone()));
+                    }
+                    editor.hook(Hook::FileType(file_type.name));
                 } else {
                     editor.feedback = Feedback::Error(format!("Invalid file type: {name}"));
                 }
@@ -800,6 +811,7 @@ impl LuaUserData for Editor {
                     .files
                     .open_up(editor.ptr.clone(), FileLayout::Terminal(term));
                 editor.cache_old_ptr(&editor.ptr.clone());
+                editor.hook(Hook::SplitOpen);
                 Ok(true)
             } else {
                 Ok(false)
@@ -817,6 +829,7 @@ impl LuaUserData for Editor {
                     .files
                     .open_down(editor.ptr.clone(), FileLayout::Terminal(term));
                 editor.cache_old_ptr(&editor.ptr.clone());
+                editor.hook(Hook::SplitOpen);
                 Ok(true)
             } else {
                 Ok(false)
@@ -834,6 +847,7 @@ impl LuaUserData for Editor {
                     .files
                     .open_left(editor.ptr.clone(), FileLayout::Terminal(term));
                 editor.cache_old_ptr(&editor.ptr.clone());
+                editor.hook(Hook::SplitOpen);
                 Ok(true)
             } else {
                 Ok(false)
@@ -851,6 +865,7 @@ impl LuaUserData for Editor {
                     .files
                     .open_right(editor.ptr.clone(), FileLayout::Terminal(term));
                 editor.cache_old_ptr(&editor.ptr.clone());
+                editor.hook(Hook::SplitOpen);
                 Ok(true)
             } else {
                 Ok(false)
@@ -881,6 +896,7 @@ impl LuaUserData for Editor {
                                 .files
                                 .open_right(editor.ptr.clone(), FileLayout::Terminal(term));
                             editor.cache_old_ptr(&editor.ptr.clone());
+                            editor.hook(Hook::SplitOpen);
                             let _ = editor.render(lua);
                             // ...then compile and run the code
                             if let Some(FileLayout::Terminal(term)) =
@@ -903,16 +919,146 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
-        // Miscellaneous
-        methods.add_method_mut("open_command_line", |_, editor, ()| {
-            match editor.prompt("Command") {
-                Ok(command) => {
-                    editor.command = Some(command);
+        methods.add_method_mut("run_task", |lua, editor, name: String| {
+            if let Err(err) = editor.run_task(lua, &name) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("pick_task", |lua, editor, ()| {
+            if let Err(err) = editor.pick_task(lua) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        // Recently used files and projects
+        methods.add_method_mut("pick_recent_file", |_, editor, ()| {
+            if let Err(err) = editor.pick_recent_file() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("pick_recent_project", |_, editor, ()| {
+            if let Err(err) = editor.pick_recent_project() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method("task_list", |lua, editor, ()| {
+            Ok(editor.task_names(lua).unwrap_or_default())
+        });
+        // Buffers
+        methods.add_method("buffers", |_, editor, ()| {
+            let ids = editor.files.buffer_ids();
+            Ok(ids.into_iter().map(|id| Buffer { id }).collect::<Vec<_>>())
+        });
+        methods.add_method("current_buffer", |_, editor, ()| {
+            Ok(editor
+                .files
+                .get(editor.ptr.clone())
+                .map(|file| Buffer { id: file.id }))
+        });
+        methods.add_method("get_buffer", |_, editor, id: usize| {
+            Ok(editor.files.find_buffer(vec![], id).map(|_| Buffer { id }))
+        });
+        methods.add_method_mut("create_buffer", |_, editor, opts: Option<LuaTable>| {
+            let (name, read_only, file_type, focus) = match opts {
+                Some(opts) => (
+                    opts.get::<Option<String>>("name")?,
+                    opts.get::<Option<bool>>("read_only")?.unwrap_or(false),
+                    opts.get::<Option<String>>("filetype")?,
+                    opts.get::<Option<bool>>("focus")?.unwrap_or(true),
+                ),
+                None => (None, false, None, true),
+            };
+            let name = name.unwrap_or_else(|| "[Scratch]".to_string());
+            match editor.create_scratch(name, read_only, file_type.as_deref()) {
+                Ok(Some(id)) => {
+                    if focus {
+                        editor.focus_buffer(id);
+                    }
+                    Ok(Some(Buffer { id }))
                 }
+                Ok(None) => Ok(None),
                 Err(err) => {
                     editor.feedback = Feedback::Error(err.to_string());
+                    Ok(None)
                 }
             }
+        });
+        // Floating windows
+        methods.add_method_mut("open_window", |lua, editor, opts: LuaTable| {
+            let task_manager = editor.config.task_manager.clone();
+            let mut tm = task_manager.lock().unwrap();
+            let window = FloatingWindow::from_lua(lua, &opts, &mut tm)?;
+            std::mem::drop(tm);
+            Ok(editor.open_window(window))
+        });
+        methods.add_method_mut("close_window", |_, editor, id: usize| {
+            Ok(editor.close_window(id))
+        });
+        methods.add_method_mut(
+            "set_window_lines",
+            |_, editor, (id, lines): (usize, LuaTable)| {
+                let lines = lines_from_lua(&lines)?;
+                if let Some(window) = editor.floating.get_mut(id) {
+                    window.lines = lines;
+                    window.scroll = 0;
+                    window.selection = window.selection.map(|_| 0);
+                }
+                editor.needs_rerender = true;
+                Ok(())
+            },
+        );
+        methods.add_method_mut(
+            "scroll_window",
+            |_, editor, (id, amount): (usize, isize)| {
+                if let Err(err) = editor.move_window(id, amount) {
+                    editor.feedback = Feedback::Error(err.to_string());
+                }
+                Ok(())
+            },
+        );
+        methods.add_method_mut("window_selection", |_, editor, id: usize| {
+            Ok(editor
+                .floating
+                .get_mut(id)
+                .and_then(|window| window.selection)
+                .map(|idx| idx + 1))
+        });
+        // Decorations
+        methods.add_method_mut(
+            "add_decoration",
+            |lua, editor, (namespace, info): (String, LuaTable)| {
+                let Some(file) = editor.files.get_mut(editor.ptr.clone()) else {
+                    return Ok(None);
+                };
+                let id = file.add_decoration(lua, &namespace, &info)?;
+                editor.needs_rerender = true;
+                Ok(Some(id))
+            },
+        );
+        methods.add_method_mut("remove_decoration", |_, editor, id: usize| {
+            editor.needs_rerender = true;
+            Ok(editor
+                .files
+                .get_mut(editor.ptr.clone())
+                .is_some_and(|file| file.decorations.remove(&mut file.doc, id)))
+        });
+        methods.add_method_mut(
+            "clear_decorations",
+            |_, editor, namespace: Option<String>| {
+                if let Some(file) = editor.files.get_mut(editor.ptr.clone()) {
+                    file.decorations.clear(&mut file.doc, namespace.as_deref());
+                }
+                editor.needs_rerender = true;
+                Ok(())
+            },
+        );
+        // Miscellaneous
+        methods.add_method_mut("open_command_line", |_, editor, ()| {
+            // (it is opened once the key press has been handled, so completers can use the editor)
+            editor.command_line = true;
             Ok(())
         });
         // Macro
@@ -937,8 +1083,8 @@ impl LuaUserData for Editor {
 
 /// For representing a cursor location object within lua
 pub struct LuaLoc {
-    x: usize,
-    y: usize,
+    pub x: usize,
+    pub y: usize,
 }
 
 impl IntoLua for LuaLoc {
@@ -0,0 +1,377 @@
+/// Reading the settings that `.editorconfig` files give to the files in a project
+/// (see <https://editorconfig.org> for the format)
+use crate::config::{DocumentSettings, Indentation};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// A section of an `.editorconfig` file (the glob it applies to, and the properties it sets)
+type Section = (String, Vec<(String, String)>);
+
+impl DocumentSettings {
+    /// Apply the `.editorconfig` properties that apply to a file over the top of these settings
+    pub fn apply_editorconfig(&mut self, path: &str) {
+        let props = editorconfig(path);
+        let get = |key: &str| props.get(key).map(String::as_str);
+        let number = |key: &str| {
+            get(key)
+                .and_then(|v| v.parse::<usize>().ok())
+                .filter(|n| *n > 0)
+        };
+        let flag = |key: &str| get(key).and_then(|v| v.parse::<bool>().ok());
+        match get("indent_style") {
+            Some("tab") => self.indentation = Indentation::Tabs,
+            Some("space") => self.indentation = Indentation::Spaces,
+            _ => (),
+        }
+        // (when only one of the widths is given, the other one follows it)
+        let tab_width = number("tab_width");
+        let indent_size = if get("indent_size") == Some("tab") {
+            Some(tab_width.unwrap_or(self.tab_width))
+        } else {
+            number("indent_size")
+        };
+        if let Some(width) = tab_width.or(indent_size) {
+            self.tab_width = width;
+        }
+        if let Some(size) = indent_size.or(tab_width) {
+            self.indent_size = size;
+        }
+        if let Some(eol @ ("lf" | "crlf" | "cr")) = get("end_of_line") {
+            self.end_of_line = Some(eol.to_string());
+        }
+        if let Some(charset) = get("charset") {
+            self.charset = Some(charset.to_string());
+        }
+        if let Some(trim) = flag("trim_trailing_whitespace") {
+            self.trim_trailing_whitespace = trim;
+        }
+        if let Some(newline) = flag("insert_final_newline") {
+            self.insert_final_newline = Some(newline);
+        }
+        match get("max_line_length") {
+            Some("off") => self.max_line_length = None,
+            Some(_) => self.max_line_length = number("max_line_length").or(self.max_line_length),
+            None => (),
+        }
+    }
+}
+
+/// Find the `.editorconfig` properties that apply to a file
+/// (files closer to the file win over ones further up, and a file can mark itself as the root)
+fn editorconfig(path: &str) -> HashMap<String, String> {
+    let path = Path::new(path);
+    let mut files = vec![];
+    for dir in path.ancestors().skip(1) {
+        let Ok(text) = std::fs::read_to_string(dir.join(".editorconfig")) else {
+            continue;
+        };
+        let (root, sections) = parse(&text);
+        files.push((dir, sections));
+        if root {
+            break;
+        }
+    }
+    let mut properties = HashMap::new();
+    for (dir, sections) in files.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        for (glob, props) in sections {
+            if !matches(glob, &relative) {
+                continue;
+            }
+            for (key, value) in props {
+                if value == "unset" {
+                    properties.remove(key);
+                } else {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+    properties
+}
+
+/// Read an `.editorconfig` file (returns whether it is the root and the sections it contains)
+fn parse(text: &str) -> (bool, Vec<Section>) {
+    let mut root = false;
+    let mut sections: Vec<Section> = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((glob.to_string(), vec![]));
+        } else if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_lowercase();
+            if let Some((_, props)) = sections.last_mut() {
+                props.push((key, value));
+            } else if key == "root" {
+                // (properties before the first section apply to the file itself)
+                root = value == "true";
+            }
+        }
+    }
+    (root, sections)
+}
+
+/// Determine if a section glob matches a path (relative to the `.editorconfig` file)
+fn matches(glob: &str, path: &str) -> bool {
+    let (pattern, ranges) = glob_to_regex(glob.strip_prefix('/').unwrap_or(glob));
+    // Globs without a slash in them match files in any folder
+    let anchor = if glob.contains('/') { "^" } else { "(?:^|/)" };
+    let Ok(re) = Regex::new(&format!("{anchor}{pattern}$")) else {
+        return false;
+    };
+    let Some(caps) = re.captures(path) else {
+        return false;
+    };
+    // Number ranges are checked once the numbers have been found
+    ranges.iter().enumerate().all(|(idx, (start, end))| {
+        caps.get(idx + 1).is_none_or(|m| {
+            m.as_str()
+                .parse()
+                .is_ok_and(|n: i64| (*start..=*end).contains(&n))
+        })
+    })
+}
+
+/// Convert a glob into a regular expression
+/// (number ranges like {1..3} are captured, and their bounds are returned in order)
+fn glob_to_regex(glob: &str) -> (String, Vec<(i64, i64)>) {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut result = String::new();
+    let mut ranges = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                result += &regex::escape(&chars[i].to_string());
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 1;
+                if chars.get(i + 1) == Some(&'/') {
+                    // (a/**/b matches a/b too)
+                    i += 1;
+                    result += "(?:.*/)?";
+                } else {
+                    result += ".*";
+                }
+            }
+            '*' => result += "[^/]*",
+            '?' => result += "[^/]",
+            '[' => {
+                let close = chars[i + 1..].iter().position(|c| *c == ']');
+                let class: Option<String> =
+                    close.map(|len| chars[i + 1..=i + len].iter().collect());
+                match class.filter(|class| !class.contains('/') && !class.is_empty()) {
+                    Some(class) => {
+                        i += class.chars().count() + 1;
+                        let (negate, class) = match class.strip_prefix('!') {
+                            Some(rest) => ("^", rest.to_string()),
+                            None => ("", class),
+                        };
+                        let class = class.replace('\\', "\\\\").replace('[', "\\[");
+                        let _ = write!(result, "[{negate}{class}]");
+                    }
+                    None => result += "\\[",
+                }
+            }
+            '{' => {
+                let Some(len) = closing_brace(&chars[i + 1..]) else {
+                    result += "\\{";
+                    i += 1;
+                    continue;
+                };
+                let inner: String = chars[i + 1..i + 1 + len].iter().collect();
+                i += len + 1;
+                let range = inner.split_once("..").and_then(|(start, end)| {
+                    Some((start.parse::<i64>().ok()?, end.parse::<i64>().ok()?))
+                });
+                let options = split_options(&inner);
+                if let Some((start, end)) = range {
+                    result += r"([+-]?\d+)";
+                    ranges.push((start.min(end), start.max(end)));
+                } else if options.len() > 1 {
+                    let options: Vec<String> = options
+                        .iter()
+                        .map(|option| {
+                            let (pattern, mut inner_ranges) = glob_to_regex(option);
+                            ranges.append(&mut inner_ranges);
+                            pattern
+                        })
+                        .collect();
+                    let _ = write!(result, "(?:{})", options.join("|"));
+                } else {
+                    // (braces with a single option in them are taken literally)
+                    result += &regex::escape(&format!("{{{inner}}}"));
+                }
+            }
+            c => result += &regex::escape(&c.to_string()),
+        }
+        i += 1;
+    }
+    (result, ranges)
+}
+
+/// Find the length of the text before the brace that closes an opening brace
+fn closing_brace(chars: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (idx, c) in chars.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(idx),
+            '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Split the inside of braces into the options it contains (ignoring commas in nested braces)
+fn split_options(inner: &str) -> Vec<String> {
+    let mut options = vec![String::new()];
+    let mut depth = 0;
+    let mut escaped = false;
+    for c in inner.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                options.push(String::new());
+                continue;
+            }
+            _ => (),
+        }
+        options.last_mut().unwrap().push(c);
+    }
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check a glob against paths (relative to the `.editorconfig` file) it should and shouldn't match
+    fn check(cases: &[(&str, &[&str], &[&str])]) {
+        for (glob, yes, no) in cases {
+            for path in *yes {
+                assert!(matches(glob, path), "'{glob}' should match '{path}'");
+            }
+            for path in *no {
+                assert!(!matches(glob, path), "'{glob}' shouldn't match '{path}'");
+            }
+        }
+    }
+
+    #[test]
+    fn stars_and_question_marks() {
+        check(&[
+            ("*", &["a.c", "dir/a.c", ".hidden"], &[]),
+            (
+                "*.c",
+                &["a.c", "dir/a.c", "a/b/c.c", ".c"],
+                &["a.h", "a.cc", "c"],
+            ),
+            (
+                "a*e.c",
+                &["ae.c", "abcde.c", "dir/abe.c"],
+                &["a/e.c", "abe.cc"],
+            ),
+            ("a?c", &["abc", "a.c", "dir/abc"], &["ac", "abbc", "a/c"]),
+            (
+                "src/*.rs",
+                &["src/a.rs"],
+                &["src/a/b.rs", "x/src/a.rs", "a.rs"],
+            ),
+            ("/src/*.rs", &["src/a.rs"], &["x/src/a.rs"]),
+        ]);
+    }
+
+    #[test]
+    fn double_stars() {
+        check(&[
+            ("**.c", &["a.c", "a/b.c", "a/b/c.c"], &["a.h"]),
+            (
+                "a/**/z.c",
+                &["a/z.c", "a/b/z.c", "a/b/c/z.c"],
+                &["z.c", "b/z.c", "a/bz.c"],
+            ),
+            ("a**z.c", &["az.c", "abz.c", "a/b/z.c"], &["b/az.c.x"]),
+            ("a/**", &["a/b", "a/b/c.d"], &["b/a/c"]),
+        ]);
+    }
+
+    #[test]
+    fn character_classes() {
+        check(&[
+            ("[ab].c", &["a.c", "b.c"], &["c.c", "ab.c"]),
+            ("[!ab].c", &["c.c", "d.c"], &["a.c", "b.c"]),
+            ("[a-c].c", &["a.c", "b.c", "c.c"], &["d.c"]),
+            // (a class can't match a slash, so this is taken literally)
+            ("a[a/]b", &["a[a/]b"], &["aab", "a/b"]),
+            ("[.c", &["[.c"], &["a.c"]),
+        ]);
+    }
+
+    #[test]
+    fn braces() {
+        check(&[
+            ("*.{c,h}", &["a.c", "a.h"], &["a.o", "a.{c,h}"]),
+            (
+                "{foo,bar}.txt",
+                &["foo.txt", "bar.txt", "d/foo.txt"],
+                &["baz.txt"],
+            ),
+            ("{a,{b,c}}.d", &["a.d", "b.d", "c.d"], &["{b,c}.d"]),
+            ("{a,b}{c,d}", &["ac", "ad", "bc", "bd"], &["ab", "cd"]),
+            ("{a,*.x}", &["a", "b.x"], &["b"]),
+            ("{,a}b", &["b", "ab"], &["cb"]),
+            // (braces with a single option, or none, are taken literally)
+            ("{single}.b", &["{single}.b"], &["single.b"]),
+            ("{}", &["{}"], &[""]),
+            ("{a,b", &["{a,b"], &["a"]),
+            ("a\\{b,c}", &["a{b,c}"], &["ab", "ac"]),
+        ]);
+    }
+
+    #[test]
+    fn number_ranges() {
+        check(&[
+            (
+                "file{3..120}",
+                &["file3", "file60", "file120", "file060"],
+                &["file2", "file121", "filea"],
+            ),
+            (
+                "{10..3}.log",
+                &["3.log", "7.log", "10.log"],
+                &["2.log", "11.log"],
+            ),
+            ("{-5..5}", &["-5", "0", "+3", "5"], &["-6", "6"]),
+            ("a{1..3}b{5..6}", &["a1b5", "a3b6"], &["a4b5", "a1b7"]),
+            ("{a,{1..3}}.c", &["a.c", "2.c"], &["4.c"]),
+        ]);
+    }
+
+    #[test]
+    fn escapes() {
+        check(&[
+            ("\\*.c", &["*.c"], &["a.c"]),
+            ("a\\?c", &["a?c"], &["abc"]),
+            ("\\[ab\\]", &["[ab]"], &["a"]),
+            ("a.c", &["a.c"], &["abc"]),
+        ]);
+    }
+}
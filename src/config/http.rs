@@ -0,0 +1,317 @@
+//! Native HTTP client (part of the plug-in networking API)
+
+use crate::config::tasks::{Completion, TaskManager};
+use mlua::prelude::*;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default number of milliseconds to wait for a response
+const DEFAULT_TIMEOUT: u64 = 30_000;
+
+/// Represents a request that a plug-in wishes to make
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+    pub timeout: u64,
+}
+
+impl FromLua for HttpRequest {
+    fn from_lua(val: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let LuaValue::Table(table) = val else {
+            return Err(LuaError::external("http request options must be a table"));
+        };
+        let mut headers = vec![];
+        if let Some(given) = table.get::<Option<LuaTable>>("headers")? {
+            for pair in given.pairs::<String, String>() {
+                headers.push(pair?);
+            }
+        }
+        headers.sort();
+        Ok(Self {
+            method: table
+                .get::<Option<String>>("method")?
+                .unwrap_or_else(|| "GET".to_string())
+                .to_uppercase(),
+            url: table.get("url")?,
+            headers,
+            body: table.get("body")?,
+            timeout: table
+                .get::<Option<u64>>("timeout")?
+                .unwrap_or(DEFAULT_TIMEOUT),
+        })
+    }
+}
+
+impl HttpRequest {
+    /// Send off the request, waiting for the response
+    pub fn send(&self) -> HttpResponse {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_millis(self.timeout))
+            .build();
+        // Allow URLs without a scheme (e.g. localhost:5000/update)
+        let url = if self.url.contains("://") {
+            self.url.clone()
+        } else {
+            format!("http://{}", self.url)
+        };
+        let mut request = agent.request(&self.method, &url);
+        for (key, value) in &self.headers {
+            request = request.set(key, value);
+        }
+        let result = match &self.body {
+            Some(body) => request.send_string(body),
+            None => request.call(),
+        };
+        match result {
+            // Error status codes (e.g. 404) still come with a response to hand back
+            Ok(response) | Err(ureq::Error::Status(_, response)) => HttpResponse::from(response),
+            Err(ureq::Error::Transport(err)) => HttpResponse {
+                error: Some(err.to_string()),
+                ..HttpResponse::default()
+            },
+        }
+    }
+}
+
+/// Represents the response to a request
+#[derive(Debug, Default, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub error: Option<String>,
+}
+
+impl From<ureq::Response> for HttpResponse {
+    fn from(response: ureq::Response) -> Self {
+        let status = response.status();
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+        let mut body = String::new();
+        let error = response
+            .into_reader()
+            .read_to_string(&mut body)
+            .err()
+            .map(|e| e.to_string());
+        Self {
+            status,
+            headers,
+            body,
+            error,
+        }
+    }
+}
+
+impl IntoLua for HttpResponse {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        let headers = lua.create_table()?;
+        for (key, value) in self.headers {
+            headers.set(key, value)?;
+        }
+        table.set(
+            "ok",
+            self.error.is_none() && (200..300).contains(&self.status),
+        )?;
+        table.set("status", self.status)?;
+        table.set("headers", headers)?;
+        table.set("body", self.body)?;
+        table.set("error", self.error)?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+/// Push the `http` and `json` libraries into the lua environment
+pub fn register(lua: &Lua, task_manager: &Arc<Mutex<TaskManager>>) -> LuaResult<()> {
+    let http = lua.create_table()?;
+    http.set("backend", "native")?;
+    // Make a request, if a callback is provided, it will be run in the background
+    let task_manager = Arc::clone(task_manager);
+    let request = lua.create_function(
+        move |lua, (request, callback): (HttpRequest, Option<LuaFunction>)| {
+            let Some(callback) = callback else {
+                return request.send().into_lua(lua);
+            };
+            let id = task_manager
+                .lock()
+                .unwrap()
+                .register_callback(lua.create_registry_value(callback)?);
+            let task_manager = Arc::clone(&task_manager);
+            std::thread::spawn(move || {
+                let response = request.send();
+                task_manager
+                    .lock()
+                    .unwrap()
+                    .complete(id, Completion::Http(response));
+            });
+            Ok(LuaValue::Nil)
+        },
+    )?;
+    http.set("request", request)?;
+    lua.globals().set("http", http)?;
+
+    // Conversion to and from JSON
+    let json = lua.create_table()?;
+    let encode = lua.create_function(|lua, value: LuaValue| {
+        let value: serde_json::Value = lua.from_value(value)?;
+        serde_json::to_string(&value).map_err(LuaError::external)
+    })?;
+    json.set("encode", encode)?;
+    let decode = lua.create_function(|lua, text: String| {
+        let value: serde_json::Value = serde_json::from_str(&text).map_err(LuaError::external)?;
+        let options = LuaSerializeOptions::new()
+            .serialize_none_to_null(false)
+            .serialize_unit_to_null(false);
+        lua.to_value_with(&value, options)
+    })?;
+    json.set("decode", decode)?;
+    lua.globals().set("json", json)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Start a server on the loopback interface that answers a single request
+    /// (the reply is given the request that was received, and the server hands it back too)
+    fn serve_once(reply: fn(&str) -> String) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some((key, value)) = line.split_once(':') {
+                    if key.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            reader
+                .get_mut()
+                .write_all(reply(&request).as_bytes())
+                .unwrap();
+            request
+        });
+        (address, server)
+    }
+
+    fn request(method: &str, url: String, body: Option<&str>) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            url,
+            headers: vec![("X-Plugin".to_string(), "tests".to_string())],
+            body: body.map(str::to_string),
+            timeout: 5_000,
+        }
+    }
+
+    #[test]
+    fn request_and_response_round_trip() {
+        let (address, server) = serve_once(|request| {
+            let body = request.split("\r\n\r\n").nth(1).unwrap_or_default();
+            let body = format!("got {body}");
+            format!(
+                "HTTP/1.1 201 Created\r\nX-Reply: yes\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+        });
+        let response = request("POST", format!("{address}/update"), Some("{\"a\":1}")).send();
+        let received = server.join().unwrap();
+        assert!(received.starts_with("POST /update HTTP/1.1\r\n"));
+        assert!(received.to_lowercase().contains("x-plugin: tests\r\n"));
+        assert!(received.ends_with("\r\n\r\n{\"a\":1}"));
+        assert_eq!(response.error, None);
+        assert_eq!(response.status, 201);
+        assert!(response
+            .headers
+            .iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case("x-reply") && value == "yes"));
+        assert_eq!(response.body, "got {\"a\":1}");
+    }
+
+    #[test]
+    fn error_statuses_still_give_a_response() {
+        let (address, server) = serve_once(|_| {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 7\r\n\r\nmissing".to_string()
+        });
+        let response = request("GET", format!("http://{address}/nothing"), None).send();
+        server.join().unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body, "missing");
+        assert_eq!(response.error, None);
+        // (lua sees that the request didn't succeed)
+        let lua = Lua::new();
+        let table: LuaTable = lua.unpack(response.into_lua(&lua).unwrap()).unwrap();
+        assert!(!table.get::<bool>("ok").unwrap());
+        assert_eq!(table.get::<u16>("status").unwrap(), 404);
+    }
+
+    #[test]
+    fn failing_to_connect_gives_an_error() {
+        // (nothing is listening once the listener has been dropped)
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let response = request("GET", format!("http://{address}/"), None).send();
+        assert_eq!(response.status, 0);
+        assert!(response.error.is_some());
+    }
+
+    #[test]
+    fn requests_from_lua() {
+        let (address, server) = serve_once(|_| {
+            let body = "{\"items\":[1,2],\"name\":\"ox\"}";
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+        });
+        let lua = Lua::new();
+        let task_manager = Arc::new(Mutex::new(TaskManager::default()));
+        register(&lua, &task_manager).unwrap();
+        lua.globals().set("address", address).unwrap();
+        let (ok, name, second): (bool, String, i64) = lua
+            .load(
+                r#"
+                local response = http.request({url = address, headers = {accept = "application/json"}})
+                local data = json.decode(response.body)
+                return response.ok, data.name, data.items[2]
+                "#,
+            )
+            .eval()
+            .unwrap();
+        assert!(server
+            .join()
+            .unwrap()
+            .contains("accept: application/json\r\n"));
+        assert!(ok);
+        assert_eq!(name, "ox");
+        assert_eq!(second, 2);
+    }
+}
@@ -18,6 +18,8 @@ type LuaRes<T> = RResult<T, LuaError>;
 pub struct Terminal {
     pub mouse_enabled: bool,
     pub scroll_amount: usize,
+    /// Use the kitty keyboard protocol when the terminal supports it
+    pub keyboard_enhancement: bool,
     #[cfg(not(target_os = "windows"))]
     pub shell: Shell,
     #[cfg(target_os = "windows")]
@@ -30,6 +32,7 @@ impl Default for Terminal {
         Self {
             mouse_enabled: true,
             scroll_amount: 1,
+            keyboard_enhancement: true,
             #[cfg(not(target_os = "windows"))]
             shell: Shell::Bash,
             #[cfg(target_os = "windows")]
@@ -50,6 +53,13 @@ impl LuaUserData for Terminal {
             this.scroll_amount = value;
             Ok(())
         });
+        fields.add_field_method_get("keyboard_enhancement", |_, this| {
+            Ok(this.keyboard_enhancement)
+        });
+        fields.add_field_method_set("keyboard_enhancement", |_, this, value| {
+            this.keyboard_enhancement = value;
+            Ok(())
+        });
         #[cfg(not(target_os = "windows"))]
         fields.add_field_method_get("shell", |_, this| Ok(this.shell));
         #[cfg(not(target_os = "windows"))]
@@ -120,10 +130,16 @@ impl Default for GreetingMessage {
 
 impl GreetingMessage {
     /// Take the configuration information and render the greeting message
-    pub fn render(&self, lua: &Lua) -> (String, Vec<usize>) {
+    pub fn render(&self, lua: &Lua, recent_files: &[String]) -> (String, Vec<usize>) {
         let mut result = self.format.clone();
         // Substitute in simple values
         result = result.replace("{version}", VERSION).to_string();
+        let recent_files = if recent_files.is_empty() {
+            String::new()
+        } else {
+            format!("Recent Files:\n{}", recent_files.join("\n"))
+        };
+        result = result.replace("{recent_files}", &recent_files);
         result = result.replace('\t', "    ").to_string();
         // Handle highlighted part
         let start = result.find("{highlight_start}");
@@ -270,20 +286,12 @@ impl Default for TabLine {
 impl TabLine {
     /// Take the configuration information and render the tab line
     pub fn render(&self, lua: &Lua, fc: &FileContainer, fb: &mut Feedback) -> String {
-        let path = fc
-            .doc
-            .file_name
-            .clone()
-            .unwrap_or_else(|| "[No Name]".to_string());
+        let path = fc.display_path().unwrap_or_else(|| "[No Name]".to_string());
         let file_extension = get_file_ext(&path).unwrap_or_else(|| "Unknown".to_string());
         let absolute_path = get_absolute_path(&path).unwrap_or_else(|| "[No Name]".to_string());
         let file_name = get_file_name(&path).unwrap_or_else(|| "[No Name]".to_string());
         let icon = fc.file_type.clone().map_or("󰈙 ".to_string(), |t| t.icon);
-        let modified = if fc.doc.event_mgmt.with_disk(&fc.doc.take_snapshot()) {
-            ""
-        } else {
-            "[+]"
-        };
+        let modified = if fc.modified() { "[+]" } else { "" };
         let mut result = self.format.clone();
         result = result
             .replace("{file_extension}", &file_extension)
@@ -364,10 +372,7 @@ impl StatusLine {
         let mut result = vec![];
         let fc = editor.files.get(ptr.to_vec()).unwrap();
         let doc = &fc.doc;
-        let path = doc
-            .file_name
-            .clone()
-            .unwrap_or_else(|| "[No Name]".to_string());
+        let path = fc.display_path().unwrap_or_else(|| "[No Name]".to_string());
         let file_extension = get_file_ext(&path).unwrap_or_else(|| "Unknown".to_string());
         let absolute_path = get_absolute_path(&path).unwrap_or_else(|| "[No Name]".to_string());
         let file_name = get_file_name(&path).unwrap_or_else(|| "[No Name]".to_string());
@@ -376,14 +381,16 @@ impl StatusLine {
             .clone()
             .map_or("Unknown".to_string(), |ft| ft.name);
         let icon = fc.file_type.clone().map_or("󰈙 ".to_string(), |ft| ft.icon);
-        let modified = if doc.event_mgmt.with_disk(&doc.take_snapshot()) {
-            ""
-        } else {
-            "[+]"
-        };
+        let modified = if fc.modified() { "[+]" } else { "" };
         let cursor_y = (doc.loc().y + 1).to_string();
         let cursor_x = doc.char_ptr.to_string();
         let line_count = doc.len_lines().to_string();
+        let task_status = editor.task_runner.status();
+        let indentation = fc.settings.indentation.to_string();
+        let indent_size = fc.settings.indent_size.to_string();
+        let tab_width = fc.settings.tab_width.to_string();
+        let end_of_line = fc.end_of_line();
+        let charset = fc.charset();
 
         for part in &self.parts {
             let mut part = part.clone();
@@ -399,6 +406,12 @@ impl StatusLine {
             part = part.replace("{cursor_y}", &cursor_y).to_string();
             part = part.replace("{cursor_x}", &cursor_x).to_string();
             part = part.replace("{line_count}", &line_count).to_string();
+            part = part.replace("{task_status}", &task_status);
+            part = part.replace("{indentation}", &indentation);
+            part = part.replace("{indent_size}", &indent_size);
+            part = part.replace("{tab_width}", &tab_width);
+            part = part.replace("{end_of_line}", &end_of_line);
+            part = part.replace("{charset}", &charset);
             // Find functions to call and substitute in
             let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
             while let Some(m) = searcher.lfind(&part) {
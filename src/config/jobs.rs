@@ -0,0 +1,273 @@
+//! Background processes (part of the plug-in concurrency API)
+
+use crate::config::tasks::{Completion, TaskManager};
+use mlua::prelude::*;
+use std::io::{BufRead, BufReader, Read, Write};
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::process::CommandExt;
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// Represents a process running in the background
+#[derive(Debug)]
+pub struct Job {
+    pub pid: u32,
+    stdin: Option<ChildStdin>,
+    /// Set once the process has exited and all of its output has been delivered
+    finished: Arc<AtomicBool>,
+}
+
+impl Job {
+    /// Start a process, sending its output to the callback with the provided id
+    pub fn spawn(mut command: Command, id: usize, tm: &Arc<Mutex<TaskManager>>) -> LuaResult<Self> {
+        // (the process leads its own group, so anything it starts can be killed along with it)
+        #[cfg(not(target_os = "windows"))]
+        command.process_group(0);
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(LuaError::external)?;
+        let pid = child.id();
+        let stdin = child.stdin.take();
+        let readers = vec![
+            child
+                .stdout
+                .take()
+                .map(|out| forward(out, id, tm, Completion::JobStdout)),
+            child
+                .stderr
+                .take()
+                .map(|err| forward(err, id, tm, Completion::JobStderr)),
+        ];
+        let finished = Arc::new(AtomicBool::new(false));
+        let finished_clone = Arc::clone(&finished);
+        let tm = Arc::clone(tm);
+        std::thread::spawn(move || {
+            let code = child.wait().ok().and_then(|status| status.code());
+            // Make sure all output is delivered before reporting the exit
+            for reader in readers.into_iter().flatten() {
+                let _ = reader.join();
+            }
+            finished_clone.store(true, Ordering::SeqCst);
+            tm.lock().unwrap().complete(id, Completion::JobExit(code));
+        });
+        Ok(Self {
+            pid,
+            stdin,
+            finished,
+        })
+    }
+
+    /// Determine if the process (or anything it started) is still going
+    pub fn running(&self) -> bool {
+        !self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Stop the process, along with anything it started (returns false if it had already finished)
+    pub fn kill(&mut self) -> bool {
+        self.stdin = None;
+        if !self.running() {
+            return false;
+        }
+        #[cfg(not(target_os = "windows"))]
+        {
+            use nix::sys::signal::{killpg, Signal};
+            use nix::unistd::Pid;
+            #[allow(clippy::cast_possible_wrap)]
+            killpg(Pid::from_raw(self.pid as i32), Signal::SIGKILL).is_ok()
+        }
+        #[cfg(target_os = "windows")]
+        {
+            Command::new("taskkill")
+                .args(["/F", "/T", "/PID", &self.pid.to_string()])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success())
+        }
+    }
+}
+
+/// Send each line read from a stream to a callback
+fn forward<R: Read + Send + 'static>(
+    stream: R,
+    id: usize,
+    tm: &Arc<Mutex<TaskManager>>,
+    kind: fn(String) -> Completion,
+) -> JoinHandle<()> {
+    let tm = Arc::clone(tm);
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            tm.lock().unwrap().complete(id, kind(line));
+        }
+    })
+}
+
+impl LuaUserData for Job {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("pid", |_, job| Ok(job.pid));
+        fields.add_field_method_get("running", |_, job| Ok(job.running()));
+    }
+
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut("write", |_, job, data: String| {
+            if let Some(stdin) = &mut job.stdin {
+                stdin.write_all(data.as_bytes())?;
+                stdin.flush()?;
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        });
+        methods.add_method_mut("close", |_, job, ()| {
+            // Dropping stdin sends end of file to the process
+            job.stdin = None;
+            Ok(())
+        });
+        methods.add_method_mut("kill", |_, job, ()| Ok(job.kill()));
+    }
+}
+
+/// Push the `jobs` library into the lua environment
+pub fn register(lua: &Lua, task_manager: &Arc<Mutex<TaskManager>>) -> LuaResult<()> {
+    let jobs = lua.create_table()?;
+    let task_manager = Arc::clone(task_manager);
+    let spawn = lua.create_function(move |lua, (cmd, options): (LuaValue, Option<LuaTable>)| {
+        let options = match options {
+            Some(options) => options,
+            None => lua.create_table()?,
+        };
+        let mut command = build_command(cmd)?;
+        if let Some(cwd) = options.get::<Option<String>>("cwd")? {
+            command.current_dir(cwd);
+        }
+        if let Some(env) = options.get::<Option<LuaTable>>("env")? {
+            for pair in env.pairs::<String, String>() {
+                let (key, value) = pair?;
+                command.env(key, value);
+            }
+        }
+        // Pass each event on to the relevant handler
+        let dispatch = lua.create_function(move |_, (kind, data): (String, LuaValue)| {
+            if let Some(handler) = options.get::<Option<LuaFunction>>(format!("on_{kind}"))? {
+                handler.call::<()>(data)?;
+            }
+            Ok(())
+        })?;
+        let id = task_manager
+            .lock()
+            .unwrap()
+            .register_callback(lua.create_registry_value(dispatch)?);
+        Job::spawn(command, id, &task_manager)
+    })?;
+    jobs.set("spawn", spawn)?;
+    lua.globals().set("jobs", jobs)?;
+    Ok(())
+}
+
+/// Create a command from either a shell command (string) or a list of arguments (table)
+fn build_command(cmd: LuaValue) -> LuaResult<Command> {
+    match cmd {
+        LuaValue::String(cmd) => {
+            let cmd = cmd.to_string_lossy();
+            let mut command;
+            if cfg!(target_os = "windows") {
+                command = Command::new("cmd");
+                command.arg("/C");
+            } else {
+                command = Command::new("sh");
+                command.arg("-c");
+            }
+            command.arg(cmd);
+            Ok(command)
+        }
+        LuaValue::Table(args) => {
+            let args = args
+                .sequence_values::<String>()
+                .collect::<LuaResult<Vec<_>>>()?;
+            let Some((program, args)) = args.split_first() else {
+                return Err(LuaError::external("no program was given to run"));
+            };
+            let mut command = Command::new(program);
+            command.args(args);
+            Ok(command)
+        }
+        _ => Err(LuaError::external(
+            "a job must be a string or a list of arguments",
+        )),
+    }
+}
+
+#[cfg(test)]
+#[cfg(not(target_os = "windows"))]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Start a shell command as a job
+    fn start(cmd: &str) -> (Job, Arc<Mutex<TaskManager>>) {
+        let tm = Arc::new(Mutex::new(TaskManager::default()));
+        let command = build_command(LuaValue::String(Lua::new().create_string(cmd).unwrap()));
+        let job = Job::spawn(command.unwrap(), 1, &tm).unwrap();
+        (job, tm)
+    }
+
+    /// Collect everything a job reports until it exits (panicking if that takes too long)
+    fn results(tm: &Arc<Mutex<TaskManager>>) -> Vec<String> {
+        let start = Instant::now();
+        let mut results = vec![];
+        while start.elapsed() < Duration::from_secs(5) {
+            for (id, completion) in tm.lock().unwrap().completion_list() {
+                assert_eq!(id, 1);
+                results.push(match completion {
+                    Completion::JobStdout(line) => format!("stdout {line}"),
+                    Completion::JobStderr(line) => format!("stderr {line}"),
+                    Completion::JobExit(code) => {
+                        return [results, vec![format!("exit {code:?}")]].concat()
+                    }
+                    other => panic!("unexpected completion {other:?}"),
+                });
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("job didn't exit, got {results:?}");
+    }
+
+    #[test]
+    fn output_and_exit_code_are_reported() {
+        let (job, tm) = start("echo one; echo two >&2; echo three; exit 3");
+        let mut results = results(&tm);
+        assert_eq!(results.pop().unwrap(), "exit Some(3)");
+        results.sort();
+        assert_eq!(results, ["stderr two", "stdout one", "stdout three"]);
+        assert!(!job.running());
+    }
+
+    #[test]
+    fn input_is_passed_on() {
+        let (mut job, tm) = start("cat");
+        job.stdin.as_mut().unwrap().write_all(b"hello\n").unwrap();
+        // (closing stdin lets cat finish)
+        job.stdin = None;
+        assert_eq!(results(&tm), ["stdout hello", "exit Some(0)"]);
+    }
+
+    #[test]
+    fn killing_stops_everything_the_job_started() {
+        // (the background sleep holds on to the output, so the exit is only reported once it dies)
+        let (mut job, tm) = start("sleep 30 & echo started; sleep 30");
+        while tm.lock().unwrap().completed.is_empty() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(job.running());
+        assert!(job.kill());
+        assert_eq!(results(&tm), ["stdout started", "exit None"]);
+        assert!(!job.running());
+        assert!(!job.kill());
+    }
+}
@@ -0,0 +1,479 @@
+//! Key bindings made up of sequences of key presses, which can be scoped to certain contexts
+
+use crate::config::key_to_string;
+use crossterm::event::KeyEvent;
+use mlua::prelude::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// An action bound to a key sequence, along with a description to show in hints
+#[derive(Debug, Clone)]
+struct Binding {
+    action: LuaFunction,
+    description: Option<String>,
+}
+
+/// A tree of key sequences, where each key press moves further down the tree
+#[derive(Debug, Default)]
+struct KeyTrie {
+    children: HashMap<String, KeyTrie>,
+    binding: Option<Binding>,
+}
+
+impl KeyTrie {
+    /// Follow a sequence of keys down the tree
+    fn walk(&self, keys: &[String]) -> Option<&Self> {
+        keys.iter()
+            .try_fold(self, |node, key| node.children.get(key))
+    }
+
+    /// Bind an action to a sequence of keys
+    fn insert(&mut self, keys: &[String], binding: Binding) {
+        let node = keys.iter().fold(self, |node, key| {
+            node.children.entry(key.clone()).or_default()
+        });
+        node.binding = Some(binding);
+    }
+
+    /// Unbind a sequence of keys, tidying up branches that no longer lead anywhere
+    fn remove(&mut self, keys: &[String]) -> bool {
+        let Some((first, rest)) = keys.split_first() else {
+            return self.binding.take().is_some();
+        };
+        let Some(child) = self.children.get_mut(first) else {
+            return false;
+        };
+        let removed = child.remove(rest);
+        if child.binding.is_none() && child.children.is_empty() {
+            self.children.remove(first);
+        }
+        removed
+    }
+}
+
+/// What should happen after a key has been pressed
+#[derive(Debug)]
+pub enum KeyOutcome {
+    /// A key sequence has been completed, run this action
+    Run(LuaFunction),
+    /// The keys pressed so far could lead to a binding, wait for more
+    Pending,
+    /// Nothing is bound to this key, the editor should handle it as usual
+    Unbound(KeyEvent),
+}
+
+/// Stores key bindings for each context, along with any key sequence that is part-way through.
+///
+/// Plug-ins and configuration files bind sequences of keys through the `keymap` global:
+/// ```lua
+/// keymap:set("document", "ctrl_k ctrl_c", function() ... end, "Comment line")
+/// keymap:set("global", "alt_space f", function() ... end, "Find file")
+/// keymap:set("prompt", "ctrl_u", function(input) return "" end, "Clear input")
+/// keymap:remove("document", "ctrl_k ctrl_c")
+/// keymap.timeout = 1000 -- how long to wait for the next key (in milliseconds)
+/// keymap.hints = true   -- show which keys can come next while waiting
+/// ```
+/// Contexts are `global` (everywhere apart from prompts), `document`, `file_tree`, `terminal`
+/// and `prompt` (where bindings receive the input typed so far and can return new input).
+/// Any other name is a custom context, which is active while entered with `keymap:enter(name)`
+/// (until `keymap:leave(name)`) or while a scratch buffer using it is focused.
+#[derive(Debug)]
+pub struct Keymap {
+    maps: HashMap<String, KeyTrie>,
+    /// Custom contexts that plug-ins have entered (the most recent is last)
+    pub entered: Vec<String>,
+    /// The keys pressed so far in an incomplete sequence
+    pending: Vec<KeyEvent>,
+    pending_since: Option<Instant>,
+    /// How long to wait for the next key in a sequence (in milliseconds)
+    pub timeout: u64,
+    /// Whether to show the keys that can come next in a sequence
+    pub hints: bool,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            maps: HashMap::default(),
+            entered: vec![],
+            pending: vec![],
+            pending_since: None,
+            timeout: 1000,
+            hints: true,
+        }
+    }
+}
+
+impl Keymap {
+    /// Work out what to do when a key is pressed, given the contexts that are active
+    /// (in order of priority)
+    /// A key that breaks a sequence first gives back what should happen with the keys before it
+    pub fn feed(&mut self, contexts: &[String], key: KeyEvent) -> Vec<KeyOutcome> {
+        self.pending.push(key);
+        let mut binding = None;
+        let mut prefix = false;
+        for node in contexts.iter().filter_map(|c| self.node(c)) {
+            prefix |= !node.children.is_empty();
+            if binding.is_none() {
+                binding.clone_from(&node.binding);
+            }
+        }
+        // Wait to see if a longer sequence is being typed
+        if prefix {
+            self.pending_since = Some(Instant::now());
+            return vec![KeyOutcome::Pending];
+        }
+        if let Some(binding) = binding {
+            self.cancel();
+            return vec![KeyOutcome::Run(binding.action)];
+        }
+        self.pending.pop();
+        if self.pending.is_empty() {
+            return vec![KeyOutcome::Unbound(key)];
+        }
+        // The sequence was broken, deal with the keys before this one,
+        // then see if this key starts a new sequence
+        let mut outcomes = self.flush(contexts);
+        outcomes.retain(|outcome| !matches!(outcome, KeyOutcome::Pending));
+        outcomes.extend(self.feed(contexts, key));
+        outcomes
+    }
+
+    /// Check whether an incomplete sequence has timed out, giving back what should happen
+    /// with the keys pressed so far
+    pub fn expire(&mut self, contexts: &[String]) -> Option<Vec<KeyOutcome>> {
+        let since = self.pending_since?;
+        if since.elapsed() < Duration::from_millis(self.timeout) {
+            return None;
+        }
+        Some(self.flush(contexts))
+    }
+
+    /// Give up on an incomplete sequence, running what is bound to the longest run of keys
+    /// pressed so far (or handing the first key back to be handled as usual if nothing is bound)
+    /// and feeding the keys after it back in
+    fn flush(&mut self, contexts: &[String]) -> Vec<KeyOutcome> {
+        let keys = self.cancel();
+        if keys.is_empty() {
+            return vec![];
+        }
+        let pressed: Vec<String> = keys
+            .iter()
+            .map(|key| key_to_string(key.modifiers, key.code))
+            .collect();
+        let bound = (1..=keys.len()).rev().find_map(|len| {
+            let binding = contexts.iter().find_map(|c| {
+                let node = self.maps.get(c)?.walk(&pressed[..len])?;
+                node.binding.clone()
+            })?;
+            Some((len, binding))
+        });
+        let (mut outcomes, len) = match bound {
+            Some((len, binding)) => (vec![KeyOutcome::Run(binding.action)], len),
+            None => (vec![KeyOutcome::Unbound(keys[0])], 1),
+        };
+        for key in &keys[len..] {
+            // (only the last key can leave a sequence waiting)
+            outcomes.retain(|outcome| !matches!(outcome, KeyOutcome::Pending));
+            outcomes.extend(self.feed(contexts, *key));
+        }
+        outcomes
+    }
+
+    /// Forget about any incomplete sequence, returning the keys that were pressed
+    pub fn cancel(&mut self) -> Vec<KeyEvent> {
+        self.pending_since = None;
+        std::mem::take(&mut self.pending)
+    }
+
+    /// The keys pressed so far in an incomplete sequence
+    pub fn pending(&self) -> Vec<String> {
+        self.pending
+            .iter()
+            .map(|key| key_to_string(key.modifiers, key.code))
+            .collect()
+    }
+
+    /// The keys that can come next in an incomplete sequence, along with their descriptions
+    pub fn next_keys(&self, contexts: &[String]) -> Vec<(String, String)> {
+        let mut result: Vec<(String, String)> = vec![];
+        for node in contexts.iter().filter_map(|c| self.node(c)) {
+            for (key, child) in &node.children {
+                if result.iter().any(|(k, _)| k == key) {
+                    continue;
+                }
+                let description = match &child.binding {
+                    Some(Binding {
+                        description: Some(description),
+                        ..
+                    }) => description.clone(),
+                    _ if !child.children.is_empty() => "+more".to_string(),
+                    _ => String::new(),
+                };
+                result.push((display_key(key), description));
+            }
+        }
+        result.sort();
+        result
+    }
+
+    /// Find where the pending keys lead to within a context
+    fn node(&self, context: &str) -> Option<&KeyTrie> {
+        self.maps.get(context)?.walk(&self.pending())
+    }
+}
+
+/// Split a sequence of keys (e.g. `ctrl_k ctrl_c`) into the format used by the editor
+fn parse_keys(sequence: &str) -> Vec<String> {
+    sequence
+        .split_whitespace()
+        .map(|key| {
+            if key == "space" || key.ends_with("_space") {
+                format!("{} ", key.trim_end_matches("space"))
+            } else if key == "shift_tab" {
+                "shift_backtab".to_string()
+            } else {
+                key.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Turn a key back into the format it was bound in
+pub fn display_key(key: &str) -> String {
+    if let Some(modifiers) = key.strip_suffix(' ') {
+        format!("{modifiers}space")
+    } else {
+        key.to_string()
+    }
+}
+
+impl LuaUserData for Keymap {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("timeout", |_, keymap| Ok(keymap.timeout));
+        fields.add_field_method_set("timeout", |_, this, value| {
+            this.timeout = value;
+            Ok(())
+        });
+        fields.add_field_method_get("hints", |_, keymap| Ok(keymap.hints));
+        fields.add_field_method_set("hints", |_, this, value| {
+            this.hints = value;
+            Ok(())
+        });
+        fields.add_field_method_get("pending", |_, keymap| {
+            Ok(keymap
+                .pending()
+                .iter()
+                .map(|key| display_key(key))
+                .collect::<Vec<_>>()
+                .join(" "))
+        });
+    }
+
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut(
+            "set",
+            |_,
+             keymap,
+             (context, keys, action, description): (
+                String,
+                String,
+                LuaFunction,
+                Option<String>,
+            )| {
+                let keys = parse_keys(&keys);
+                if keys.is_empty() {
+                    return Err(LuaError::external("no keys were provided"));
+                }
+                let binding = Binding {
+                    action,
+                    description,
+                };
+                keymap
+                    .maps
+                    .entry(context)
+                    .or_default()
+                    .insert(&keys, binding);
+                Ok(())
+            },
+        );
+        // (given an action, the keys are only unbound if they are still bound to that action)
+        methods.add_method_mut(
+            "remove",
+            |_, keymap, (context, keys, action): (String, String, Option<LuaFunction>)| {
+                let keys = parse_keys(&keys);
+                let Some(map) = keymap.maps.get_mut(&context) else {
+                    return Ok(false);
+                };
+                if let Some(action) = action {
+                    let bound = map.walk(&keys).and_then(|node| node.binding.as_ref());
+                    if !bound.is_some_and(|binding| binding.action == action) {
+                        return Ok(false);
+                    }
+                }
+                Ok(map.remove(&keys))
+            },
+        );
+        methods.add_method_mut("enter", |_, keymap, context: String| {
+            keymap.entered.push(context);
+            Ok(())
+        });
+        methods.add_method_mut("leave", |_, keymap, context: String| {
+            if let Some(idx) = keymap.entered.iter().rposition(|c| *c == context) {
+                keymap.entered.remove(idx);
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    /// Set up a keymap with some sequences bound in the document context
+    fn keymap(lua: &Lua) -> LuaAnyUserData {
+        let keymap = lua.create_userdata(Keymap::default()).unwrap();
+        lua.globals().set("keymap", keymap.clone()).unwrap();
+        lua.load(
+            r#"
+            keymap:set("document", "ctrl_k ctrl_c", function() return "comment" end)
+            keymap:set("document", "g g", function() return "top" end)
+            keymap:set("document", "space f", function() return "find" end)
+            keymap:set("document", "space", function() return "leader" end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        keymap
+    }
+
+    /// Press some keys, describing what should happen after the last one
+    fn press(keymap: &LuaAnyUserData, keys: &[KeyEvent]) -> Vec<String> {
+        let contexts = vec!["document".to_string()];
+        let mut keymap = keymap.borrow_mut::<Keymap>().unwrap();
+        let mut outcomes = vec![];
+        for key in keys {
+            outcomes = keymap.feed(&contexts, *key);
+        }
+        describe(outcomes)
+    }
+
+    fn describe(outcomes: Vec<KeyOutcome>) -> Vec<String> {
+        outcomes
+            .into_iter()
+            .map(|outcome| match outcome {
+                KeyOutcome::Run(action) => action.call(()).unwrap(),
+                KeyOutcome::Pending => "pending".to_string(),
+                KeyOutcome::Unbound(key) => key_to_string(key.modifiers, key.code),
+            })
+            .collect()
+    }
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn sequences_run_once_complete() {
+        let lua = Lua::new();
+        let keymap = keymap(&lua);
+        assert_eq!(press(&keymap, &[ctrl('k')]), vec!["pending"]);
+        assert_eq!(press(&keymap, &[ctrl('c')]), vec!["comment"]);
+        assert_eq!(press(&keymap, &[key('g'), key('g')]), vec!["top"]);
+        assert_eq!(press(&keymap, &[key('x')]), vec!["x"]);
+    }
+
+    #[test]
+    fn broken_sequences_replay_their_keys() {
+        let lua = Lua::new();
+        let keymap = keymap(&lua);
+        assert_eq!(press(&keymap, &[ctrl('k'), key('x')]), vec!["ctrl_k", "x"]);
+        assert_eq!(press(&keymap, &[key('g'), key('h')]), vec!["g", "h"]);
+        // (a key that breaks one sequence can start another)
+        assert_eq!(
+            press(&keymap, &[ctrl('k'), key('g')]),
+            vec!["ctrl_k", "pending"]
+        );
+        assert_eq!(press(&keymap, &[key('g')]), vec!["top"]);
+        // (keys bound on their own run instead of being replayed)
+        assert_eq!(press(&keymap, &[key(' '), key('x')]), vec!["leader", "x"]);
+        assert_eq!(press(&keymap, &[key(' '), key('f')]), vec!["find"]);
+    }
+
+    #[test]
+    fn incomplete_sequences_expire() {
+        let lua = Lua::new();
+        let keymap = keymap(&lua);
+        let contexts = vec!["document".to_string()];
+        keymap.borrow_mut::<Keymap>().unwrap().timeout = 0;
+        assert_eq!(press(&keymap, &[ctrl('k')]), vec!["pending"]);
+        let expired = keymap.borrow_mut::<Keymap>().unwrap().expire(&contexts);
+        assert_eq!(describe(expired.unwrap()), vec!["ctrl_k"]);
+        assert_eq!(press(&keymap, &[key(' ')]), vec!["pending"]);
+        let expired = keymap.borrow_mut::<Keymap>().unwrap().expire(&contexts);
+        assert_eq!(describe(expired.unwrap()), vec!["leader"]);
+        // (nothing happens once there is nothing left to wait for)
+        let expired = keymap.borrow_mut::<Keymap>().unwrap().expire(&contexts);
+        assert!(expired.is_none());
+    }
+
+    #[test]
+    fn broken_sequences_run_the_longest_bound_part() {
+        let lua = Lua::new();
+        let keymap = keymap(&lua);
+        let contexts = vec!["document".to_string()];
+        lua.load(r#"keymap:set("document", "space g g", function() return "grep" end)"#)
+            .exec()
+            .unwrap();
+        assert_eq!(
+            press(&keymap, &[key(' '), key('g'), key('x')]),
+            vec!["leader", "g", "x"]
+        );
+        // (the keys after the bound part can start a new sequence)
+        assert_eq!(
+            press(&keymap, &[key(' '), key('g'), ctrl('k')]),
+            vec!["leader", "g", "pending"]
+        );
+        assert_eq!(press(&keymap, &[ctrl('c')]), vec!["comment"]);
+        keymap.borrow_mut::<Keymap>().unwrap().timeout = 0;
+        assert_eq!(press(&keymap, &[key(' '), key('g')]), vec!["pending"]);
+        let expired = keymap.borrow_mut::<Keymap>().unwrap().expire(&contexts);
+        assert_eq!(describe(expired.unwrap()), vec!["leader", "pending"]);
+        let expired = keymap.borrow_mut::<Keymap>().unwrap().expire(&contexts);
+        assert_eq!(describe(expired.unwrap()), vec!["g"]);
+    }
+
+    #[test]
+    fn bindings_are_only_removed_for_their_action() {
+        let lua = Lua::new();
+        let keymap = keymap(&lua);
+        let removed: (bool, bool) = lua
+            .load(
+                r#"
+                local other = keymap:remove("document", "g g", function() end)
+                return other, keymap:remove("document", "space f")
+                "#,
+            )
+            .eval()
+            .unwrap();
+        assert_eq!(removed, (false, true));
+        assert_eq!(press(&keymap, &[key('g'), key('g')]), vec!["top"]);
+        assert_eq!(press(&keymap, &[key(' ')]), vec!["leader"]);
+        lua.load(
+            r#"
+            local action = function() return "bottom" end
+            keymap:set("document", "g t", action)
+            assert(keymap:remove("document", "g t", action))
+            "#,
+        )
+        .exec()
+        .unwrap();
+        assert_eq!(press(&keymap, &[key('g'), key('t')]), vec!["g", "t"]);
+    }
+}
@@ -82,6 +82,13 @@ pub fn key_to_string(modifiers: KMod, key: KCode) -> String {
     if modifiers.contains(KMod::META) {
         result += "meta_";
     }
+    // (only reported by terminals using the kitty keyboard protocol)
+    if modifiers.contains(KMod::SUPER) {
+        result += "super_";
+    }
+    if modifiers.contains(KMod::HYPER) {
+        result += "hyper_";
+    }
     result += &match key {
         KCode::Char('\\') => "\\\\".to_string(),
         KCode::Char('"') => "\\\"".to_string(),
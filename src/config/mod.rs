@@ -1,33 +1,91 @@
 /// For general configuration
-use crate::editor::{FileType, FileTypes};
+use crate::editor::{FileType, FileTypeSettings, FileTypes};
 use crate::error::{OxError, Result};
 use mlua::prelude::*;
 use std::fmt::{Display, Error, Formatter};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 mod assistant;
+mod buffers;
 mod colors;
 mod editor;
+mod editorconfig;
 mod filetree;
 mod highlighting;
+mod http;
 mod interface;
+mod jobs;
+mod keymap;
 mod keys;
+mod project;
 mod runner;
+mod session;
 mod tasks;
 
 pub use assistant::Assistant;
+pub use buffers::Buffer;
 pub use colors::{Color, Colors};
+pub use editor::LuaLoc;
 pub use filetree::FileTree;
 pub use highlighting::SyntaxHighlighting;
 pub use interface::{GreetingMessage, HelpMessage, LineNumbers, StatusLine, TabLine, Terminal};
+pub use keymap::{display_key, KeyOutcome, Keymap};
 pub use keys::{get_listeners, key_to_string, run_key, run_key_before};
-pub use tasks::TaskManager;
+pub use project::{find_project_tasks, is_trusted, read_trusted, startup_project_config, trust};
+pub use runner::{load_tasks, project_root, shell_quote, task_order, TaskVariables};
+pub use session::Session;
+pub use tasks::{Completion, TaskManager};
+use tasks::{TaskTarget, TimerHandle};
+
+/// The folder ox keeps its own files in (`~/.config/ox`, or `~/ox` on Windows)
+pub fn config_dir() -> Option<PathBuf> {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()?;
+    Some(if cfg!(target_os = "windows") {
+        Path::new(&home).join("ox")
+    } else {
+        Path::new(&home).join(".config").join("ox")
+    })
+}
 
 /// Issue a warning to the user
 fn issue_warning(msg: &str) {
     eprintln!("[WARNING] {msg}");
 }
 
+/// Attach a task to the task manager on behalf of a plug-in
+/// (functions are given a delay in milliseconds, whereas the older form of providing
+/// the name of a global function keeps using a delay in seconds, so existing plug-ins
+/// and configuration files carry on working)
+fn schedule(
+    lua: &Lua,
+    task_manager: &Arc<Mutex<TaskManager>>,
+    delay: u64,
+    target: LuaValue,
+    repeat: bool,
+) -> LuaResult<TimerHandle> {
+    let mut tm = task_manager.lock().unwrap();
+    let (delay, target) = match target {
+        LuaValue::Function(func) => {
+            let id = tm.register_callback(lua.create_registry_value(func)?);
+            (Duration::from_millis(delay), TaskTarget::Callback(id))
+        }
+        LuaValue::String(name) => (
+            Duration::from_secs(delay),
+            TaskTarget::Name(name.to_string_lossy()),
+        ),
+        _ => return Err(LuaError::external("expected a function or function name")),
+    };
+    let id = tm.attach(delay, target, repeat);
+    Ok(TimerHandle {
+        id,
+        task_manager: Arc::clone(task_manager),
+    })
+}
+
 /// This contains the default configuration lua file
 const DEFAULT_CONFIG: &str = include_str!("../../config/.oxrc");
 
@@ -91,6 +149,12 @@ macro_rules! config {
     ($cfg:expr, terminal) => {
         $cfg.terminal.borrow::<$crate::config::Terminal>().unwrap()
     };
+    ($cfg:expr, session) => {
+        $cfg.session.borrow::<$crate::config::Session>().unwrap()
+    };
+    ($cfg:expr, keymap) => {
+        $cfg.keymap.borrow::<$crate::config::Keymap>().unwrap()
+    };
 }
 
 /// The struct that holds all the configuration information
@@ -106,6 +170,8 @@ pub struct Config {
     pub file_tree: LuaAnyUserData,
     pub terminal: LuaAnyUserData,
     pub document: LuaAnyUserData,
+    pub keymap: LuaAnyUserData,
+    pub session: LuaAnyUserData,
     pub task_manager: Arc<Mutex<TaskManager>>,
 }
 
@@ -123,14 +189,11 @@ impl Config {
         let file_tree = lua.create_userdata(FileTree::default())?;
         let terminal = lua.create_userdata(Terminal::default())?;
         let document = lua.create_userdata(Document::default())?;
+        let keymap = lua.create_userdata(Keymap::default())?;
+        let session = lua.create_userdata(Session::default())?;
 
-        // Set up the task manager
+        // Set up the task manager (it is serviced by the main event loop)
         let task_manager = Arc::new(Mutex::new(TaskManager::default()));
-        let task_manager_clone = Arc::clone(&task_manager);
-        std::thread::spawn(move || loop {
-            task_manager_clone.lock().unwrap().cycle();
-            std::thread::sleep(std::time::Duration::from_secs(1));
-        });
 
         // Push in configuration globals
         lua.globals().set("syntax", syntax_highlighting.clone())?;
@@ -144,6 +207,8 @@ impl Config {
         lua.globals().set("colors", colors.clone())?;
         lua.globals().set("terminal", terminal.clone())?;
         lua.globals().set("document", document.clone())?;
+        lua.globals().set("keymap", keymap.clone())?;
+        lua.globals().set("session", session.clone())?;
 
         // Define task list
         let task_manager_clone = Arc::clone(&task_manager);
@@ -155,30 +220,26 @@ impl Config {
         })?;
         lua.globals().set("get_task_list", get_task_list)?;
 
-        // Provide a function "after" to run a function after n seconds
+        // Provide a function "after" to run a function after n milliseconds
         let task_manager_clone = Arc::clone(&task_manager);
-        let after = lua.create_function(move |_, args: (isize, String)| {
-            let (delay, target) = args;
-            task_manager_clone
-                .lock()
-                .unwrap()
-                .attach(delay, target, false);
-            Ok(())
+        let after = lua.create_function(move |lua, (delay, target): (u64, LuaValue)| {
+            schedule(lua, &task_manager_clone, delay, target, false)
         })?;
         lua.globals().set("after", after)?;
 
-        // Provide a function "every" to run a function every n seconds
+        // Provide a function "every" to run a function every n milliseconds
         let task_manager_clone = Arc::clone(&task_manager);
-        let every = lua.create_function(move |_, args: (isize, String)| {
-            let (delay, target) = args;
-            task_manager_clone
-                .lock()
-                .unwrap()
-                .attach(delay, target, true);
-            Ok(())
+        let every = lua.create_function(move |lua, (delay, target): (u64, LuaValue)| {
+            schedule(lua, &task_manager_clone, delay, target, true)
         })?;
         lua.globals().set("every", every)?;
 
+        // Provide the native networking library
+        http::register(lua, &task_manager)?;
+
+        // Provide the background process library
+        jobs::register(lua, &task_manager)?;
+
         Ok(Config {
             syntax_highlighting,
             line_numbers,
@@ -190,12 +251,15 @@ impl Config {
             file_tree,
             terminal,
             document,
+            keymap,
+            session,
             task_manager,
         })
     }
 
     /// Actually take the configuration file, open it and interpret it
-    pub fn read(path: &str, lua: &Lua) -> Result<()> {
+    /// (along with the configuration of the project, if it has been trusted)
+    pub fn read(path: &str, project: Option<&str>, lua: &Lua) -> Result<()> {
         // Load the default config to start with
         lua.load(DEFAULT_CONFIG).exec()?;
 
@@ -211,6 +275,13 @@ impl Config {
             user_provided_config = true;
         }
 
+        // Layer the project's configuration on top (as long as it hasn't changed since it was trusted)
+        if let Some(project) = project {
+            if let Some(config) = read_trusted(std::path::Path::new(project)) {
+                lua.load(config).set_name(format!("@{project}")).exec()?;
+            }
+        }
+
         // Determine whether or not to load built-in plugins
         let builtins: Vec<(&str, &str)> = vec![
             ("autoindent.lua", AUTOINDENT),
@@ -275,7 +346,7 @@ impl Config {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Indentation {
     Tabs,
     Spaces,
@@ -325,6 +396,70 @@ impl Default for Document {
     }
 }
 
+impl Document {
+    /// The settings a document gets when nothing more specific applies to it
+    pub fn settings(&self) -> DocumentSettings {
+        DocumentSettings {
+            tab_width: self.tab_width,
+            indent_size: self.tab_width,
+            indentation: self.indentation,
+            wrap_cursor: self.wrap_cursor,
+            undo_period: self.undo_period,
+            end_of_line: None,
+            charset: None,
+            trim_trailing_whitespace: false,
+            insert_final_newline: None,
+            max_line_length: None,
+        }
+    }
+}
+
+/// The settings that apply to a particular document
+/// (worked out from the configuration and any `.editorconfig` files)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSettings {
+    /// How wide a tab character is displayed
+    pub tab_width: usize,
+    /// How many spaces make up an indent (when indenting with spaces)
+    pub indent_size: usize,
+    pub indentation: Indentation,
+    pub wrap_cursor: bool,
+    /// How many seconds of inactivity split up undo points
+    pub undo_period: usize,
+    /// The line ending to save with: "lf", "crlf" or "cr" (None leaves line endings alone)
+    pub end_of_line: Option<String>,
+    /// The character set to save with, such as "utf-8" or "utf-8-bom"
+    pub charset: Option<String>,
+    pub trim_trailing_whitespace: bool,
+    /// Whether the file should end with a line ending
+    pub insert_final_newline: Option<bool>,
+    /// The length lines are meant to be kept under
+    pub max_line_length: Option<usize>,
+}
+
+impl Default for DocumentSettings {
+    fn default() -> Self {
+        Document::default().settings()
+    }
+}
+
+impl IntoLua for DocumentSettings {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let table = lua.create_table()?;
+        table.set("tab_width", self.tab_width)?;
+        table.set("indent_size", self.indent_size)?;
+        table.set("indentation", self.indentation.to_string())?;
+        table.set("wrap_cursor", self.wrap_cursor)?;
+        table.set("undo_period", self.undo_period)?;
+        table.set("end_of_line", self.end_of_line)?;
+        table.set("charset", self.charset)?;
+        table.set("trim_trailing_whitespace", self.trim_trailing_whitespace)?;
+        table.set("insert_final_newline", self.insert_final_newline)?;
+        table.set("max_line_length", self.max_line_length)?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
 impl LuaUserData for Document {
     fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
         fields.add_field_method_get("tab_width", |_, document| Ok(document.tab_width));
@@ -352,6 +487,27 @@ impl LuaUserData for Document {
     }
 }
 
+impl FromLua for FileTypeSettings {
+    fn from_lua(value: LuaValue, _: &Lua) -> std::result::Result<Self, LuaError> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::Table(settings) => Ok(Self {
+                tab_width: settings.get("tab_width")?,
+                indentation: settings
+                    .get::<Option<String>>("indentation")?
+                    .map(Indentation::from),
+                wrap_cursor: settings.get("wrap_cursor")?,
+                undo_period: settings.get("undo_period")?,
+            }),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "FileTypeSettings".to_string(),
+                message: Some("expected a table".to_string()),
+            }),
+        }
+    }
+}
+
 impl FromLua for FileTypes {
     fn from_lua(value: LuaValue, lua: &Lua) -> std::result::Result<Self, LuaError> {
         let mut result = vec![];
@@ -378,6 +534,14 @@ impl FromLua for FileTypes {
                     .filter_map(|val| if let Ok((_, v)) = val { Some(v) } else { None })
                     .collect::<Vec<String>>();
                 let color = info.get::<String>("color")?;
+                // A bad settings table shouldn't take the other file types down with it
+                let settings = FileTypeSettings::from_lua(info.get("settings")?, lua)
+                    .unwrap_or_else(|err| {
+                        issue_warning(&format!(
+                            "Ignoring invalid settings for the {name} file type: {err}"
+                        ));
+                        FileTypeSettings::default()
+                    });
                 result.push(FileType {
                     name,
                     icon,
@@ -385,9 +549,181 @@ impl FromLua for FileTypes {
                     extensions,
                     modelines,
                     color,
+                    settings,
                 });
             }
         }
         Ok(Self { types: result })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_timers_keep_using_seconds() {
+        let lua = Lua::new();
+        let tm = Arc::new(Mutex::new(TaskManager::default()));
+        let name = LuaValue::String(lua.create_string("refresh").unwrap());
+        schedule(&lua, &tm, 2, name, true).unwrap();
+        let due = tm.lock().unwrap().time_until_due().unwrap();
+        assert!(due > Duration::from_secs(1), "due in {due:?}");
+        let func = LuaValue::Function(lua.create_function(|_, ()| Ok(())).unwrap());
+        schedule(&lua, &tm, 2, func, true).unwrap();
+        let due = tm.lock().unwrap().time_until_due().unwrap();
+        assert!(due <= Duration::from_millis(2), "due in {due:?}");
+    }
+
+    /// Load plug-ins through the sandbox (given as name and code), with some capabilities
+    /// granted beforehand, giving back the lua state and the reason each failed plug-in failed
+    fn sandboxed(plugins: &[(&str, &str)], grants: &str) -> (Lua, Vec<(String, String)>) {
+        let dir = std::env::temp_dir().join(format!("ox-sandbox-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("grants.json"), "").unwrap();
+        let lua = Lua::new();
+        let globals = lua.globals();
+        let dir = dir.to_string_lossy().replace('\\', "/");
+        globals.set("dir", dir.clone()).unwrap();
+        let mut paths = vec![];
+        for (name, code) in plugins {
+            let path = format!("{dir}/{name}.lua");
+            std::fs::write(&path, code).unwrap();
+            paths.push(path);
+        }
+        globals.set("plugins", paths).unwrap();
+        lua.load(format!(
+            r#"
+            headless, home, path_sep, plugin_path = true, dir, "/", dir
+            json = {{ decode = function() return {grants} end, encode = function() end }}
+            editor = {{ cwd = dir, get_line = function() return "line" end }}
+            function editor:insert(text) self.inserted = text end
+            keymap, shell, jobs, python_interop, http = {{}}, {{}}, {{}}, {{}}, {{}}
+            function shell:run() return 0 end
+            function file_exists() return true end
+            after, every = function() end, function() end
+            event_mapping, command_completers, tasks = {{}}, {{}}, {{}}
+            commands = {{ save = function() end }}
+            file_types = {{ Rust = {{ extensions = {{ "rs" }} }} }}
+            runner = {{ rust = {{ run = "cargo run" }} }}
+            "#
+        ))
+        .exec()
+        .unwrap();
+        let failures = match lua.load(PLUGIN_RUN).exec() {
+            Ok(()) => vec![],
+            Err(LuaError::RuntimeError(msg)) => msg
+                .lines()
+                .filter_map(|line| {
+                    let (name, reason) = line.strip_prefix("plug-in '")?.split_once('\'')?;
+                    Some((name.to_string(), reason.to_string()))
+                })
+                .collect(),
+            Err(err) => panic!("{err}"),
+        };
+        (lua, failures)
+    }
+
+    #[test]
+    fn plugins_need_the_capabilities_they_use() {
+        let (lua, failures) = sandboxed(
+            &[
+                ("undeclared", "shell:run('true')"),
+                (
+                    "declared",
+                    "capabilities { process = true }\nshell:run('true')",
+                ),
+                ("reads", "assert(editor:get_line() == 'line')"),
+                ("mutates", "editor:insert('x')"),
+                (
+                    "editing",
+                    "capabilities { editor = true }\neditor:insert('hello')",
+                ),
+            ],
+            "{ declared = { process = true }, editing = { editor = true } }",
+        );
+        let reason = |name: &str| failures.iter().find(|(n, _)| n == name).map(|(_, r)| r);
+        assert_eq!(failures.len(), 2, "{failures:?}");
+        assert!(reason("undeclared")
+            .unwrap()
+            .contains("didn't declare the 'process'"));
+        assert!(reason("mutates")
+            .unwrap()
+            .contains("didn't declare the 'editor'"));
+        // (proxied methods are still given the real editor)
+        let inserted: String = lua.load("editor.inserted").eval().unwrap();
+        assert_eq!(inserted, "hello");
+    }
+
+    #[test]
+    fn plugins_only_reach_the_files_they_were_allowed() {
+        let (_, failures) = sandboxed(
+            &[
+                (
+                    "inside",
+                    "capabilities { fs = { dir } }\nfile_exists(dir .. '/x')",
+                ),
+                (
+                    "climbing",
+                    "capabilities { fs = { dir } }\nfile_exists(dir .. '/../x')",
+                ),
+                (
+                    "outside",
+                    "capabilities { fs = { dir } }\nfile_exists('/etc/passwd')",
+                ),
+                (
+                    "denied",
+                    "capabilities { fs = { dir } }\nfile_exists(dir .. '/x')",
+                ),
+            ],
+            "{ inside = { fs = { [dir] = true } }, climbing = { fs = { [dir] = true } }, \
+             outside = { fs = { [dir] = true } } }",
+        );
+        let reason = |name: &str| failures.iter().find(|(n, _)| n == name).map(|(_, r)| r);
+        assert_eq!(failures.len(), 3, "{failures:?}");
+        assert!(reason("climbing")
+            .unwrap()
+            .contains("didn't declare access to"));
+        assert!(reason("outside")
+            .unwrap()
+            .contains("didn't declare access to"));
+        assert!(reason("denied")
+            .unwrap()
+            .contains("wasn't allowed to access files in"));
+    }
+
+    #[test]
+    fn plugins_can_only_add_to_editor_tables() {
+        let (lua, failures) = sandboxed(
+            &[
+                ("runs", "runner.rust.run = 'true'"),
+                ("tasks", "tasks.build = { run = 'true' }"),
+                ("replaces", "commands.save = function() end"),
+                ("hooks", "global_event_mapping.ctrl_s = {}"),
+                (
+                    "adds",
+                    "commands.hello = function() end\nfile_types.Todo = { extensions = {} }\n\
+                     for name, ft in pairs(file_types) do assert(ft.extensions, name) end",
+                ),
+                (
+                    "allowed",
+                    "capabilities { process = true }\ntasks.build = { run = 'true' }",
+                ),
+            ],
+            "{ allowed = { process = true } }",
+        );
+        let reason = |name: &str| failures.iter().find(|(n, _)| n == name).map(|(_, r)| r);
+        assert_eq!(failures.len(), 4, "{failures:?}");
+        for name in ["runs", "tasks"] {
+            assert!(reason(name).unwrap().contains("'process'"), "{name}");
+        }
+        for name in ["replaces", "hooks"] {
+            assert!(reason(name).unwrap().contains("'editor'"), "{name}");
+        }
+        let changed: (String, bool, bool, bool) = lua
+            .load("runner.rust.run, commands.hello ~= nil, file_types.Todo ~= nil, tasks.build ~= nil")
+            .eval()
+            .unwrap();
+        assert_eq!(changed, ("cargo run".to_string(), true, true, true));
+    }
+}
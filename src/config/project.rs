@@ -0,0 +1,198 @@
+/// Configuration provided by projects, and keeping track of which project files the user trusts
+use crate::config::{config_dir, project_root};
+use crate::gets;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Where a project can keep its configuration file (relative to the project folder)
+const CONFIG_FILES: [&str; 2] = [".ox.lua", ".ox/config.lua"];
+
+/// Find the configuration file of the project a folder belongs to (looking in parent folders too)
+pub fn find_project_config(dir: &str) -> Option<PathBuf> {
+    Path::new(dir).ancestors().find_map(|dir| {
+        CONFIG_FILES
+            .iter()
+            .map(|file| dir.join(file))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Find the tasks file of the project a folder belongs to
+pub fn find_project_tasks(dir: &str) -> Option<PathBuf> {
+    let path = Path::new(&project_root(dir)?).join(".ox").join("tasks.lua");
+    path.is_file().then_some(path)
+}
+
+/// Work out where the list of trusted files is kept
+fn trust_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("trusted.json"))
+}
+
+/// Read the list of trusted files (path => checksum of the contents that were trusted)
+fn trusted(store: Option<&Path>) -> Value {
+    store
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_else(|| Value::Object(serde_json::Map::new()))
+}
+
+/// Identify a file by its path and contents
+fn fingerprint(path: &Path, contents: &[u8]) -> Option<(String, String)> {
+    let path = std::fs::canonicalize(path).ok()?;
+    let digest = Sha256::digest(contents);
+    let hex = digest.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    });
+    Some((path.to_string_lossy().to_string(), format!("sha256:{hex}")))
+}
+
+/// Read a project lua file, as long as the user trusts it
+/// (a file that has changed since it was trusted isn't trusted any more, and the contents given
+/// back are the ones that were checked, so they can't change before they are run)
+pub fn read_trusted(path: &Path) -> Option<Vec<u8>> {
+    read_trusted_in(trust_path().as_deref(), path)
+}
+
+/// Read a project lua file, as long as it is trusted in a list of trusted files
+fn read_trusted_in(store: Option<&Path>, path: &Path) -> Option<Vec<u8>> {
+    let contents = std::fs::read(path).ok()?;
+    let (path, checksum) = fingerprint(path, &contents)?;
+    (trusted(store)[path] == checksum).then_some(contents)
+}
+
+/// Determine if the user trusts a project lua file
+pub fn is_trusted(path: &Path) -> bool {
+    read_trusted(path).is_some()
+}
+
+/// Trust a project lua file as it is now
+pub fn trust(path: &Path) -> std::io::Result<()> {
+    let Some(store) = trust_path() else {
+        return Ok(());
+    };
+    trust_in(&store, path)
+}
+
+/// Add a project lua file, as it is now, to a list of trusted files
+fn trust_in(store: &Path, path: &Path) -> std::io::Result<()> {
+    let contents = std::fs::read(path)?;
+    let Some((path, checksum)) = fingerprint(path, &contents) else {
+        return Ok(());
+    };
+    let mut trusted = trusted(Some(store));
+    trusted[path] = Value::String(checksum);
+    if let Some(dir) = store.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(store, trusted.to_string())
+}
+
+/// Find the configuration of the project ox is starting in, asking the user before any lua it
+/// provides is trusted (returns the configuration file if it can be used)
+pub fn startup_project_config(dir: &str, can_ask: bool) -> Option<String> {
+    startup_project_config_in(trust_path().as_deref(), dir, can_ask)
+}
+
+/// Find the configuration of the project ox is starting in, using a list of trusted files
+fn startup_project_config_in(store: Option<&Path>, dir: &str, can_ask: bool) -> Option<String> {
+    let is_trusted = |path: &Path| read_trusted_in(store, path).is_some();
+    let config = find_project_config(dir);
+    let untrusted: Vec<PathBuf> = config
+        .iter()
+        .cloned()
+        .chain(find_project_tasks(dir))
+        .filter(|path| !is_trusted(path))
+        .collect();
+    if !untrusted.is_empty() {
+        let list = untrusted
+            .iter()
+            .map(|path| format!("  {}", path.display()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if can_ask {
+            println!("This project provides lua code that ox would run:\n{list}");
+            println!("Only trust it if you know where it came from.");
+            if gets!("Do you trust it? (y/n) ")
+                .trim()
+                .eq_ignore_ascii_case("y")
+            {
+                for path in &untrusted {
+                    let result = store.map_or(Ok(()), |store| trust_in(store, path));
+                    if let Err(err) = result {
+                        eprintln!("[Error] Failed to trust '{}': {err}", path.display());
+                    }
+                }
+            }
+        } else {
+            eprintln!("[Warning] Ignoring project lua code that hasn't been trusted:\n{list}");
+        }
+    }
+    config
+        .filter(|path| is_trusted(path))
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Make a folder to hold a project (and the list of trusted files)
+    fn folder(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ox-project-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn changed_files_are_no_longer_trusted() {
+        let dir = folder("trust");
+        let store = dir.join("trusted.json");
+        let file = dir.join(".ox.lua");
+        std::fs::write(&file, "print('hello')").unwrap();
+        let before = read_trusted_in(Some(&store), &file);
+        trust_in(&store, &file).unwrap();
+        let trusted = read_trusted_in(Some(&store), &file);
+        std::fs::write(&file, "os.execute('rm -rf ~')").unwrap();
+        let changed = read_trusted_in(Some(&store), &file);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(before, None);
+        assert_eq!(trusted, Some(b"print('hello')".to_vec()));
+        assert_eq!(changed, None);
+    }
+
+    #[test]
+    fn project_configs_are_found_in_parent_folders() {
+        let dir = folder("ancestors");
+        let nested = dir.join("src").join("editor");
+        std::fs::create_dir_all(&nested).unwrap();
+        let before = find_project_config(&nested.to_string_lossy());
+        std::fs::create_dir_all(dir.join(".ox")).unwrap();
+        std::fs::write(dir.join(".ox").join("config.lua"), "").unwrap();
+        let nested_config = find_project_config(&nested.to_string_lossy());
+        // (a configuration file closer to the folder wins)
+        std::fs::write(dir.join("src").join(".ox.lua"), "").unwrap();
+        let closer_config = find_project_config(&nested.to_string_lossy());
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(before, None);
+        assert_eq!(nested_config, Some(dir.join(".ox").join("config.lua")));
+        assert_eq!(closer_config, Some(dir.join("src").join(".ox.lua")));
+    }
+
+    #[test]
+    fn untrusted_code_is_ignored_when_nobody_can_be_asked() {
+        let dir = folder("startup");
+        let store = dir.join("trusted.json");
+        let file = dir.join(".ox.lua");
+        std::fs::write(&file, "print('hello')").unwrap();
+        let project = dir.to_string_lossy().to_string();
+        let untrusted = startup_project_config_in(Some(&store), &project, false);
+        trust_in(&store, &file).unwrap();
+        let trusted = startup_project_config_in(Some(&store), &project, false);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(untrusted, None);
+        assert_eq!(trusted, Some(file.to_string_lossy().to_string()));
+    }
+}
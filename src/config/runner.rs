@@ -1,6 +1,10 @@
 //! Configuration for defining how programs should be compiled and run
 
+use crate::config::read_trusted;
+use crate::error::{OxError, Result};
 use mlua::prelude::*;
+use std::collections::BTreeMap;
+use std::path::Path;
 
 /// Main struct to determine how a language should be compiled / run
 #[derive(Debug, Default)]
@@ -22,3 +26,184 @@ impl FromLua for RunCommand {
         }
     }
 }
+
+/// A named task defined by a project (e.g. within `.ox/tasks.lua`)
+#[derive(Debug, Default, Clone)]
+pub struct ProjectTask {
+    pub command: String,
+    pub cwd: Option<String>,
+    pub env: Vec<(String, String)>,
+    pub depends: Vec<String>,
+    pub background: bool,
+}
+
+impl FromLua for ProjectTask {
+    fn from_lua(val: LuaValue, _: &Lua) -> LuaResult<Self> {
+        match val {
+            // A plain string is shorthand for a task with just a command
+            LuaValue::String(command) => Ok(Self {
+                command: command.to_string_lossy(),
+                ..Self::default()
+            }),
+            LuaValue::Table(table) => {
+                let mut env = vec![];
+                if let Some(vars) = table.get::<Option<LuaTable>>("env")? {
+                    for pair in vars.pairs::<String, String>() {
+                        env.push(pair?);
+                    }
+                }
+                env.sort();
+                Ok(Self {
+                    command: table.get::<Option<String>>("command")?.unwrap_or_default(),
+                    cwd: table.get("cwd")?,
+                    env,
+                    depends: table
+                        .get::<Option<Vec<String>>>("depends")?
+                        .unwrap_or_default(),
+                    background: table.get::<Option<bool>>("background")?.unwrap_or(false),
+                })
+            }
+            _ => Err(LuaError::external("a task must be a string or a table")),
+        }
+    }
+}
+
+/// Values that can be substituted into task commands and working directories
+#[derive(Debug, Default)]
+pub struct TaskVariables {
+    pub file_path: String,
+    pub file_dir: String,
+    pub line: usize,
+    pub selection: String,
+    pub project_root: String,
+}
+
+impl TaskVariables {
+    /// Replace any variables within a string with their values
+    pub fn substitute(&self, text: &str) -> String {
+        self.substitute_with(text, str::to_string)
+    }
+
+    /// Replace any variables within a shell command with their values
+    /// (each value is quoted, so file names and selections can't run commands of their own)
+    pub fn substitute_quoted(&self, text: &str) -> String {
+        self.substitute_with(text, shell_quote)
+    }
+
+    /// Replace any variables within a string with their values, after transforming them
+    fn substitute_with(&self, text: &str, transform: fn(&str) -> String) -> String {
+        text.replace("{file_path}", &transform(&self.file_path))
+            .replace("{file_dir}", &transform(&self.file_dir))
+            .replace("{line}", &self.line.to_string())
+            .replace("{selection}", &transform(&self.selection))
+            .replace("{project_root}", &transform(&self.project_root))
+    }
+}
+
+/// Quote a string so that it is passed to the shell as-is
+pub fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Find the root of the project a directory belongs to
+/// (the closest ancestor containing an `.ox` or `.git` folder)
+pub fn project_root(dir: &str) -> Option<String> {
+    Path::new(dir)
+        .ancestors()
+        .find(|p| p.join(".ox").is_dir() || p.join(".git").exists())
+        .map(|p| p.to_string_lossy().to_string())
+}
+
+/// Obtain all the tasks available, combining the `tasks` table from the configuration
+/// file with those defined in the project's `.ox/tasks.lua` file
+pub fn load_tasks(lua: &Lua, root: Option<&str>) -> LuaResult<BTreeMap<String, ProjectTask>> {
+    let mut result = BTreeMap::new();
+    if let Ok(tasks) = lua.globals().get::<LuaTable>("tasks") {
+        for pair in tasks.pairs::<String, ProjectTask>() {
+            let (name, task) = pair?;
+            result.insert(name, task);
+        }
+    }
+    if let Some(root) = root {
+        let path = Path::new(root).join(".ox").join("tasks.lua");
+        // (project lua code only runs once the user has trusted it)
+        if let Some(code) = read_trusted(&path) {
+            let name = path.to_string_lossy().to_string();
+            let tasks: LuaTable = lua.load(code).set_name(name).eval()?;
+            for pair in tasks.pairs::<String, ProjectTask>() {
+                let (name, task) = pair?;
+                result.insert(name, task);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Work out which tasks need to run (and in what order) to run the task provided,
+/// dependencies come first and each task will only appear once
+pub fn task_order(tasks: &BTreeMap<String, ProjectTask>, name: &str) -> Result<Vec<String>> {
+    fn visit(
+        tasks: &BTreeMap<String, ProjectTask>,
+        name: &str,
+        visiting: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|n| n == name) {
+            return Ok(());
+        }
+        if visiting.iter().any(|n| n == name) {
+            let msg = format!("Task '{name}' depends on itself");
+            return Err(OxError::Config { msg });
+        }
+        let Some(task) = tasks.get(name) else {
+            let msg = format!("No task named '{name}'");
+            return Err(OxError::Config { msg });
+        };
+        visiting.push(name.to_string());
+        for dependency in &task.depends {
+            visit(tasks, dependency, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(name.to_string());
+        Ok(())
+    }
+    let mut order = vec![];
+    visit(tasks, name, &mut vec![], &mut order)?;
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn substituted_values_reach_the_shell_as_they_are() {
+        let vars = TaskVariables {
+            file_path: "/tmp/it's a file; echo oops".to_string(),
+            selection: "$(echo oops) \"quoted\" \\ `oops`".to_string(),
+            line: 3,
+            ..TaskVariables::default()
+        };
+        let command = vars.substitute_quoted("printf '%s|' {file_path} {selection} {line}");
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("{}|{}|3|", vars.file_path, vars.selection)
+        );
+    }
+
+    #[test]
+    fn substitution_outside_of_commands_is_unquoted() {
+        let vars = TaskVariables {
+            file_dir: "/tmp/a dir".to_string(),
+            ..TaskVariables::default()
+        };
+        assert_eq!(vars.substitute("{file_dir}/build"), "/tmp/a dir/build");
+        assert_eq!(vars.substitute_quoted("cd {file_dir}"), "cd '/tmp/a dir'");
+    }
+}
@@ -0,0 +1,34 @@
+/// Related to saving and restoring sessions
+use mlua::prelude::*;
+
+#[derive(Debug)]
+pub struct Session {
+    /// Pick up where you left off when ox is started without any files
+    pub restore: bool,
+    /// Remember the layout of the editor when it is closed
+    pub save: bool,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            restore: true,
+            save: true,
+        }
+    }
+}
+
+impl LuaUserData for Session {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("restore", |_, this| Ok(this.restore));
+        fields.add_field_method_set("restore", |_, this, value| {
+            this.restore = value;
+            Ok(())
+        });
+        fields.add_field_method_get("save", |_, this| Ok(this.save));
+        fields.add_field_method_set("save", |_, this, value| {
+            this.save = value;
+            Ok(())
+        });
+    }
+}
@@ -1,11 +1,59 @@
 /// For dealing with tasks (part of the plug-in concurrency API)
+use crate::config::http::HttpResponse;
+use mlua::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct Task {
+    id: usize,
     repeat: bool,
-    delay: isize,
-    remaining: isize,
-    target: String,
+    delay: Duration,
+    due: Instant,
+    target: TaskTarget,
+}
+
+/// What to run when a task is due
+#[derive(Debug)]
+pub enum TaskTarget {
+    /// The name of a global function
+    Name(String),
+    /// The id of a registered callback
+    Callback(usize),
+}
+
+/// Results of background work, to be handed to lua callbacks on the main thread
+#[derive(Debug)]
+pub enum Completion {
+    Http(HttpResponse),
+    JobStdout(String),
+    JobStderr(String),
+    JobExit(Option<i32>),
+    Timer(bool),
+}
+
+impl Completion {
+    /// Whether the callback will no longer be needed after receiving this
+    pub fn is_final(&self) -> bool {
+        match self {
+            Self::Http(_) | Self::JobExit(_) => true,
+            Self::JobStdout(_) | Self::JobStderr(_) => false,
+            Self::Timer(last) => *last,
+        }
+    }
+}
+
+impl IntoLuaMulti for Completion {
+    fn into_lua_multi(self, lua: &Lua) -> LuaResult<LuaMultiValue> {
+        match self {
+            Self::Http(response) => response.into_lua_multi(lua),
+            Self::JobStdout(line) => ("stdout", line).into_lua_multi(lua),
+            Self::JobStderr(line) => ("stderr", line).into_lua_multi(lua),
+            Self::JobExit(code) => ("exit", code).into_lua_multi(lua),
+            Self::Timer(_) => ().into_lua_multi(lua),
+        }
+    }
 }
 
 /// A struct in charge of executing functions concurrently
@@ -13,29 +61,46 @@ pub struct Task {
 pub struct TaskManager {
     pub tasks: Vec<Task>,
     pub to_execute: Vec<String>,
+    pub callbacks: HashMap<usize, LuaRegistryKey>,
+    pub completed: Vec<(usize, Completion)>,
+    /// Callbacks that were let go of while lua wasn't to hand (see `free_released`)
+    released: Vec<LuaRegistryKey>,
+    next_callback: usize,
+    next_task: usize,
 }
 
 impl TaskManager {
-    /// Thread to run and keep track of which tasks to execute
+    /// Find any tasks that are due and queue them up for execution
     pub fn cycle(&mut self) {
+        let now = Instant::now();
+        let mut finished = vec![];
         for task in &mut self.tasks {
-            // Decrement remaining time
-            if task.remaining > 0 {
-                task.remaining = task.remaining.saturating_sub(1);
-            }
             // Check if activation is required
-            if task.remaining == 0 {
-                self.to_execute.push(task.target.clone());
-                // Check whether to repeat or not
-                if task.repeat {
-                    // Re-load the task
-                    task.remaining = task.delay;
-                } else {
-                    // Condemn the task to decrementing forever
-                    task.remaining = -1;
-                }
+            if task.due > now {
+                continue;
+            }
+            // Check whether to repeat or not
+            let last = !task.repeat;
+            if task.repeat {
+                // Re-load the task
+                task.due = now + task.delay;
+            } else {
+                finished.push(task.id);
+            }
+            match &task.target {
+                TaskTarget::Name(name) => self.to_execute.push(name.clone()),
+                TaskTarget::Callback(id) => self.completed.push((*id, Completion::Timer(last))),
             }
         }
+        self.tasks.retain(|task| !finished.contains(&task.id));
+    }
+
+    /// Work out how long until the next task is due
+    pub fn time_until_due(&self) -> Option<Duration> {
+        self.tasks
+            .iter()
+            .map(|task| task.due.saturating_duration_since(Instant::now()))
+            .min()
     }
 
     /// Obtain a list of functions to execute (and remove them from the execution list)
@@ -45,13 +110,93 @@ impl TaskManager {
         new
     }
 
-    /// Define a new task
-    pub fn attach(&mut self, delay: isize, target: String, repeat: bool) {
+    /// Define a new task, returning an id that can be used to cancel it
+    pub fn attach(&mut self, delay: Duration, target: TaskTarget, repeat: bool) -> usize {
+        self.next_task += 1;
         self.tasks.push(Task {
-            remaining: delay,
+            id: self.next_task,
+            due: Instant::now() + delay,
             delay,
             target,
             repeat,
         });
+        self.next_task
+    }
+
+    /// Stop a task from running (returns true if the task was still active)
+    pub fn cancel(&mut self, lua: &Lua, id: usize) -> bool {
+        let Some(idx) = self.tasks.iter().position(|task| task.id == id) else {
+            return false;
+        };
+        let task = self.tasks.remove(idx);
+        if let TaskTarget::Callback(callback) = task.target {
+            self.remove_callback(lua, callback);
+        }
+        true
+    }
+
+    /// Determine whether a task is still waiting to run
+    pub fn is_active(&self, id: usize) -> bool {
+        self.tasks.iter().any(|task| task.id == id)
+    }
+
+    /// Store a lua callback, returning an id that background work can refer to it by
+    pub fn register_callback(&mut self, callback: LuaRegistryKey) -> usize {
+        self.next_callback += 1;
+        self.callbacks.insert(self.next_callback, callback);
+        self.next_callback
+    }
+
+    /// Remove a callback, freeing the lua function it refers to
+    pub fn remove_callback(&mut self, lua: &Lua, id: usize) {
+        if let Some(key) = self.callbacks.remove(&id) {
+            let _ = lua.remove_registry_value(key);
+        }
+    }
+
+    /// Remove a callback without lua to hand
+    /// (the lua function it refers to is freed the next time `free_released` is called)
+    pub fn release_callback(&mut self, id: usize) {
+        if let Some(key) = self.callbacks.remove(&id) {
+            self.released.push(key);
+        }
+    }
+
+    /// Free the lua functions of callbacks that were released
+    pub fn free_released(&mut self, lua: &Lua) {
+        for key in std::mem::take(&mut self.released) {
+            let _ = lua.remove_registry_value(key);
+        }
+    }
+
+    /// Called by background work once a result is ready for a callback
+    pub fn complete(&mut self, id: usize, result: Completion) {
+        self.completed.push((id, result));
+    }
+
+    /// Obtain a list of results to hand to callbacks (and remove them from the list)
+    pub fn completion_list(&mut self) -> Vec<(usize, Completion)> {
+        std::mem::take(&mut self.completed)
+    }
+}
+
+/// A handle given to plug-ins to keep track of (and cancel) a timer
+#[derive(Debug)]
+pub struct TimerHandle {
+    pub id: usize,
+    pub task_manager: Arc<Mutex<TaskManager>>,
+}
+
+impl LuaUserData for TimerHandle {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("active", |_, handle| {
+            Ok(handle.task_manager.lock().unwrap().is_active(handle.id))
+        });
+    }
+
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("cancel", |lua, handle, ()| {
+            Ok(handle.task_manager.lock().unwrap().cancel(lua, handle.id))
+        });
     }
 }
@@ -0,0 +1,242 @@
+/// Tools for plug-ins to work with any open buffer (including scratch buffers they own)
+use crate::config;
+use crate::editor::{Editor, FileContainer, FileLayout};
+use crate::error::Result;
+use crate::ui::Feedback;
+use kaolinite::Loc;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Information about a scratch buffer, which is owned by a plug-in rather than a file on the disk
+#[derive(Debug, Clone, Default)]
+pub struct Scratch {
+    /// The name to display in place of a file name
+    pub name: String,
+    /// Key bindings that only apply within this buffer (key => task manager callback id)
+    pub keymaps: HashMap<String, usize>,
+    /// A keymap context that is active while this buffer is focused
+    pub context: Option<String>,
+}
+
+impl FileLayout {
+    /// Find a file container location from its id
+    pub fn find_buffer(&self, idx: Vec<usize>, id: usize) -> Option<(Vec<usize>, usize)> {
+        match self {
+            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::Atom(containers, _) => containers
+                .iter()
+                .position(|fc| fc.id == id)
+                .map(|ptr| (idx, ptr)),
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
+                layouts.iter().enumerate().find_map(|(nth, (layout, _))| {
+                    let mut this_idx = idx.clone();
+                    this_idx.push(nth);
+                    layout.find_buffer(this_idx, id)
+                })
+            }
+        }
+    }
+
+    /// Get the ids of every file container in this layout
+    pub fn buffer_ids(&self) -> Vec<usize> {
+        match self {
+            Self::None | Self::FileTree | Self::Terminal(_) => vec![],
+            Self::Atom(containers, _) => containers.iter().map(|fc| fc.id).collect(),
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => layouts
+                .iter()
+                .flat_map(|(layout, _)| layout.buffer_ids())
+                .collect(),
+        }
+    }
+}
+
+impl Editor {
+    /// Obtain a new id for a file container
+    pub fn new_buffer_id(&mut self) -> usize {
+        self.next_buffer_id += 1;
+        self.next_buffer_id
+    }
+
+    /// Get hold of a buffer from its id
+    pub fn buffer_mut(&mut self, id: usize) -> Option<&mut FileContainer> {
+        let (idx, ptr) = self.files.find_buffer(vec![], id)?;
+        self.files
+            .get_atom_mut(idx)
+            .and_then(|(fcs, _)| fcs.get_mut(ptr))
+    }
+
+    /// Run an action on a buffer as though it were focused, without the user seeing focus change
+    /// (returns None if the buffer doesn't exist)
+    pub fn with_buffer<T>(&mut self, id: usize, action: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        let (idx, ptr) = self.files.find_buffer(vec![], id)?;
+        let (_, old_tab) = self.files.get_atom(idx.clone())?;
+        let old_ptr = std::mem::replace(&mut self.ptr, idx.clone());
+        self.files.move_to(idx.clone(), ptr);
+        let result = action(self);
+        self.files.move_to(idx, old_tab);
+        self.ptr = old_ptr;
+        self.needs_rerender = true;
+        Some(result)
+    }
+
+    /// Move focus to a buffer (returns false if it doesn't exist)
+    pub fn focus_buffer(&mut self, id: usize) -> bool {
+        let Some((idx, ptr)) = self.files.find_buffer(vec![], id) else {
+            return false;
+        };
+        self.ptr.clone_from(&idx);
+        self.files.move_to(idx, ptr);
+        self.update_cwd();
+        self.needs_rerender = true;
+        true
+    }
+
+    /// Close a buffer in the same way the user would (returns false if it doesn't exist)
+    pub fn close_buffer(&mut self, id: usize) -> Result<bool> {
+        let Some((idx, ptr)) = self.files.find_buffer(vec![], id) else {
+            return Ok(false);
+        };
+        // Work out where focus should return to afterwards
+        let old_ptr = self.ptr.clone();
+        let old_tab = self.files.get_atom(old_ptr.clone()).map(|(_, tab)| tab);
+        let last_file = self.files.get_atom(idx.clone()).map(|(fcs, _)| fcs.len()) == Some(1);
+        self.ptr.clone_from(&idx);
+        self.files.move_to(idx.clone(), ptr);
+        self.quit()?;
+        // The user may have decided not to close it after all
+        if self.files.find_buffer(vec![], id).is_some() {
+            self.ptr = old_ptr;
+            if let Some(tab) = old_tab {
+                self.files.move_to(self.ptr.clone(), tab);
+            }
+            return Ok(false);
+        }
+        // Closing the last buffer in a split rearranges the layout, so focus can't be restored
+        if let (Some(tab), false) = (old_tab, last_file) {
+            let tab = match (old_ptr == idx, tab.cmp(&ptr)) {
+                (false, _) | (true, Ordering::Less) => Some(tab),
+                (true, Ordering::Greater) => Some(tab - 1),
+                // The closed buffer itself had focus (quitting will have moved it along)
+                (true, Ordering::Equal) => None,
+            };
+            if let Some(tab) = tab {
+                self.ptr.clone_from(&old_ptr);
+                self.files.move_to(old_ptr, tab);
+            }
+        }
+        self.needs_rerender = true;
+        Ok(true)
+    }
+
+    /// Create a scratch buffer next to the current document, returning its id
+    pub fn create_scratch(
+        &mut self,
+        name: String,
+        read_only: bool,
+        file_type: Option<&str>,
+    ) -> Result<Option<usize>> {
+        self.blank()?;
+        let file_type =
+            file_type.and_then(|name| config!(self.config, document).file_types.get_name(name));
+        let highlighter = file_type.as_ref().map(|t| {
+            let tab_width = config!(self.config, document).tab_width;
+            t.get_highlighter(&self.config, tab_width)
+        });
+        // The blank document is placed just after the current one
+        let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) else {
+            return Ok(None);
+        };
+        let at = (*ptr + 1).min(files.len() - 1);
+        let file = &mut files[at];
+        file.doc.info.read_only = read_only;
+        if let Some(mut highlighter) = highlighter {
+            highlighter.run(&file.doc.lines);
+            file.highlighter = highlighter;
+        }
+        file.file_type = file_type;
+        file.scratch = Some(Scratch {
+            name,
+            ..Scratch::default()
+        });
+        self.needs_rerender = true;
+        Ok(Some(file.id))
+    }
+
+    /// Determine if the current document is a scratch buffer (letting the user know it can't be saved)
+    pub fn scratch_focused(&mut self) -> bool {
+        let focused = self
+            .files
+            .get(self.ptr.clone())
+            .is_some_and(|file| file.scratch.is_some());
+        if focused {
+            self.feedback = Feedback::Warning("Scratch buffers can't be saved".to_string());
+        }
+        focused
+    }
+
+    /// Insert text at a location in the current document (leaving the cursor where it was)
+    pub fn insert_at(&mut self, text: &str, loc: Loc) -> Result<()> {
+        let Some(location) = self.try_doc().map(kaolinite::Document::char_loc) else {
+            return Ok(());
+        };
+        self.plugin_active = true;
+        self.try_doc_mut().unwrap().move_to(&loc);
+        let result = text.chars().try_for_each(|ch| self.character(ch));
+        self.try_doc_mut().unwrap().move_to(&location);
+        self.update_highlighter();
+        self.plugin_active = false;
+        result
+    }
+
+    /// Remove the character at a location in the current document (leaving the cursor where it was)
+    pub fn remove_at(&mut self, loc: Loc) -> Result<()> {
+        let Some(location) = self.try_doc().map(kaolinite::Document::char_loc) else {
+            return Ok(());
+        };
+        self.plugin_active = true;
+        self.try_doc_mut().unwrap().move_to(&loc);
+        let result = self.delete();
+        self.try_doc_mut().unwrap().move_to(&location);
+        self.update_highlighter();
+        self.plugin_active = false;
+        result
+    }
+
+    /// Insert a line in the current document before line y (leaving the cursor where it was)
+    pub fn insert_line_at(&mut self, text: &str, y: usize) -> Result<()> {
+        let Some(location) = self.try_doc().map(kaolinite::Document::char_loc) else {
+            return Ok(());
+        };
+        self.plugin_active = true;
+        let doc = self.try_doc_mut().unwrap();
+        let result = if y < doc.len_lines() {
+            doc.move_to_y(y);
+            doc.move_home();
+            let result = self.enter();
+            self.up();
+            result
+        } else {
+            doc.move_bottom();
+            self.enter()
+        };
+        let result = result.and_then(|()| text.chars().try_for_each(|ch| self.character(ch)));
+        self.try_doc_mut().unwrap().move_to(&location);
+        self.update_highlighter();
+        self.plugin_active = false;
+        result
+    }
+
+    /// Remove line y from the current document (leaving the cursor where it was)
+    pub fn remove_line_at(&mut self, y: usize) -> Result<()> {
+        let Some(location) = self.try_doc().map(kaolinite::Document::char_loc) else {
+            return Ok(());
+        };
+        self.plugin_active = true;
+        self.try_doc_mut().unwrap().move_to_y(y);
+        let result = self.delete_line();
+        self.try_doc_mut().unwrap().move_to(&location);
+        self.update_highlighter();
+        self.plugin_active = false;
+        result
+    }
+}
@@ -1,6 +1,7 @@
 /// Functions for moving the cursor around
-use crate::{config, ged, handle_event, CEvent, Loc, Result};
+use crate::{ged, handle_event, CEvent, Result};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use kaolinite::document::Gravity;
 use kaolinite::event::Status;
 use mlua::{AnyUserData, Lua};
 
@@ -23,7 +24,7 @@ impl Editor {
 
     /// Move the cursor left
     pub fn select_left(&mut self) {
-        let wrapping = config!(self.config, document).wrap_cursor;
+        let wrapping = self.focused_settings().wrap_cursor;
         if let Some(doc) = self.try_doc_mut() {
             let status = doc.select_left();
             // Cursor wrapping if cursor hits the start of the line
@@ -36,7 +37,7 @@ impl Editor {
 
     /// Move the cursor right
     pub fn select_right(&mut self) {
-        let wrapping = config!(self.config, document).wrap_cursor;
+        let wrapping = self.focused_settings().wrap_cursor;
         if let Some(doc) = self.try_doc_mut() {
             let status = doc.select_right();
             // Cursor wrapping if cursor hits the end of a line
@@ -71,7 +72,7 @@ impl Editor {
 
     /// Move the cursor left
     pub fn left(&mut self) {
-        let wrapping = config!(self.config, document).wrap_cursor;
+        let wrapping = self.focused_settings().wrap_cursor;
         if let Some(doc) = self.try_doc_mut() {
             let status = doc.move_left();
             // Cursor wrapping if cursor hits the start of the line
@@ -84,7 +85,7 @@ impl Editor {
 
     /// Move the cursor right
     pub fn right(&mut self) {
-        let wrapping = config!(self.config, document).wrap_cursor;
+        let wrapping = self.focused_settings().wrap_cursor;
         if let Some(doc) = self.try_doc_mut() {
             let status = doc.move_right();
             // Cursor wrapping if cursor hits the end of a line
@@ -97,7 +98,7 @@ impl Editor {
 
     /// Move the cursor to the previous word in the line
     pub fn prev_word(&mut self) {
-        let wrapping = config!(self.config, document).wrap_cursor;
+        let wrapping = self.focused_settings().wrap_cursor;
         if let Some(doc) = self.try_doc_mut() {
             let status = doc.move_prev_word();
             if status == Status::StartOfLine && wrapping {
@@ -109,7 +110,7 @@ impl Editor {
 
     /// Move the cursor to the next word in the line
     pub fn next_word(&mut self) {
-        let wrapping = config!(self.config, document).wrap_cursor;
+        let wrapping = self.focused_settings().wrap_cursor;
         if let Some(doc) = self.try_doc_mut() {
             let status = doc.move_next_word();
             if status == Status::EndOfLine && wrapping {
@@ -121,124 +122,44 @@ impl Editor {
 }
 
 /// Handle multiple cursors (replay a key event for each of them)
-pub fn handle_multiple_cursors(
-    editor: &AnyUserData,
-    event: &CEvent,
-    lua: &Lua,
-    original_loc: &Loc,
-) -> Result<()> {
-    if ged!(&editor).try_doc().is_none() {
+/// (cursors are markers, so they move along with the edits made at the other cursors)
+pub fn handle_multiple_cursors(editor: &AnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
+    let Some((primary, secondary_cursors)) = ged!(mut &editor).try_doc_mut().map(|doc| {
+        let loc = doc.char_loc();
+        (
+            doc.add_marker(loc, Gravity::Right),
+            doc.secondary_cursors.clone(),
+        )
+    }) else {
         return Ok(());
-    }
-    let mut original_loc = *original_loc;
-    // Cache the state of the document
-    let mut cursor = ged!(&editor).try_doc().unwrap().cursor;
-    let mut secondary_cursors = ged!(&editor).try_doc().unwrap().secondary_cursors.clone();
+    };
     ged!(mut &editor).macro_man.playing = true;
-    // Prevent interference
-    adjust_other_cursors(
-        &mut secondary_cursors,
-        &original_loc.clone(),
-        &cursor.loc,
-        event,
-        &mut original_loc,
-    );
     // Update each secondary cursor
-    let mut ptr = 0;
-    while ptr < secondary_cursors.len() {
-        // Move to the secondary cursor position
-        let sec_cursor = secondary_cursors[ptr];
-        ged!(mut &editor)
-            .try_doc_mut()
-            .unwrap()
-            .move_to(&sec_cursor);
-        // Replay the event
-        let old_loc = ged!(&editor).try_doc().unwrap().char_loc();
-        handle_event(editor, event, lua)?;
-        // Prevent any interference
-        let char_loc = ged!(&editor).try_doc().unwrap().char_loc();
-        cursor.loc = adjust_other_cursors(
-            &mut secondary_cursors,
-            &old_loc,
-            &char_loc,
-            event,
-            &mut cursor.loc,
-        );
-        // Update the secondary cursor
-        *secondary_cursors.get_mut(ptr).unwrap() = char_loc;
-        // Move to the next secondary cursor
-        ptr += 1;
+    let mut result = Ok(());
+    for id in secondary_cursors {
+        let Some(loc) = ged!(&editor).try_doc().and_then(|doc| doc.marker_loc(id)) else {
+            continue;
+        };
+        ged!(mut &editor).try_doc_mut().unwrap().move_to(&loc);
+        // Replay the event, then put the secondary cursor where the event left it
+        result = handle_event(editor, event, lua);
+        if result.is_err() {
+            break;
+        }
+        if let Some(doc) = ged!(mut &editor).try_doc_mut() {
+            let loc = doc.char_loc();
+            doc.move_marker(id, loc);
+        }
     }
-    ged!(mut &editor).try_doc_mut().unwrap().secondary_cursors = secondary_cursors;
     ged!(mut &editor).macro_man.playing = false;
-    // Restore back to the state of the document beforehand
-    // TODO: calculate char_ptr and old_cursor too
-    ged!(mut &editor).try_doc_mut().unwrap().cursor = cursor;
-    let char_ptr = ged!(&editor).try_doc().unwrap().character_idx(&cursor.loc);
-    ged!(mut &editor).try_doc_mut().unwrap().char_ptr = char_ptr;
-    ged!(mut &editor).try_doc_mut().unwrap().old_cursor = cursor.loc.x;
-    ged!(mut &editor).try_doc_mut().unwrap().cancel_selection();
-    Ok(())
-}
-
-/// Adjust other secondary cursors based of a change in one
-fn adjust_other_cursors(
-    cursors: &mut Vec<Loc>,
-    old_pos: &Loc,
-    new_pos: &Loc,
-    event: &CEvent,
-    primary: &mut Loc,
-) -> Loc {
-    cursors.push(*primary);
-    match event {
-        CEvent::Key(KeyEvent {
-            code: KeyCode::Enter,
-            ..
-        }) => {
-            // Enter key, push all cursors below this line downwards
-            for c in cursors.iter_mut() {
-                if c == old_pos {
-                    continue;
-                }
-                let mut new_loc = *c;
-                // Adjust x position
-                if old_pos.y == c.y && old_pos.x < c.x {
-                    new_loc.x -= old_pos.x;
-                }
-                // If this cursor is after the currently moved cursor, shift down
-                if c.y > old_pos.y || (c.y == old_pos.y && c.x > old_pos.x) {
-                    new_loc.y += 1;
-                }
-                // Update the secondary cursor
-                *c = new_loc;
-            }
-        }
-        CEvent::Key(KeyEvent {
-            code: KeyCode::Backspace,
-            ..
-        }) => {
-            // Backspace key, push all cursors below this line upwards
-            for c in cursors.iter_mut() {
-                if c == old_pos {
-                    continue;
-                }
-                let mut new_loc = *c;
-                let at_line_start = old_pos.x == 0 && old_pos.y != 0;
-                // Adjust x position
-                if old_pos.y == c.y && old_pos.x < c.x && at_line_start {
-                    new_loc.x += new_pos.x;
-                }
-                // If this cursor is after the currently moved cursor, shift up
-                if (c.y > old_pos.y || (c.y == old_pos.y && c.x > old_pos.x)) && at_line_start {
-                    new_loc.y -= 1;
-                }
-                // Update the secondary cursor
-                *c = new_loc;
-            }
+    // Go back to the primary cursor
+    if let Some(doc) = ged!(mut &editor).try_doc_mut() {
+        if let Some(loc) = doc.remove_marker(primary) {
+            doc.move_to(&loc);
+            doc.old_cursor = doc.loc().x;
         }
-        _ => (),
     }
-    cursors.pop().unwrap()
+    result
 }
 
 // Determine whether an event should be acted on by the multi cursor
@@ -0,0 +1,327 @@
+/// Decorations allow plug-ins to annotate documents without changing their contents
+use crate::config::{Color, SyntaxHighlighting as SH};
+use crate::editor::FileContainer;
+use crossterm::style::{Color as CColor, SetBackgroundColor as Bg, SetForegroundColor as Fg};
+use kaolinite::document::{Gravity, MarkerId};
+use kaolinite::{Document, Loc};
+use mlua::prelude::*;
+
+/// A colour for a decoration, either a name from the syntax theme or a colour in its own right
+#[derive(Debug, Clone)]
+pub enum DecorationColor {
+    Theme(String, Color),
+    Fixed(Color),
+}
+
+impl DecorationColor {
+    /// Read in a colour (where strings could refer to the theme)
+    pub fn from_lua(value: LuaValue) -> Self {
+        if let LuaValue::String(ref name) = value {
+            Self::Theme(name.to_string_lossy(), Color::from_lua(value))
+        } else {
+            Self::Fixed(Color::from_lua(value))
+        }
+    }
+
+    /// Work out the colour to display, preferring colours from the theme
+    pub fn to_color(&self, sh: &SH) -> Option<CColor> {
+        match self {
+            Self::Theme(name, fallback) => sh.get_theme(name).or_else(|_| fallback.to_color()),
+            Self::Fixed(color) => color.to_color(),
+        }
+        .ok()
+    }
+}
+
+/// The different ways a document can be decorated.
+///
+/// Plug-ins add decorations to the current document under a namespace, for example:
+/// ```lua
+/// local id = editor:add_decoration("todo", {kind = "sign", y = 3, text = "!", fg = "red"})
+/// editor:remove_decoration(id)
+/// editor:clear_decorations("todo")
+/// ```
+/// - `highlight` - recolour text from `x`, `y` to `end_x`, `end_y` using `fg` and `bg`
+/// - `underline` / `undercurl` - underline text within the same range using `color`
+/// - `virtual_text` - show `text` after the end of line `y`
+/// - `inlay` - show `text` before the character at `x`, `y`
+/// - `sign` - show `text` in the sign column next to line `y`
+///
+/// Colours can be names of colours within the syntax theme (e.g. "comment")
+/// or any other colour the configuration file accepts
+#[derive(Debug, Clone)]
+pub enum DecorationKind {
+    /// Recolour a range of text
+    Highlight {
+        fg: Option<DecorationColor>,
+        bg: Option<DecorationColor>,
+    },
+    /// Underline a range of text (with a wavy line when curl is true)
+    Underline {
+        curl: bool,
+        color: Option<DecorationColor>,
+    },
+    /// Text displayed after the end of a line
+    VirtualText {
+        text: String,
+        fg: Option<DecorationColor>,
+    },
+    /// Text displayed between characters of a line
+    Inlay {
+        text: String,
+        fg: Option<DecorationColor>,
+    },
+    /// A symbol displayed in the sign column next to a line
+    Sign {
+        text: String,
+        fg: Option<DecorationColor>,
+    },
+}
+
+/// A decoration placed within the document (found through `Decorations::line`)
+#[derive(Debug)]
+pub struct Decoration<'a> {
+    pub start: Loc,
+    pub end: Loc,
+    pub kind: &'a DecorationKind,
+}
+
+impl Decoration<'_> {
+    /// Determine whether this decoration covers a character in the document
+    pub fn covers(&self, loc: Loc) -> bool {
+        (self.start.y, self.start.x) <= (loc.y, loc.x) && (loc.y, loc.x) < (self.end.y, self.end.x)
+    }
+}
+
+/// A decoration, anchored to markers so it moves along with edits to the document
+#[derive(Debug, Clone)]
+struct Anchored {
+    id: usize,
+    namespace: String,
+    start: MarkerId,
+    end: MarkerId,
+    kind: DecorationKind,
+}
+
+impl FromLua for DecorationKind {
+    fn from_lua(val: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let LuaValue::Table(table) = val else {
+            return Err(LuaError::external("a decoration must be a table"));
+        };
+        let color = |key: &str| -> LuaResult<Option<DecorationColor>> {
+            Ok(match table.get::<LuaValue>(key)? {
+                LuaValue::Nil => None,
+                value => Some(DecorationColor::from_lua(value)),
+            })
+        };
+        let text = || -> LuaResult<String> {
+            Ok(table.get::<Option<String>>("text")?.unwrap_or_default())
+        };
+        let kind: String = table.get("kind")?;
+        Ok(match kind.as_str() {
+            "highlight" => Self::Highlight {
+                fg: color("fg")?,
+                bg: color("bg")?,
+            },
+            "underline" | "undercurl" => Self::Underline {
+                curl: kind == "undercurl",
+                color: color("color")?,
+            },
+            "virtual_text" => Self::VirtualText {
+                text: text()?,
+                fg: color("fg")?,
+            },
+            "inlay" => Self::Inlay {
+                text: text()?,
+                fg: color("fg")?,
+            },
+            "sign" => Self::Sign {
+                text: text()?,
+                fg: color("fg")?,
+            },
+            _ => {
+                return Err(LuaError::external(format!(
+                    "unknown decoration kind '{kind}'"
+                )))
+            }
+        })
+    }
+}
+
+/// Stores all the decorations for a document
+#[derive(Debug, Clone, Default)]
+pub struct Decorations {
+    items: Vec<Anchored>,
+    next_id: usize,
+}
+
+impl Decorations {
+    /// Add a decoration, returning an id that can be used to remove it
+    pub fn add(
+        &mut self,
+        doc: &mut Document,
+        namespace: &str,
+        start: Loc,
+        end: Loc,
+        kind: DecorationKind,
+    ) -> usize {
+        // Point decorations stick to the text after them, ranges shouldn't grow at the end
+        let start_marker = doc.add_marker(start, Gravity::Right);
+        let end_marker = if start == end {
+            start_marker
+        } else {
+            doc.add_marker(end, Gravity::Left)
+        };
+        self.next_id += 1;
+        self.items.push(Anchored {
+            id: self.next_id,
+            namespace: namespace.to_string(),
+            start: start_marker,
+            end: end_marker,
+            kind,
+        });
+        self.next_id
+    }
+
+    /// Remove a decoration (returns true if it existed)
+    pub fn remove(&mut self, doc: &mut Document, id: usize) -> bool {
+        let before = self.items.len();
+        self.retain(doc, |d| d.id != id);
+        self.items.len() != before
+    }
+
+    /// Remove all decorations within a namespace (or every decoration if no namespace is given)
+    pub fn clear(&mut self, doc: &mut Document, namespace: Option<&str>) {
+        self.retain(doc, |d| namespace.is_some_and(|ns| d.namespace != ns));
+    }
+
+    /// Keep only certain decorations, cleaning up the markers of the others
+    fn retain(&mut self, doc: &mut Document, keep: impl Fn(&Anchored) -> bool) {
+        for decoration in self.items.iter().filter(|d| !keep(d)) {
+            doc.remove_marker(decoration.start);
+            doc.remove_marker(decoration.end);
+        }
+        self.items.retain(keep);
+    }
+
+    /// Get all the decorations that appear on a certain line
+    pub fn line<'a>(&'a self, doc: &Document, y: usize) -> Vec<Decoration<'a>> {
+        self.items
+            .iter()
+            .filter_map(|d| {
+                let start = doc.marker_loc(d.start)?;
+                let mut end = doc.marker_loc(d.end)?;
+                // Ranges that have had all their text removed become empty
+                if (end.y, end.x) < (start.y, start.x) {
+                    end = start;
+                }
+                (start.y <= y && y <= end.y).then_some(Decoration {
+                    start,
+                    end,
+                    kind: &d.kind,
+                })
+            })
+            .collect()
+    }
+
+    /// Whether the document needs to make room for a sign column
+    pub fn has_signs(&self) -> bool {
+        self.items
+            .iter()
+            .any(|d| matches!(d.kind, DecorationKind::Sign { .. }))
+    }
+}
+
+impl FileContainer {
+    /// Add a decoration from the information a plug-in provides, returning its id
+    pub fn add_decoration(
+        &mut self,
+        lua: &Lua,
+        namespace: &str,
+        info: &LuaTable,
+    ) -> LuaResult<usize> {
+        let kind = DecorationKind::from_lua(LuaValue::Table(info.clone()), lua)?;
+        let y = info.get::<usize>("y")?.saturating_sub(1);
+        let start = Loc::at(info.get::<Option<usize>>("x")?.unwrap_or(0), y);
+        // Ranges cover the rest of the line unless told otherwise
+        let end = if matches!(
+            kind,
+            DecorationKind::Highlight { .. } | DecorationKind::Underline { .. }
+        ) {
+            let end_y = info
+                .get::<Option<usize>>("end_y")?
+                .map_or(y, |y| y.saturating_sub(1));
+            let end_x = info
+                .get::<Option<usize>>("end_x")?
+                .unwrap_or_else(|| self.doc.line(end_y).map_or(0, |line| line.chars().count()));
+            Loc::at(end_x, end_y)
+        } else {
+            start
+        };
+        Ok(self
+            .decorations
+            .add(&mut self.doc, namespace, start, end, kind))
+    }
+}
+
+/// Find any inlay text that is displayed before a certain character
+pub fn inlays_at(decorations: &[Decoration], loc: Loc, sh: &SH) -> Vec<(String, Option<CColor>)> {
+    decorations
+        .iter()
+        .filter_map(|d| match &d.kind {
+            DecorationKind::Inlay { text, fg } if d.start == loc => {
+                Some((text.clone(), fg.as_ref().and_then(|c| c.to_color(sh))))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Find any virtual text to display after the end of a line
+pub fn virtual_text(
+    decorations: &[Decoration],
+    y: usize,
+    sh: &SH,
+) -> Vec<(String, Option<CColor>)> {
+    decorations
+        .iter()
+        .filter_map(|d| match &d.kind {
+            DecorationKind::VirtualText { text, fg } if d.start.y == y => {
+                Some((format!(" {text}"), fg.as_ref().and_then(|c| c.to_color(sh))))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Work out the colours and underline of a character once decorations are taken into account
+/// (later decorations take priority over earlier ones)
+pub fn decorate(
+    decorations: &[Decoration],
+    loc: Loc,
+    sh: &SH,
+    mut fg: Fg,
+    mut bg: Bg,
+) -> (Fg, Bg, Option<(bool, Option<CColor>)>) {
+    let mut underline = None;
+    for decoration in decorations.iter().filter(|d| d.covers(loc)) {
+        match &decoration.kind {
+            DecorationKind::Highlight {
+                fg: foreground,
+                bg: background,
+            } => {
+                if let Some(col) = foreground.as_ref().and_then(|c| c.to_color(sh)) {
+                    fg = Fg(col);
+                }
+                if let Some(col) = background.as_ref().and_then(|c| c.to_color(sh)) {
+                    bg = Bg(col);
+                }
+            }
+            DecorationKind::Underline { curl, color } => {
+                underline = Some((*curl, color.as_ref().and_then(|c| c.to_color(sh))));
+            }
+            _ => (),
+        }
+    }
+    (fg, bg, underline)
+}
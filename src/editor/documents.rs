@@ -1,5 +1,6 @@
 /// Tools for placing all information about open files into one place
-use crate::editor::{get_absolute_path, Editor, FileType};
+use crate::config::DocumentSettings;
+use crate::editor::{get_absolute_path, Decorations, Editor, FileType, Scratch};
 #[cfg(not(target_os = "windows"))]
 use crate::pty::Pty;
 use crate::Loc;
@@ -846,20 +847,47 @@ impl FileLayout {
 /// Container for a file
 #[derive(Debug, Clone)]
 pub struct FileContainer {
+    /// Unique identifier (stays the same as splits are rearranged)
+    pub id: usize,
     /// Document (stores kaolinite information)
     pub doc: Document,
     /// Highlighter (stores synoptic information)
     pub highlighter: Highlighter,
     /// File type (stores which file type this file is)
     pub file_type: Option<FileType>,
+    /// Decorations (stores annotations made by plug-ins)
+    pub decorations: Decorations,
+    /// Scratch buffer information (if this buffer is owned by a plug-in rather than a file)
+    pub scratch: Option<Scratch>,
+    /// Settings (stores the indentation, line endings etc. that apply to this file)
+    pub settings: DocumentSettings,
+}
+
+impl FileContainer {
+    /// Determine if this file has changes that haven't been saved (scratch buffers never do)
+    pub fn modified(&self) -> bool {
+        self.scratch.is_none() && !self.doc.event_mgmt.with_disk(&self.doc.take_snapshot())
+    }
+
+    /// The path to display for this file (scratch buffers display their name)
+    pub fn display_path(&self) -> Option<String> {
+        self.scratch
+            .as_ref()
+            .map(|scratch| scratch.name.clone())
+            .or_else(|| self.doc.file_name.clone())
+    }
 }
 
 impl Default for FileContainer {
     fn default() -> Self {
         Self {
+            id: 0,
             doc: Document::new(Size { w: 10, h: 10 }),
             highlighter: Highlighter::new(4),
             file_type: None,
+            decorations: Decorations::default(),
+            scratch: None,
+            settings: DocumentSettings::default(),
         }
     }
 }
@@ -3,7 +3,7 @@ use crate::error::Result;
 use kaolinite::event::Event;
 use kaolinite::utils::Loc;
 
-use super::Editor;
+use super::{Editor, Hook};
 
 impl Editor {
     /// Execute an edit event
@@ -27,7 +27,11 @@ impl Editor {
                 // This condition makes sure there is a copy to go back to if this is the case
                 self.try_doc_mut().unwrap().commit();
             }
-            self.try_doc_mut().unwrap().exe(ev)?;
+            // (listeners only hear about edits that actually happened)
+            self.try_doc_mut().unwrap().exe(ev.clone())?;
+            if !self.try_doc().unwrap().info.read_only {
+                self.hook(Hook::Change(Some(ev)));
+            }
         }
         Ok(())
     }
@@ -201,6 +205,7 @@ impl Editor {
         if let Some(doc) = self.try_doc_mut() {
             doc.redo()?;
             self.reload_highlight();
+            self.hook(Hook::Change(None));
         }
         Ok(())
     }
@@ -210,6 +215,7 @@ impl Editor {
         if let Some(doc) = self.try_doc_mut() {
             doc.undo()?;
             self.reload_highlight();
+            self.hook(Hook::Change(None));
         }
         Ok(())
     }
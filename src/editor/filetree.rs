@@ -4,6 +4,7 @@ use crate::editor::FileLayout;
 use crate::ui::size;
 use crate::{config, Editor, Feedback, FileTypes, OxError, Result};
 use kaolinite::utils::{file_or_dir, get_cwd, get_file_name};
+use mlua::Lua;
 use std::path::{Path, PathBuf};
 
 /// How parts of a file tree are stored
@@ -588,8 +589,8 @@ impl Editor {
     }
 
     /// Create a new file / folder
-    pub fn file_tree_new(&mut self) -> Result<()> {
-        let path = self.path_prompt()?;
+    pub fn file_tree_new(&mut self, lua: &Lua) -> Result<()> {
+        let path = self.path_prompt(lua)?;
         if path.ends_with(std::path::MAIN_SEPARATOR) {
             std::fs::create_dir_all(path)?;
             self.file_tree_refresh();
@@ -603,10 +604,13 @@ impl Editor {
     }
 
     /// Delete a file
-    pub fn file_tree_delete(&mut self) -> Result<()> {
+    pub fn file_tree_delete(&mut self, lua: &Lua) -> Result<()> {
         if let Some(file_name) = &self.file_tree_selection.clone() {
-            let prompt =
-                self.prompt(format!("Are you sure you wish to delete {file_name} (y/n)"))?;
+            let prompt = self.prompt(
+                lua,
+                format!("Are you sure you wish to delete {file_name} (y/n)"),
+                None,
+            )?;
             if prompt == "y" {
                 if file_or_dir(file_name) == "file" {
                     std::fs::remove_file(file_name)?;
@@ -624,9 +628,9 @@ impl Editor {
     }
 
     /// Copy a file
-    pub fn file_tree_copy(&mut self) -> Result<()> {
+    pub fn file_tree_copy(&mut self, lua: &Lua) -> Result<()> {
         if let Some(old_file) = &self.file_tree_selection.clone() {
-            let path = self.path_prompt()?;
+            let path = self.path_prompt(lua)?;
             if file_or_dir(old_file) == "file" {
                 std::fs::copy(old_file, path.clone())?;
                 self.file_tree_refresh();
@@ -640,9 +644,9 @@ impl Editor {
     }
 
     /// Move (or rename) a file / folder
-    pub fn file_tree_move(&mut self) -> Result<()> {
+    pub fn file_tree_move(&mut self, lua: &Lua) -> Result<()> {
         if let Some(old_file) = &self.file_tree_selection.clone() {
-            let path = self.path_prompt()?;
+            let path = self.path_prompt(lua)?;
             std::fs::rename(old_file, path.clone())?;
             self.file_tree_refresh();
             self.file_tree_selection = Some(path.clone());
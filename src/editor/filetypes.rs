@@ -1,5 +1,6 @@
 /// Tools for managing and identifying file types
 use crate::config;
+use crate::config::Indentation;
 use crate::editor::Config;
 use kaolinite::utils::get_file_name;
 use kaolinite::Document;
@@ -66,6 +67,20 @@ pub struct FileType {
     pub modelines: Vec<String>,
     /// The colour associated with this file type
     pub color: String,
+    /// The document settings this file type overrides
+    pub settings: FileTypeSettings,
+}
+
+/// Document settings for a file type (anything left as None uses the document configuration)
+/// ```lua
+/// file_types["Go"].settings = {indentation = "tabs", tab_width = 8}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileTypeSettings {
+    pub tab_width: Option<usize>,
+    pub indentation: Option<Indentation>,
+    pub wrap_cursor: Option<bool>,
+    pub undo_period: Option<usize>,
 }
 
 impl Default for FileType {
@@ -77,6 +92,7 @@ impl Default for FileType {
             extensions: vec![],
             modelines: vec![],
             color: "grey".to_string(),
+            settings: FileTypeSettings::default(),
         }
     }
 }
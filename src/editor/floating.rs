@@ -0,0 +1,337 @@
+/// Floating windows that plug-ins can display over the top of the editor
+use crate::config;
+use crate::config::{display_key, Color, TaskManager};
+use crate::editor::decorations::DecorationColor;
+use crate::editor::{Editor, FileLayout};
+use crate::error::Result;
+use crate::ui::size;
+use kaolinite::{Loc, Size};
+use mlua::prelude::*;
+
+/// A piece of styled text within a floating window
+#[derive(Debug, Clone, Default)]
+pub struct Span {
+    pub text: String,
+    pub fg: Option<DecorationColor>,
+    pub bg: Option<DecorationColor>,
+    pub bold: bool,
+}
+
+impl FromLua for Span {
+    fn from_lua(val: LuaValue, _: &Lua) -> LuaResult<Self> {
+        match val {
+            LuaValue::String(text) => Ok(Self {
+                text: text.to_string_lossy(),
+                ..Self::default()
+            }),
+            LuaValue::Table(table) => {
+                let color = |key: &str| -> LuaResult<Option<DecorationColor>> {
+                    Ok(match table.get::<LuaValue>(key)? {
+                        LuaValue::Nil => None,
+                        value => Some(DecorationColor::from_lua(value)),
+                    })
+                };
+                Ok(Self {
+                    text: table.get::<Option<String>>("text")?.unwrap_or_default(),
+                    fg: color("fg")?,
+                    bg: color("bg")?,
+                    bold: table.get::<Option<bool>>("bold")?.unwrap_or(false),
+                })
+            }
+            _ => Err(LuaError::external("text must be a string or a table")),
+        }
+    }
+}
+
+/// Read in the lines of a window, where each line is either a string or a list of spans
+pub fn lines_from_lua(lines: &LuaTable) -> LuaResult<Vec<Vec<Span>>> {
+    let mut result = vec![];
+    for line in lines.sequence_values::<LuaValue>() {
+        match line? {
+            LuaValue::Table(spans) => {
+                let spans = spans.sequence_values::<Span>().collect::<LuaResult<_>>()?;
+                result.push(spans);
+            }
+            // Split up multi-line strings
+            LuaValue::String(text) => {
+                for line in text.to_string_lossy().split('\n') {
+                    result.push(vec![Span {
+                        text: line.to_string(),
+                        ..Span::default()
+                    }]);
+                }
+            }
+            _ => return Err(LuaError::external("a line must be a string or a table")),
+        }
+    }
+    Ok(result)
+}
+
+/// Where a floating window should be placed
+#[derive(Debug, Clone, Copy)]
+pub enum Anchor {
+    /// Just below the cursor (or above if there isn't space)
+    Cursor,
+    /// In the middle of the screen
+    Center,
+    /// In the bottom right corner of the screen (just above the status line)
+    Bottom,
+    /// At a certain position on the screen
+    Screen(Loc),
+}
+
+/// A floating window.
+///
+/// Plug-ins open floating windows with a table of options, for example:
+/// ```lua
+/// local id = editor:open_window({
+///     title = "Confirm",
+///     lines = {"Yes", {{text = "No", fg = "keyword", bold = true}}},
+///     anchor = "center",
+///     selectable = true,
+///     on_select = function(index) ... end,
+/// })
+/// ```
+/// - `lines` - strings or lists of spans (`{text, fg, bg, bold}`)
+/// - `anchor` - "cursor" (default), "center", "bottom" or "screen" (placed at `x` and `y`)
+/// - `width` / `height` - the largest size the contents of the window can take up
+/// - `focus` - take key presses (the default for selectable windows),
+///   `on_key(id, key)` sees them first and can return true to stop the default handling
+/// - `transient` - close the window as soon as a key is pressed
+/// - `selectable` - allow a line to be picked with enter, which calls `on_select(index)`
+///
+/// Windows are closed with escape or `editor:close_window(id)`
+#[derive(Debug, Clone)]
+pub struct FloatingWindow {
+    pub id: usize,
+    pub title: Option<String>,
+    pub lines: Vec<Vec<Span>>,
+    pub anchor: Anchor,
+    /// The largest the contents of the window can be
+    pub max_width: Option<usize>,
+    pub max_height: Option<usize>,
+    /// Whether this window receives key presses
+    pub focus: bool,
+    /// Whether this window closes when the next key is pressed
+    pub transient: bool,
+    /// The currently selected line (for windows that act as menus)
+    pub selection: Option<usize>,
+    pub scroll: usize,
+    /// Callbacks (ids of callbacks registered with the task manager)
+    pub on_key: Option<usize>,
+    pub on_select: Option<usize>,
+}
+
+impl FloatingWindow {
+    /// Read in a window from the options a plug-in provides
+    pub fn from_lua(lua: &Lua, opts: &LuaTable, tm: &mut TaskManager) -> LuaResult<Self> {
+        let anchor = match opts.get::<Option<String>>("anchor")?.as_deref() {
+            None | Some("cursor") => Anchor::Cursor,
+            Some("center") => Anchor::Center,
+            Some("bottom") => Anchor::Bottom,
+            Some("screen") => Anchor::Screen(Loc::at(
+                opts.get::<Option<usize>>("x")?.unwrap_or(0),
+                opts.get::<Option<usize>>("y")?.unwrap_or(0),
+            )),
+            Some(other) => return Err(LuaError::external(format!("unknown anchor '{other}'"))),
+        };
+        let lines = match opts.get::<Option<LuaTable>>("lines")? {
+            Some(lines) => lines_from_lua(&lines)?,
+            None => vec![],
+        };
+        let selectable = opts.get::<Option<bool>>("selectable")?.unwrap_or(false);
+        let mut callback = |name: &str| -> LuaResult<Option<usize>> {
+            match opts.get::<Option<LuaFunction>>(name)? {
+                Some(func) => Ok(Some(tm.register_callback(lua.create_registry_value(func)?))),
+                None => Ok(None),
+            }
+        };
+        Ok(Self {
+            id: 0,
+            title: opts.get("title")?,
+            lines,
+            anchor,
+            max_width: opts.get("width")?,
+            max_height: opts.get("height")?,
+            focus: opts.get::<Option<bool>>("focus")?.unwrap_or(selectable),
+            transient: opts.get::<Option<bool>>("transient")?.unwrap_or(false),
+            selection: selectable.then_some(0),
+            scroll: 0,
+            on_key: callback("on_key")?,
+            on_select: callback("on_select")?,
+        })
+    }
+
+    /// Move the selection (or scroll if nothing is selectable)
+    pub fn move_by(&mut self, amount: isize, height: usize) {
+        let last = self.lines.len().saturating_sub(1);
+        if let Some(selection) = self.selection {
+            let selection = selection.saturating_add_signed(amount).min(last);
+            self.selection = Some(selection);
+            // Make sure the selection is visible
+            if selection < self.scroll {
+                self.scroll = selection;
+            } else if selection >= self.scroll + height {
+                self.scroll = selection + 1 - height;
+            }
+        } else {
+            let max_scroll = self.lines.len().saturating_sub(height);
+            self.scroll = self.scroll.saturating_add_signed(amount).min(max_scroll);
+        }
+    }
+}
+
+/// Keeps track of all the floating windows currently open
+#[derive(Debug, Default)]
+pub struct FloatingWindows {
+    pub items: Vec<FloatingWindow>,
+    /// The window showing which keys can come next in a key sequence
+    pub hints: Option<usize>,
+    next_id: usize,
+}
+
+impl FloatingWindows {
+    /// Get a window by its id
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut FloatingWindow> {
+        self.items.iter_mut().find(|w| w.id == id)
+    }
+
+    /// The window that key presses should go to (the most recent focused window)
+    pub fn focused(&self) -> Option<&FloatingWindow> {
+        self.items.iter().rev().find(|w| w.focus)
+    }
+}
+
+impl Editor {
+    /// Display a floating window, returning its id
+    pub fn open_window(&mut self, mut window: FloatingWindow) -> usize {
+        self.floating.next_id += 1;
+        window.id = self.floating.next_id;
+        self.floating.items.push(window);
+        self.needs_rerender = true;
+        self.floating.next_id
+    }
+
+    /// Close a floating window (returns true if the window was open)
+    pub fn close_window(&mut self, id: usize) -> bool {
+        let Some(idx) = self.floating.items.iter().position(|w| w.id == id) else {
+            return false;
+        };
+        let window = self.floating.items.remove(idx);
+        // Callbacks are no longer needed
+        let mut tm = self.config.task_manager.lock().unwrap();
+        for callback in [window.on_key, window.on_select].into_iter().flatten() {
+            tm.release_callback(callback);
+        }
+        self.needs_rerender = true;
+        true
+    }
+
+    /// Move the selection (or scroll position) of a floating window
+    pub fn move_window(&mut self, id: usize, amount: isize) -> Result<()> {
+        let size = size()?;
+        let Some(window) = self.floating.items.iter().find(|w| w.id == id) else {
+            return Ok(());
+        };
+        let (_, Size { h, .. }) = self.window_rect(window, size);
+        if let Some(window) = self.floating.get_mut(id) {
+            window.move_by(amount, h);
+        }
+        self.needs_rerender = true;
+        Ok(())
+    }
+
+    /// Close any windows that only last until the next key press
+    pub fn close_transient_windows(&mut self) {
+        let transient: Vec<usize> = self
+            .floating
+            .items
+            .iter()
+            .filter(|w| w.transient)
+            .map(|w| w.id)
+            .collect();
+        for id in transient {
+            self.close_window(id);
+        }
+    }
+
+    /// Show the keys that can come next in an incomplete key sequence
+    pub fn show_key_hints(&mut self, contexts: &[String]) {
+        self.close_key_hints();
+        let keymap = config!(self.config, keymap);
+        if !keymap.hints {
+            return;
+        }
+        let next = keymap.next_keys(contexts);
+        let key_width = next.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+        let lines = next
+            .into_iter()
+            .map(|(key, description)| {
+                vec![
+                    Span {
+                        text: format!("{key:<key_width$}  "),
+                        fg: Some(DecorationColor::Theme("keyword".to_string(), Color::Blue)),
+                        bold: true,
+                        ..Span::default()
+                    },
+                    Span {
+                        text: description,
+                        ..Span::default()
+                    },
+                ]
+            })
+            .collect();
+        let title = keymap
+            .pending()
+            .iter()
+            .map(|key| display_key(key))
+            .collect::<Vec<_>>()
+            .join(" ");
+        std::mem::drop(keymap);
+        let id = self.open_window(FloatingWindow {
+            id: 0,
+            title: Some(title),
+            lines,
+            anchor: Anchor::Bottom,
+            max_width: None,
+            max_height: None,
+            focus: false,
+            transient: false,
+            selection: None,
+            scroll: 0,
+            on_key: None,
+            on_select: None,
+        });
+        self.floating.hints = Some(id);
+    }
+
+    /// Hide the key sequence hints (if they are showing)
+    pub fn close_key_hints(&mut self) {
+        if let Some(id) = self.floating.hints.take() {
+            self.close_window(id);
+        }
+    }
+
+    /// The key binding contexts that are currently active (in order of priority)
+    pub fn key_contexts(&self) -> Vec<String> {
+        let mut contexts: Vec<String> = config!(self.config, keymap)
+            .entered
+            .iter()
+            .rev()
+            .cloned()
+            .collect();
+        let scratch = self
+            .files
+            .get(self.ptr.clone())
+            .and_then(|file| file.scratch.as_ref()?.context.clone());
+        contexts.extend(scratch);
+        let area = match self.files.get_raw(self.ptr.clone()) {
+            Some(FileLayout::FileTree) => "file_tree",
+            Some(FileLayout::Terminal(_)) => "terminal",
+            _ => "document",
+        };
+        contexts.push(area.to_string());
+        contexts.push("global".to_string());
+        contexts
+    }
+}
@@ -0,0 +1,409 @@
+/// Remembering where the user was in each file, which files and projects they used recently,
+/// and what they typed into the command line and prompts
+use crate::config::{config_dir, project_root};
+use crate::editor::{Editor, FileContainer};
+use crate::error::{OxError, Result};
+use crate::ui::Feedback;
+use kaolinite::utils::get_absolute_path;
+use kaolinite::{Document, Loc};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// How many files to remember the state of
+const MAX_FILES: usize = 500;
+/// How many projects to remember
+const MAX_PROJECTS: usize = 50;
+/// How many entries to remember for each kind of prompt
+const MAX_PROMPT_ENTRIES: usize = 100;
+/// How many kinds of prompt to remember entries for (the least recently used are forgotten)
+const MAX_PROMPT_KINDS: usize = 20;
+
+/// What is remembered about a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileState {
+    pub path: String,
+    pub cursor: Loc,
+    pub offset: Loc,
+    /// The file type the user chose for this file (if they overrode the detected one)
+    pub file_type: Option<String>,
+}
+
+impl FileState {
+    fn to_json(&self) -> Value {
+        json!({
+            "path": self.path,
+            "cursor": [self.cursor.x, self.cursor.y],
+            "offset": [self.offset.x, self.offset.y],
+            "file_type": self.file_type,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let loc = |value: &Value| {
+            let n = |i: usize| value[i].as_u64().and_then(|n| usize::try_from(n).ok());
+            Some(Loc { x: n(0)?, y: n(1)? })
+        };
+        Some(Self {
+            path: value["path"].as_str()?.to_string(),
+            cursor: loc(&value["cursor"]).unwrap_or_default(),
+            offset: loc(&value["offset"]).unwrap_or_default(),
+            file_type: value["file_type"].as_str().map(str::to_string),
+        })
+    }
+}
+
+/// Per-file state, the most recently used files and projects, and what was typed into each
+/// kind of prompt (most recent first)
+#[derive(Debug, Default)]
+pub struct History {
+    /// Where the history is kept (None if it shouldn't be written to the disk)
+    path: Option<PathBuf>,
+    pub files: Vec<FileState>,
+    pub projects: Vec<String>,
+    pub prompts: HashMap<String, Vec<String>>,
+    /// The kinds of prompt there are entries for (most recently used first)
+    prompt_kinds: Vec<String>,
+    /// Things that were used in this editor (so other editors' changes are kept)
+    touched_files: Vec<String>,
+    touched_projects: Vec<String>,
+    touched_prompts: HashMap<String, Vec<String>>,
+}
+
+impl History {
+    /// Read the history from the disk
+    pub fn load() -> Self {
+        let Some(path) = history_path() else {
+            return Self::default();
+        };
+        let mut history = Self::read(&path);
+        history.path = Some(path);
+        history
+    }
+
+    fn read(path: &Path) -> Self {
+        let value: Value = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        let mut prompts: HashMap<String, Vec<String>> = value["prompts"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(kind, entries)| {
+                let entries = entries.as_array().into_iter().flatten();
+                let entries = entries.filter_map(|e| e.as_str().map(str::to_string));
+                (kind.clone(), entries.collect())
+            })
+            .collect();
+        // (kinds missing from the order, from before it was kept, go at the end)
+        let mut prompt_kinds: Vec<String> = vec![];
+        let order = value["prompt_kinds"].as_array().into_iter().flatten();
+        for kind in order.filter_map(Value::as_str) {
+            if prompts.contains_key(kind) && !prompt_kinds.iter().any(|k| k == kind) {
+                prompt_kinds.push(kind.to_string());
+            }
+        }
+        let mut unordered: Vec<String> = prompts
+            .keys()
+            .filter(|kind| !prompt_kinds.contains(kind))
+            .cloned()
+            .collect();
+        unordered.sort();
+        prompt_kinds.extend(unordered);
+        prompt_kinds.truncate(MAX_PROMPT_KINDS);
+        prompts.retain(|kind, _| prompt_kinds.contains(kind));
+        Self {
+            files: value["files"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(FileState::from_json)
+                .collect(),
+            projects: value["projects"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|p| p.as_str().map(str::to_string))
+                .collect(),
+            prompts,
+            prompt_kinds,
+            ..Self::default()
+        }
+    }
+
+    /// Write the history to the disk (merging in anything other editors have written since)
+    pub fn write(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let disk = Self::read(path);
+        let mut files: Vec<&FileState> = self
+            .touched_files
+            .iter()
+            .filter_map(|p| self.get(p))
+            .collect();
+        files.extend(
+            disk.files
+                .iter()
+                .filter(|f| !self.touched_files.contains(&f.path)),
+        );
+        files.truncate(MAX_FILES);
+        let mut projects: Vec<&String> = self.touched_projects.iter().collect();
+        projects.extend(
+            disk.projects
+                .iter()
+                .filter(|p| !self.touched_projects.contains(p)),
+        );
+        projects.truncate(MAX_PROJECTS);
+        let mut prompts = disk.prompts;
+        for (kind, touched) in &self.touched_prompts {
+            let entries = prompts.entry(kind.clone()).or_default();
+            entries.retain(|e| !touched.contains(e));
+            entries.splice(0..0, touched.iter().cloned());
+            entries.truncate(MAX_PROMPT_ENTRIES);
+        }
+        let touched = |kind: &&String| self.touched_prompts.contains_key(*kind);
+        let mut prompt_kinds: Vec<&String> = self.prompt_kinds.iter().filter(touched).collect();
+        prompt_kinds.extend(disk.prompt_kinds.iter().filter(|kind| !touched(kind)));
+        prompt_kinds.truncate(MAX_PROMPT_KINDS);
+        prompts.retain(|kind, _| prompt_kinds.contains(&kind));
+        let value = json!({
+            "files": files.iter().map(|f| f.to_json()).collect::<Vec<_>>(),
+            "projects": projects,
+            "prompts": prompts,
+            "prompt_kinds": prompt_kinds,
+        });
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, value.to_string())
+    }
+
+    /// Get the state of a file
+    pub fn get(&self, path: &str) -> Option<&FileState> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    /// Move a file to the top of the list (creating an entry if needed)
+    fn touch(&mut self, path: &str) -> &mut FileState {
+        let state = if let Some(idx) = self.files.iter().position(|f| f.path == path) {
+            self.files.remove(idx)
+        } else {
+            FileState {
+                path: path.to_string(),
+                cursor: Loc::default(),
+                offset: Loc::default(),
+                file_type: None,
+            }
+        };
+        self.files.insert(0, state);
+        self.files.truncate(MAX_FILES);
+        self.touched_files.retain(|p| p != path);
+        self.touched_files.insert(0, path.to_string());
+        &mut self.files[0]
+    }
+
+    /// Note that a file was opened (along with the project it belongs to)
+    pub fn opened(&mut self, path: &str) {
+        self.touch(path);
+        if let Some(project) = Path::new(path)
+            .parent()
+            .and_then(|dir| project_root(&dir.to_string_lossy()))
+        {
+            self.opened_project(&project);
+        }
+    }
+
+    /// Note that a project was worked on
+    pub fn opened_project(&mut self, project: &str) {
+        for list in [&mut self.projects, &mut self.touched_projects] {
+            list.retain(|p| p != project);
+            list.insert(0, project.to_string());
+        }
+        self.projects.truncate(MAX_PROJECTS);
+    }
+
+    /// Remember where the user was in a document
+    pub fn remember(&mut self, doc: &Document) {
+        let Some(path) = doc.file_name.as_ref().and_then(|n| get_absolute_path(n)) else {
+            return;
+        };
+        let (cursor, offset) = (doc.char_loc(), doc.offset);
+        let state = self.touch(&path);
+        state.cursor = cursor;
+        state.offset = offset;
+    }
+
+    /// Remember the file type the user chose for a file (None to go back to detecting it)
+    pub fn set_file_type(&mut self, path: &str, file_type: Option<String>) {
+        self.touch(path).file_type = file_type;
+    }
+
+    /// What has been typed into a kind of prompt before (most recent first)
+    pub fn prompt_history(&self, kind: &str) -> Vec<String> {
+        self.prompts.get(kind).cloned().unwrap_or_default()
+    }
+
+    /// Remember something that was typed into a kind of prompt
+    /// (single characters, like the answers to yes or no questions, aren't worth remembering)
+    pub fn add_prompt_entry(&mut self, kind: &str, entry: &str) {
+        if entry.trim().chars().count() < 2 {
+            return;
+        }
+        for lists in [&mut self.prompts, &mut self.touched_prompts] {
+            let list = lists.entry(kind.to_string()).or_default();
+            list.retain(|e| e != entry);
+            list.insert(0, entry.to_string());
+            list.truncate(MAX_PROMPT_ENTRIES);
+        }
+        self.prompt_kinds.retain(|k| k != kind);
+        self.prompt_kinds.insert(0, kind.to_string());
+        if self.prompt_kinds.len() > MAX_PROMPT_KINDS {
+            for forgotten in self.prompt_kinds.split_off(MAX_PROMPT_KINDS) {
+                self.prompts.remove(&forgotten);
+                self.touched_prompts.remove(&forgotten);
+            }
+        }
+    }
+
+    /// The most recently used files (that still exist)
+    pub fn recent_files(&self, amount: usize) -> Vec<String> {
+        self.files
+            .iter()
+            .map(|f| f.path.clone())
+            .filter(|p| Path::new(p).is_file())
+            .take(amount)
+            .collect()
+    }
+
+    /// The most recently used projects (that still exist)
+    pub fn recent_projects(&self, amount: usize) -> Vec<String> {
+        self.projects
+            .iter()
+            .filter(|p| Path::new(p).is_dir())
+            .take(amount)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Work out where the history is kept
+fn history_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("history.json"))
+}
+
+/// Shorten a path for display (using ~ for the home folder)
+pub fn display_path(path: &str) -> String {
+    let home = std::env::var("HOME").unwrap_or_default();
+    match path.strip_prefix(&home) {
+        Some(rest) if !home.is_empty() && rest.starts_with(['/', '\\']) => format!("~{rest}"),
+        _ => path.to_string(),
+    }
+}
+
+impl FileContainer {
+    /// Put the cursor back where the user left it last time
+    pub fn restore_state(&mut self, state: &FileState) {
+        self.doc.move_to(&state.cursor);
+        self.doc.offset = state.offset;
+        self.doc.bring_cursor_in_viewport();
+        self.doc.load_to(self.doc.offset.y + self.doc.size.h);
+    }
+}
+
+impl Editor {
+    /// Allow the user to pick a recently used file to open
+    pub fn pick_recent_file(&mut self) -> Result<()> {
+        let files = self.history.recent_files(50);
+        if files.is_empty() {
+            self.feedback = Feedback::Warning("No files have been opened recently".to_string());
+            return Ok(());
+        }
+        let options: Vec<String> = files.iter().map(|f| display_path(f)).collect();
+        let choice = self.select_prompt("Recent file", &options)?;
+        let path = options
+            .iter()
+            .position(|o| *o == choice)
+            .map_or(choice, |idx| files[idx].clone());
+        // Make sure the file goes into a document split (rather than the file tree or a terminal)
+        if self.files.get_atom(self.ptr.clone()).is_none() {
+            if let Some(id) = self.files.buffer_ids().first() {
+                self.focus_buffer(*id);
+            }
+        }
+        match self.open(&path) {
+            Ok(()) => self.next(),
+            // (files that are already open are moved to instead)
+            Err(OxError::AlreadyOpen { .. }) => (),
+            Err(err) => return Err(err),
+        }
+        self.update_cwd();
+        Ok(())
+    }
+
+    /// Allow the user to pick a recently used project to move into
+    pub fn pick_recent_project(&mut self) -> Result<()> {
+        let projects = self.history.recent_projects(50);
+        if projects.is_empty() {
+            self.feedback = Feedback::Warning("No projects have been used recently".to_string());
+            return Ok(());
+        }
+        let options: Vec<String> = projects.iter().map(|p| display_path(p)).collect();
+        let choice = self.select_prompt("Recent project", &options)?;
+        let path = options
+            .iter()
+            .position(|o| *o == choice)
+            .map_or(choice, |idx| projects[idx].clone());
+        std::env::set_current_dir(&path)?;
+        self.history.opened_project(&path);
+        // Show the files in the project
+        self.close_file_tree();
+        self.open_file_tree();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_prompt_kinds_are_forgotten() {
+        let mut history = History::default();
+        for n in 0..MAX_PROMPT_KINDS {
+            history.add_prompt_entry(&format!("kind{n}"), "entry");
+        }
+        // (using a kind again keeps it around)
+        history.add_prompt_entry("kind0", "again");
+        history.add_prompt_entry("new", "entry");
+        assert_eq!(history.prompts.len(), MAX_PROMPT_KINDS);
+        assert_eq!(history.prompt_history("kind0"), ["again", "entry"]);
+        assert_eq!(history.prompt_history("new"), ["entry"]);
+        assert!(history.prompt_history("kind1").is_empty());
+        assert!(!history.touched_prompts.contains_key("kind1"));
+    }
+
+    #[test]
+    fn prompt_kinds_are_capped_on_the_disk() {
+        let path = std::env::temp_dir().join(format!("ox-history-{}.json", std::process::id()));
+        // (older files have no order, and more kinds than are kept now)
+        let prompts: HashMap<String, Vec<String>> = (0..MAX_PROMPT_KINDS + 5)
+            .map(|n| (format!("old{n:02}"), vec!["entry".to_string()]))
+            .collect();
+        std::fs::write(&path, json!({ "prompts": prompts }).to_string()).unwrap();
+        let mut history = History::read(&path);
+        assert_eq!(history.prompts.len(), MAX_PROMPT_KINDS);
+        history.path = Some(path.clone());
+        history.add_prompt_entry("search", "needle");
+        history.write().unwrap();
+        let history = History::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(history.prompts.len(), MAX_PROMPT_KINDS);
+        assert_eq!(history.prompt_kinds[0], "search");
+        assert_eq!(history.prompt_kinds[1], "old00");
+        assert!(!history
+            .prompts
+            .contains_key(&format!("old{:02}", MAX_PROMPT_KINDS - 1)));
+    }
+}
@@ -0,0 +1,125 @@
+/// Lifecycle events that plug-ins can listen for
+use crate::editor::Editor;
+use kaolinite::event::Event;
+use kaolinite::Loc;
+use mlua::prelude::*;
+
+/// Events that occur during the lifetime of the editor.
+///
+/// Plug-ins listen for these through `event_mapping`, for example:
+/// ```lua
+/// event_mapping["on_save"] = function(file_name) ... end
+/// ```
+/// - `on_open(file_name)` - a document was opened
+/// - `before_save(file_name)` - a document is about to be saved (return false to cancel)
+/// - `on_save(file_name)` - a document was saved
+/// - `before_close(file_name)` - a document is about to be closed (return false to cancel)
+/// - `on_close(file_name)` - a document was closed
+/// - `on_change(event)` - a document was edited (event is nil for undo and redo)
+/// - `on_cursor_move(loc)` - the cursor moved within a document
+/// - `on_focus_change()` - a different document or split was focused
+/// - `on_split_open()` - a new split was opened
+/// - `on_filetype(name)` - the file type of a document was determined or changed
+/// - `on_idle()` - no input has been received for a while
+#[derive(Debug, Clone)]
+pub enum Hook {
+    Open(Option<String>),
+    Save(Option<String>),
+    Close(Option<String>),
+    Change(Option<Event>),
+    CursorMove(Loc),
+    FocusChange,
+    SplitOpen,
+    FileType(String),
+    Idle,
+}
+
+impl Hook {
+    /// The name of the event that listeners are registered under
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Open(_) => "on_open",
+            Self::Save(_) => "on_save",
+            Self::Close(_) => "on_close",
+            Self::Change(_) => "on_change",
+            Self::CursorMove(_) => "on_cursor_move",
+            Self::FocusChange => "on_focus_change",
+            Self::SplitOpen => "on_split_open",
+            Self::FileType(_) => "on_filetype",
+            Self::Idle => "on_idle",
+        }
+    }
+}
+
+impl IntoLuaMulti for Hook {
+    fn into_lua_multi(self, lua: &Lua) -> LuaResult<LuaMultiValue> {
+        match self {
+            Self::Open(file) | Self::Save(file) | Self::Close(file) => file.into_lua_multi(lua),
+            Self::Change(event) => event
+                .map(|ev| event_to_lua(lua, ev))
+                .transpose()?
+                .into_lua_multi(lua),
+            Self::CursorMove(loc) => {
+                let table = lua.create_table()?;
+                table.set("x", loc.x)?;
+                table.set("y", loc.y + 1)?;
+                table.into_lua_multi(lua)
+            }
+            Self::FileType(name) => name.into_lua_multi(lua),
+            Self::FocusChange | Self::SplitOpen | Self::Idle => ().into_lua_multi(lua),
+        }
+    }
+}
+
+/// Convert an editing event into a table for lua to use
+fn event_to_lua(lua: &Lua, ev: Event) -> LuaResult<LuaTable> {
+    let table = lua.create_table()?;
+    let loc = ev.loc();
+    let (kind, text) = match ev {
+        Event::Insert(_, text) => ("insert", Some(text)),
+        Event::Delete(_, text) => ("delete", Some(text)),
+        Event::InsertLine(_, text) => ("insert_line", Some(text)),
+        Event::DeleteLine(_, text) => ("delete_line", Some(text)),
+        Event::SplitDown(_) => ("split_down", None),
+        Event::SpliceUp(_) => ("splice_up", None),
+    };
+    table.set("kind", kind)?;
+    table.set("x", loc.x)?;
+    table.set("y", loc.y + 1)?;
+    table.set("text", text)?;
+    Ok(table)
+}
+
+impl Editor {
+    /// Queue up a hook to be sent to plug-ins
+    pub fn hook(&mut self, hook: Hook) {
+        self.hooks.push(hook);
+    }
+
+    /// Obtain the list of hooks to send out (and remove them from the queue)
+    pub fn hook_list(&mut self) -> Vec<Hook> {
+        std::mem::take(&mut self.hooks)
+    }
+
+    /// Work out which document is focused (pointer and index within the atom)
+    pub fn focus(&self) -> (Vec<usize>, Option<usize>) {
+        let idx = self.files.get_atom(self.ptr.clone()).map(|(_, idx)| idx);
+        (self.ptr.clone(), idx)
+    }
+
+    /// Queue hooks for any change in focus or cursor position since the values provided
+    pub fn hook_movement(&mut self, old_focus: &(Vec<usize>, Option<usize>), old_loc: Loc) {
+        if self.focus() != *old_focus {
+            self.hook(Hook::FocusChange);
+        } else if let Some(loc) = self.try_doc().map(kaolinite::Document::char_loc) {
+            if loc != old_loc {
+                self.hook(Hook::CursorMove(loc));
+            }
+        }
+    }
+
+    /// Get the file name of the current document (for use in hooks)
+    pub fn hook_file_name(&self) -> Option<String> {
+        self.try_doc().and_then(|doc| doc.file_name.clone())
+    }
+}
@@ -1,19 +1,30 @@
 /// Functions for rendering the UI
 use crate::config::SyntaxHighlighting as SH;
-use crate::editor::{FTParts, FileLayout};
+use crate::config::{KeyOutcome, Keymap};
+use crate::editor::decorations::{decorate, inlays_at, virtual_text};
+use crate::editor::{
+    complete_path, display_path, Anchor, Completions, DecorationKind, FTParts, FileLayout,
+    FloatingWindow, LineEditor,
+};
 use crate::error::{OxError, Result};
-use crate::events::wait_for_event_hog;
+use crate::events::{get_event, wait_for_event_hog};
 use crate::ui::{key_event, size, Feedback};
 #[cfg(not(target_os = "windows"))]
 use crate::ui::{remove_ansi_codes, replace_reset, strip_escape_codes};
 use crate::{config, display, handle_lua_error};
 use crossterm::{
-    event::{KeyCode as KCode, KeyModifiers as KMod},
-    style::{Attribute, Color, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg},
+    event::{Event as CEvent, KeyCode as KCode, KeyEvent, KeyEventKind, KeyModifiers as KMod},
+    style::{
+        Attribute, Color, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg,
+        SetUnderlineColor,
+    },
+    Command,
 };
-use kaolinite::utils::{file_or_dir, get_cwd, get_parent, list_dir, width, width_char, Loc, Size};
-use mlua::Lua;
+use kaolinite::utils::{get_cwd, width, width_char, Loc, Size};
+use mlua::{Lua, Result as LuaResult, Value as LuaValue};
+use std::fmt::Write;
 use std::ops::Range;
+use std::path::{MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
 use synoptic::{trim_fit, Highlighter, TokOpt};
 
 use super::Editor;
@@ -37,7 +48,14 @@ impl Editor {
     pub fn update_render_cache(&mut self, lua: &Lua, size: Size) {
         // Calculate greeting message
         if config!(self.config, tab_line).enabled && self.greet {
-            self.render_cache.greeting_message = config!(self.config, greeting_message).render(lua);
+            let recent_files: Vec<String> = self
+                .history
+                .recent_files(5)
+                .iter()
+                .map(|path| display_path(path))
+                .collect();
+            self.render_cache.greeting_message =
+                config!(self.config, greeting_message).render(lua, &recent_files);
         }
         // Calculate span
         self.render_cache.span = self.files.span(vec![], size, Loc::at(0, 0));
@@ -221,10 +239,13 @@ impl Editor {
             self.terminal.goto(0, y);
             display!(self, line);
         }
+        // Render any floating windows over the top
+        self.render_windows(size, &syntax)?;
         // Render the feedback line
         self.render_feedback_line(w, h)?;
         // Move cursor to the correct location and perform render
-        if let Some(Loc { x, y }) = self.cursor_position() {
+        // (unless a floating window is taking key presses)
+        if let (Some(Loc { x, y }), None) = (self.cursor_position(), self.floating.focused()) {
             self.terminal.show_cursor();
             self.terminal.goto(x, y);
         }
@@ -246,6 +267,7 @@ impl Editor {
             // Move cursor to location within file
             (false, false) => {
                 let Loc { x, y } = self.try_doc().unwrap().cursor_loc_in_screen()?;
+                let x = x + self.inlay_width();
                 for (ptr, rows, cols) in &self.render_cache.span {
                     if ptr == &self.ptr {
                         return Some(Loc {
@@ -287,12 +309,12 @@ impl Editor {
         let selection_fg = Fg(config!(self.config, colors).selection_fg.to_color()?);
         let underline = SetAttribute(Attribute::Underlined);
         let no_underline = SetAttribute(Attribute::NoUnderline);
-        let tab_width = config!(self.config, document).tab_width;
         let line_numbers_enabled = config!(self.config, line_numbers).enabled;
         let ln_pad_left = config!(self.config, line_numbers).padding_left;
         let ln_pad_right = config!(self.config, line_numbers).padding_right;
         let fc = self.files.get(ptr.to_owned()).unwrap();
         let doc = &fc.doc;
+        let tab_width = doc.tab_width;
         let selection = doc.selection_loc_bound_disp();
         let has_file = doc.file_name.is_none();
         // Refuse to render help message on splits - awkward edge case
@@ -315,13 +337,38 @@ impl Editor {
         } else {
             result += &format!("{editor_fg}{editor_bg}");
         }
+        let at_line = y + doc.offset.y;
+        let decorations = fc.decorations.line(doc, at_line);
+        // Render the sign column if any signs are present
+        if fc.decorations.has_signs() {
+            let sign = decorations.iter().rev().find_map(|d| match &d.kind {
+                DecorationKind::Sign { text, fg } if d.start.y == at_line => Some((text, fg)),
+                _ => None,
+            });
+            let (text, fg) = sign.map_or((String::new(), line_number_fg), |(text, fg)| {
+                let fg = fg
+                    .as_ref()
+                    .and_then(|c| c.to_color(sh))
+                    .map_or(line_number_fg, Fg);
+                (text.chars().take(2).collect(), fg)
+            });
+            let padding = " ".repeat(2_usize.saturating_sub(width(&text, tab_width)));
+            let _ = write!(
+                result,
+                "{line_number_bg}{fg}{text}{padding}{editor_fg}{editor_bg}"
+            );
+            total_width += 2;
+        }
         w = w.saturating_sub(total_width);
         // Render the body of the document if available
-        let at_line = y + doc.offset.y;
         if let Some(line) = doc.line(at_line) {
             // Reset the cache
             let mut cache_bg = editor_bg;
             let mut cache_fg = editor_fg;
+            let mut cache_underline = None;
+            let mut content_width = 0;
+            let line_length = line.chars().count();
+            let mut rendered_end = false;
             // Gather the tokens
             let tokens = fc.highlighter.line(at_line, &line);
             let tokens = trim_fit(&tokens, doc.offset.x, w, tab_width);
@@ -330,7 +377,7 @@ impl Editor {
             // Run some more calcs
             let is_focus = self.ptr == ptr;
             let has_selection_somewhere = doc.cursor.selection_end != doc.cursor.loc;
-            for token in tokens {
+            'tokens: for token in tokens {
                 // Find out the text (and colour of that text)
                 let (text, colour, feedback) = self.breakdown_token(token, sh)?;
                 if let Some(fb) = feedback {
@@ -340,10 +387,31 @@ impl Editor {
                 for c in text.chars() {
                     let disp_loc = Loc::at(x_disp, at_line);
                     let char_loc = Loc::at(x_char, at_line);
+                    // Render any inlay text before this character
+                    // (and virtual text once the padding after the end of the line is reached)
+                    let mut extra = inlays_at(&decorations, char_loc, sh);
+                    if x_char == line_length {
+                        extra.extend(virtual_text(&decorations, at_line, sh));
+                        rendered_end = true;
+                    }
+                    let space = w.saturating_sub(content_width);
+                    let (text, extra_width) = render_extra(extra, space, tab_width, editor_bg);
+                    if extra_width > 0 {
+                        let _ = write!(result, "{text}{cache_bg}{cache_fg}");
+                        content_width += extra_width;
+                        total_width += extra_width;
+                    }
+                    let c_width = width_char(&c, tab_width);
+                    if content_width + c_width > w {
+                        break 'tokens;
+                    }
                     // Work out selection
                     let is_selected = is_focus
                         && has_selection_somewhere
                         && doc.is_this_loc_selected_disp(disp_loc, selection);
+                    // Work out how decorations affect this character
+                    let (colour, bg, decor_underline) =
+                        decorate(&decorations, char_loc, sh, colour, editor_bg);
                     // Render the correct colour
                     if is_selected {
                         if cache_bg != selection_bg {
@@ -355,15 +423,31 @@ impl Editor {
                             cache_fg = selection_fg;
                         }
                     } else {
-                        if cache_bg != editor_bg {
-                            result += &editor_bg.to_string();
-                            cache_bg = editor_bg;
+                        if cache_bg != bg {
+                            result += &bg.to_string();
+                            cache_bg = bg;
                         }
                         if cache_fg != colour {
                             result += &colour.to_string();
                             cache_fg = colour;
                         }
                     }
+                    // Render underlines from decorations
+                    if cache_underline != decor_underline {
+                        result += &no_underline.to_string();
+                        if let Some((curl, colour)) = decor_underline {
+                            let attr = if curl {
+                                Attribute::Undercurled
+                            } else {
+                                Attribute::Underlined
+                            };
+                            result += &SetAttribute(attr).to_string();
+                            if let Some(colour) = colour {
+                                let _ = SetUnderlineColor(colour).write_ansi(&mut result);
+                            }
+                        }
+                        cache_underline = decor_underline;
+                    }
                     // Render multi-cursors
                     let multi_cursor_here = doc.has_cursor(char_loc).is_some();
                     if multi_cursor_here {
@@ -374,13 +458,27 @@ impl Editor {
                     // Reset any multi-cursor display
                     if multi_cursor_here {
                         result += &format!("{no_underline}{cache_bg}{cache_fg}");
+                        cache_underline = None;
                     }
                     x_char += 1;
-                    let c_width = width_char(&c, tab_width);
                     x_disp += c_width;
                     total_width += c_width;
+                    content_width += c_width;
                 }
             }
+            if cache_underline.is_some() {
+                result += &no_underline.to_string();
+            }
+            // Render inlay text at the end of the line, followed by virtual text
+            // (if there was no padding to render it in, as long as the end of the line is on screen)
+            if x_char == line_length && !rendered_end {
+                let mut extra = inlays_at(&decorations, Loc::at(x_char, at_line), sh);
+                extra.extend(virtual_text(&decorations, at_line, sh));
+                let space = w.saturating_sub(content_width);
+                let (text, extra_width) = render_extra(extra, space, tab_width, editor_bg);
+                result += &text;
+                total_width += extra_width;
+            }
             result += &format!("{editor_fg}{editor_bg}{cache_fg}");
             result += &" ".repeat(w.saturating_sub(total_width));
         } else if config!(self.config, greeting_message).enabled && self.greet && has_file {
@@ -551,6 +649,127 @@ impl Editor {
         Ok(result)
     }
 
+    /// Work out where a floating window is displayed on the screen (and how big it is)
+    pub fn window_rect(&self, window: &FloatingWindow, size: Size) -> (Loc, Size) {
+        let tab_width = config!(self.config, document).tab_width;
+        // Work out the size of the contents
+        let content_width = window
+            .lines
+            .iter()
+            .map(|line| line.iter().map(|s| width(&s.text, tab_width)).sum())
+            .chain(window.title.iter().map(|t| width(t, tab_width) + 3))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let w = content_width
+            .min(window.max_width.unwrap_or(usize::MAX))
+            .min(size.w.saturating_sub(2));
+        let h = window
+            .lines
+            .len()
+            .max(1)
+            .min(window.max_height.unwrap_or(size.h / 2))
+            .min(size.h.saturating_sub(2));
+        let (outer_w, outer_h) = (w + 2, h + 2);
+        // Work out the position of the window
+        let Loc { x, y } = match window.anchor {
+            Anchor::Cursor => {
+                let cursor = self.cursor_position().unwrap_or_default();
+                let y = if cursor.y + 1 + outer_h <= size.h {
+                    cursor.y + 1
+                } else {
+                    cursor.y.saturating_sub(outer_h)
+                };
+                Loc::at(cursor.x, y)
+            }
+            Anchor::Center => Loc::at(
+                size.w.saturating_sub(outer_w) / 2,
+                size.h.saturating_sub(outer_h) / 2,
+            ),
+            Anchor::Bottom => Loc::at(
+                size.w.saturating_sub(outer_w),
+                size.h.saturating_sub(outer_h + 2),
+            ),
+            Anchor::Screen(loc) => loc,
+        };
+        let x = x.min(size.w.saturating_sub(outer_w));
+        let y = y.min(size.h.saturating_sub(outer_h));
+        (Loc::at(x, y), Size { w, h })
+    }
+
+    /// Render floating windows on top of everything else
+    #[allow(clippy::similar_names)]
+    pub fn render_windows(&mut self, size: Size, sh: &SH) -> Result<()> {
+        let colors = config!(self.config, colors);
+        let window_bg = Bg(colors.status_bg.to_color()?);
+        let window_fg = Fg(colors.editor_fg.to_color()?);
+        let border_fg = Fg(colors.highlight.to_color()?);
+        let selection_bg = Bg(colors.selection_bg.to_color()?);
+        let selection_fg = Fg(colors.selection_fg.to_color()?);
+        let tab_width = config!(self.config, document).tab_width;
+        let bold = SetAttribute(Attribute::Bold);
+        let no_bold = SetAttribute(Attribute::NormalIntensity);
+        let mut rows = vec![];
+        for window in &self.floating.items {
+            let (Loc { x, y }, Size { w, h }) = self.window_rect(window, size);
+            // Top border (with the title)
+            let title = window
+                .title
+                .as_ref()
+                .map(|t| format!("─ {} ", fit(t.clone(), w.saturating_sub(3), tab_width)))
+                .unwrap_or_default();
+            let rest = "─".repeat(w.saturating_sub(width(&title, tab_width)));
+            rows.push((
+                Loc::at(x, y),
+                format!("{window_bg}{border_fg}╭{bold}{title}{no_bold}{rest}╮"),
+            ));
+            // Contents
+            for row in 0..h {
+                let idx = window.scroll + row;
+                let (bg, fg) = if window.selection == Some(idx) {
+                    (selection_bg, selection_fg)
+                } else {
+                    (window_bg, window_fg)
+                };
+                let mut line = format!("{window_bg}{border_fg}│{bg}{fg}");
+                let mut total = 0;
+                for span in window.lines.get(idx).into_iter().flatten() {
+                    let text = fit(span.text.clone(), w.saturating_sub(total), tab_width);
+                    total += width(&text, tab_width);
+                    let span_fg = span.fg.as_ref().and_then(|c| c.to_color(sh)).map_or(fg, Fg);
+                    let span_bg = span.bg.as_ref().and_then(|c| c.to_color(sh)).map_or(bg, Bg);
+                    let (start, end) = if span.bold {
+                        (bold.to_string(), no_bold.to_string())
+                    } else {
+                        (String::new(), String::new())
+                    };
+                    let _ = write!(line, "{span_bg}{span_fg}{start}{text}{end}{bg}{fg}");
+                }
+                line += &" ".repeat(w.saturating_sub(total));
+                let _ = write!(line, "{window_bg}{border_fg}│");
+                rows.push((Loc::at(x, y + row + 1), line));
+            }
+            // Bottom border (with an indicator if there is more to scroll through)
+            let more = if window.scroll + h < window.lines.len() {
+                "↓"
+            } else {
+                "─"
+            };
+            rows.push((
+                Loc::at(x, y + h + 1),
+                format!(
+                    "{window_bg}{border_fg}╰{}{more}╯",
+                    "─".repeat(w.saturating_sub(1))
+                ),
+            ));
+        }
+        for (Loc { x, y }, row) in rows {
+            self.terminal.goto(x, y);
+            display!(self, row);
+        }
+        Ok(())
+    }
+
     /// Render the feedback line
     pub fn render_feedback_line(&mut self, w: usize, h: usize) -> Result<()> {
         self.terminal.goto(0, h + 2);
@@ -685,78 +904,240 @@ impl Editor {
     }
 
     /// Display a prompt in the document
-    pub fn prompt<S: Into<String>>(&mut self, prompt: S) -> Result<String> {
+    /// (if a kind is given, what is typed in is remembered for the next prompt of that kind)
+    pub fn prompt<S: Into<String>>(
+        &mut self,
+        lua: &Lua,
+        prompt: S,
+        kind: Option<&str>,
+    ) -> Result<String> {
         let prompt = prompt.into();
-        let mut input = String::new();
-        let mut done = false;
+        let history = kind.map(|kind| self.history.prompt_history(kind));
+        let mut line = LineEditor::with_history(history.unwrap_or_default());
         // Enter into a menu that asks for a prompt
-        while !done {
-            let h = size()?.h;
-            let w = size()?.w;
-            // Render prompt message
-            self.terminal.prepare_line(h);
-            self.terminal.show_cursor();
-            let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
-            display!(
-                self,
-                editor_bg,
-                prompt.clone(),
-                ": ",
-                input.clone(),
-                " ".to_string().repeat(w)
-            );
-            self.terminal.goto(prompt.len() + input.len() + 2, h);
-            self.terminal.flush()?;
-            // Handle events
-            if let Some((modifiers, code)) =
-                key_event(&wait_for_event_hog(self), &mut self.macro_man)
-            {
-                match (modifiers, code) {
-                    // Exit the menu when the enter key is pressed
-                    (KMod::NONE, KCode::Enter) => done = true,
-                    // Cancel operation
-                    (KMod::NONE, KCode::Esc) => return Err(OxError::Cancelled),
-                    // Remove from the input string if the user presses backspace
-                    (KMod::NONE, KCode::Backspace) => {
-                        input.pop();
-                    }
-                    // Add to the input string if the user presses a character
-                    (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => input.push(c),
-                    _ => (),
+        loop {
+            self.render_line_editor(&prompt, &line, "")?;
+            let Some((modifiers, code)) = self.prompt_key(lua, &mut line) else {
+                continue;
+            };
+            match (modifiers, code) {
+                // Exit the menu when the enter key is pressed
+                (KMod::NONE, KCode::Enter) => break,
+                // Cancel operation
+                (KMod::NONE, KCode::Esc) => return Err(OxError::Cancelled),
+                // Edit the input
+                _ => {
+                    line.handle_key(modifiers, code);
                 }
             }
         }
         // Return input string result
-        Ok(input)
+        if let Some(kind) = kind {
+            self.history.add_prompt_entry(kind, &line.text);
+        }
+        Ok(line.text)
     }
 
-    /// Prompt for selecting a file
+    /// Render a line that is being typed in at the bottom of the screen
+    /// (the hint is shown in grey after the text, cut short if it doesn't fit)
     #[allow(clippy::similar_names)]
-    pub fn path_prompt(&mut self) -> Result<String> {
-        let mut input = get_cwd()
+    pub fn render_line_editor(
+        &mut self,
+        prompt: &str,
+        line: &LineEditor,
+        hint: &str,
+    ) -> Result<()> {
+        let Size { w, h } = size()?;
+        self.terminal.prepare_line(h);
+        self.terminal.show_cursor();
+        let editor_fg = Fg(config!(self.config, colors).editor_fg.to_color()?);
+        let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
+        let tab_width = config!(self.config, document).tab_width;
+        let mut room =
+            w.saturating_sub(width(prompt, tab_width) + 2 + width(&line.text, tab_width));
+        let mut hint_text = String::new();
+        for c in hint.chars() {
+            let c_width = width_char(&c, tab_width);
+            if c_width > room {
+                break;
+            }
+            room -= c_width;
+            hint_text.push(c);
+        }
+        display!(
+            self,
+            editor_bg,
+            prompt,
+            ": ",
+            line.text.clone(),
+            Fg(Color::DarkGrey),
+            hint_text,
+            " ".repeat(room),
+            editor_fg
+        );
+        let x = width(prompt, tab_width) + 2 + line.cursor_width(tab_width);
+        self.terminal.goto(x, h);
+        self.terminal.flush()?;
+        Ok(())
+    }
+
+    /// Wait for a key press in a prompt
+    /// (pasted text and keys bound in the prompt context are dealt with here, giving None)
+    pub fn prompt_key(&mut self, lua: &Lua, line: &mut LineEditor) -> Option<(KMod, KCode)> {
+        // (keys from a broken key sequence are handed back one at a time)
+        if let Some(key) = self.prompt_replay.pop_front() {
+            return Some((key.modifiers, key.code));
+        }
+        let keymap = self.config.keymap.clone();
+        let mut contexts: Vec<String> = config!(self.config, keymap)
+            .entered
+            .iter()
+            .rev()
+            .cloned()
+            .collect();
+        contexts.push("prompt".to_string());
+        let event = loop {
+            match get_event(self) {
+                Some(CEvent::Key(KeyEvent {
+                    kind: KeyEventKind::Release,
+                    ..
+                })) => (),
+                Some(event) => break event,
+                None => {
+                    // Give up waiting for the rest of a key sequence
+                    let expired = keymap
+                        .borrow_mut::<Keymap>()
+                        .ok()
+                        .and_then(|mut keymap| keymap.expire(&contexts));
+                    if let Some(outcomes) = expired {
+                        return self.prompt_chord(lua, outcomes, line);
+                    }
+                }
+            }
+        };
+        if let CEvent::Paste(text) = &event {
+            self.macro_man.register(event.clone());
+            line.paste(text);
+            return None;
+        }
+        let (modifiers, code) = key_event(&event, &mut self.macro_man)?;
+        let key = KeyEvent::new(code, modifiers);
+        let Ok(outcomes) = keymap
+            .borrow_mut::<Keymap>()
+            .map(|mut keymap| keymap.feed(&contexts, key))
+        else {
+            return Some((modifiers, code));
+        };
+        self.prompt_chord(lua, outcomes, line)
+    }
+
+    /// Do what the keymap says should happen after a key press in a prompt
+    /// (giving back the first key that the prompt should handle as usual)
+    fn prompt_chord(
+        &mut self,
+        lua: &Lua,
+        outcomes: Vec<KeyOutcome>,
+        line: &mut LineEditor,
+    ) -> Option<(KMod, KCode)> {
+        for outcome in outcomes {
+            match outcome {
+                // Bindings can give back new input for the prompt
+                KeyOutcome::Run(action) => {
+                    let input = line.text.clone();
+                    match self.lend(lua, || action.call::<Option<String>>(input)) {
+                        Ok(Some(new)) => line.set(new),
+                        Ok(None) => (),
+                        Err(err) => self.feedback = Feedback::Error(err.to_string()),
+                    }
+                }
+                KeyOutcome::Unbound(key) => self.prompt_replay.push_back(key),
+                KeyOutcome::Pending => (),
+            }
+        }
+        self.prompt_replay
+            .pop_front()
+            .map(|key| (key.modifiers, key.code))
+    }
+
+    /// Run lua code while the editor is borrowed, lending the editor to it
+    /// (the `editor` global refers to this editor until the code has finished)
+    pub fn lend<R>(&mut self, lua: &Lua, run: impl FnOnce() -> LuaResult<R>) -> LuaResult<R> {
+        let globals = lua.globals();
+        let editor: LuaValue = globals.get("editor")?;
+        let result = lua.scope(|scope| {
+            globals.set("editor", scope.create_userdata_ref_mut(self)?)?;
+            run()
+        });
+        globals.set("editor", editor)?;
+        result
+    }
+
+    /// Prompt for selecting a file
+    /// (tab completes the path, listing the candidates if there are several)
+    pub fn path_prompt(&mut self, lua: &Lua) -> Result<String> {
+        let cwd = get_cwd()
             .map(|p| {
-                if p.ends_with(std::path::MAIN_SEPARATOR) {
+                if p.ends_with(MAIN_SEPARATOR) {
                     p
                 } else {
-                    p + std::path::MAIN_SEPARATOR_STR
+                    p + MAIN_SEPARATOR_STR
                 }
             })
             .unwrap_or_default();
-        let mut offset = 0;
-        let mut done = false;
-        let mut old_suggestions = vec![];
+        let mut line = LineEditor::with_history(self.history.prompt_history("path"));
+        line.set(cwd);
+        let mut completions: Option<Completions> = None;
         // Enter into a menu that asks for a prompt
-        while !done {
-            // Find the suggested files and folders
-            let parent = if input.ends_with('/') || input.ends_with('\\') {
-                input.to_string()
-            } else {
-                get_parent(&input).unwrap_or_default()
+        loop {
+            let hint = completions
+                .as_ref()
+                .map(Completions::hint)
+                .unwrap_or_default();
+            self.render_line_editor("Path", &line, &hint)?;
+            let Some((modifiers, code)) = self.prompt_key(lua, &mut line) else {
+                completions = None;
+                continue;
             };
-            let suggestions = list_dir(&parent)
-                .unwrap_or_default()
+            if !matches!(code, KCode::Tab | KCode::BackTab) {
+                completions = None;
+            }
+            match (modifiers, code) {
+                // Exit the menu when the enter key is pressed
+                (KMod::NONE, KCode::Enter) => break,
+                // Cancel when escape key is pressed
+                (KMod::NONE, KCode::Esc) => return Err(OxError::Cancelled),
+                // Complete the path, then cycle through the candidates
+                (KMod::NONE, KCode::Tab) | (KMod::SHIFT, KCode::BackTab) => {
+                    if let Some(completions) = &mut completions {
+                        completions.cycle(&mut line, code == KCode::Tab);
+                    } else {
+                        let current = line.before_cursor().to_string();
+                        let found = Completions::new(0, complete_path(&current), str::to_string);
+                        completions = line.complete(found, &current, "");
+                    }
+                }
+                // Edit the path
+                _ => {
+                    line.handle_key(modifiers, code);
+                }
+            }
+        }
+        // Return input string result
+        self.history.add_prompt_entry("path", &line.text);
+        Ok(line.text)
+    }
+
+    /// Prompt for selecting one of a list of options
+    #[allow(clippy::similar_names)]
+    pub fn select_prompt(&mut self, prompt: &str, options: &[String]) -> Result<String> {
+        let mut input = String::new();
+        let mut offset = 0;
+        let mut old_suggestions = vec![];
+        loop {
+            // Find the options that match what has been typed so far
+            let suggestions = options
                 .iter()
-                .filter(|p| p.starts_with(&input))
+                .filter(|o| o.starts_with(&input))
                 .cloned()
                 .collect::<Vec<_>>();
             // Reset offset if we've changed suggestions / out of bounds
@@ -764,44 +1145,60 @@ impl Editor {
                 offset = 0;
             }
             old_suggestions.clone_from(&suggestions);
-            // Select suggestion
-            let mut suggestion = suggestions
-                .get(offset)
-                .map(std::string::ToString::to_string)
-                .unwrap_or(input.clone());
-            // Render prompt message
+            let suggestion = suggestions.get(offset).cloned().unwrap_or(input.clone());
+            // Render prompt message, along with the other matching options
             let h = size()?.h;
+            let w = size()?.w;
             self.terminal.prepare_line(h);
             self.terminal.show_cursor();
             let suggestion_text = suggestion
                 .chars()
                 .skip(input.chars().count())
                 .collect::<String>();
+            let others = suggestions
+                .iter()
+                .filter(|s| **s != suggestion)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ");
             let editor_fg = Fg(config!(self.config, colors).editor_fg.to_color()?);
             let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
             let tab_width = config!(self.config, document).tab_width;
-            let total_width = width(&input, tab_width) + width(&suggestion_text, tab_width);
-            let padding = " ".repeat(size()?.w.saturating_sub(total_width));
+            let others = if others.is_empty() {
+                others
+            } else {
+                format!("   {others}")
+            };
+            let total_width = prompt.len()
+                + 2
+                + width(&input, tab_width)
+                + width(&suggestion_text, tab_width)
+                + width(&others, tab_width);
+            let padding = " ".repeat(w.saturating_sub(total_width));
             display!(
                 self,
                 editor_bg,
-                "Path: ",
+                prompt,
+                ": ",
                 input.clone(),
                 Fg(Color::DarkGrey),
                 suggestion_text,
+                others,
                 padding,
                 editor_fg
             );
-            let tab_width = config!(self.config, document).tab_width;
-            self.terminal.goto(6 + width(&input, tab_width), h);
+            self.terminal
+                .goto(prompt.len() + 2 + width(&input, tab_width), h);
             self.terminal.flush()?;
             // Handle events
             if let Some((modifiers, code)) =
                 key_event(&wait_for_event_hog(self), &mut self.macro_man)
             {
                 match (modifiers, code) {
-                    // Exit the menu when the enter key is pressed
-                    (KMod::NONE, KCode::Enter) => done = true,
+                    // Select the suggested option when the enter key is pressed
+                    (KMod::NONE, KCode::Enter) if options.contains(&suggestion) => {
+                        return Ok(suggestion);
+                    }
                     // Cancel when escape key is pressed
                     (KMod::NONE, KCode::Esc) => return Err(OxError::Cancelled),
                     // Remove from the input string if the user presses backspace
@@ -810,39 +1207,15 @@ impl Editor {
                     }
                     // Add to the input string if the user presses a character
                     (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => input.push(c),
-                    // Autocomplete path
-                    (KMod::NONE, KCode::Right) => {
-                        if file_or_dir(&suggestion) == "directory" {
-                            suggestion.push(std::path::MAIN_SEPARATOR);
-                        }
-                        input = suggestion;
-                        offset = 0;
-                    }
-                    // Go up a directory
-                    (KMod::NONE, KCode::Left) => {
-                        // Find the /
-                        let dir_no_sep = input
-                            .chars()
-                            .take(input.chars().count().saturating_sub(1))
-                            .collect::<String>();
-                        if let Some(parent_cut) = dir_no_sep.rfind('/') {
-                            input = input.chars().take(parent_cut + 1).collect();
-                            offset = 0;
-                        }
-                    }
+                    // Autocomplete option
+                    (KMod::NONE, KCode::Right) => input = suggestion,
                     // Cycle through suggestions
                     (KMod::SHIFT, KCode::BackTab) => offset = offset.saturating_sub(1),
-                    (KMod::NONE, KCode::Tab) => {
-                        if offset + 1 < suggestions.len() {
-                            offset += 1;
-                        }
-                    }
+                    (KMod::NONE, KCode::Tab) if offset + 1 < suggestions.len() => offset += 1,
                     _ => (),
                 }
             }
         }
-        // Return input string result
-        Ok(input)
     }
 
     /// Confirmation dialog
@@ -937,18 +1310,145 @@ impl Editor {
         }
     }
 
-    /// Work out how much to push the document to the right (to make way for line numbers)
+    /// Work out how far inlay text before the cursor pushes it to the right
+    pub fn inlay_width(&self) -> usize {
+        let Some(fc) = self.files.get(self.ptr.clone()) else {
+            return 0;
+        };
+        let tab_width = fc.doc.tab_width;
+        let cursor = fc.doc.char_loc();
+        let offset = fc.doc.character_idx(&fc.doc.offset);
+        fc.decorations
+            .line(&fc.doc, cursor.y)
+            .iter()
+            .filter_map(|d| match &d.kind {
+                DecorationKind::Inlay { text, .. }
+                    if d.start.y == cursor.y && (offset..=cursor.x).contains(&d.start.x) =>
+                {
+                    Some(width(text, tab_width))
+                }
+                _ => None,
+            })
+            .sum()
+    }
+
+    /// Work out how much to push the document to the right
+    /// (to make way for line numbers and the sign column)
     pub fn dent_for(&self, at: &[usize], doc: usize) -> usize {
+        let Some((fcs, _)) = self.files.get_atom(at.to_owned()) else {
+            return 0;
+        };
+        let signs = if fcs[doc].decorations.has_signs() {
+            2
+        } else {
+            0
+        };
         if config!(self.config, line_numbers).enabled {
             let padding_left = config!(self.config, line_numbers).padding_left;
             let padding_right = config!(self.config, line_numbers).padding_right;
-            if let Some((fcs, _)) = self.files.get_atom(at.to_owned()) {
-                fcs[doc].doc.len_lines().to_string().len() + 1 + padding_left + padding_right
-            } else {
-                0
-            }
+            fcs[doc].doc.len_lines().to_string().len() + 1 + padding_left + padding_right + signs
         } else {
-            0
+            signs
+        }
+    }
+}
+
+/// Render inlay and virtual text, cut down to fit within a certain width
+/// (returns the text to render, along with how wide it is)
+fn render_extra(
+    extra: Vec<(String, Option<Color>)>,
+    max: usize,
+    tab_width: usize,
+    bg: Bg,
+) -> (String, usize) {
+    let mut result = String::new();
+    let mut total = 0;
+    for (text, fg) in extra {
+        let fg = fg.map_or(Fg(Color::DarkGrey), Fg);
+        let text = fit(text, max.saturating_sub(total), tab_width);
+        total += width(&text, tab_width);
+        let _ = write!(result, "{bg}{fg}{text}");
+    }
+    (result, total)
+}
+
+/// Cut text down so that it fits within a certain width
+fn fit(text: String, max: usize, tab_width: usize) -> String {
+    if width(&text, tab_width) <= max {
+        return text;
+    }
+    let mut result = String::new();
+    let mut total = 0;
+    for c in text.chars() {
+        total += width_char(&c, tab_width);
+        if total > max {
+            break;
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LineNumbers;
+    use crate::editor::FileContainer;
+    use regex::Regex;
+
+    /// Render a line of a document holding some text, once decorations have been added
+    fn render_with(text: &str, decorations: &str, y: usize) -> String {
+        let lua = Lua::new();
+        let mut editor = Editor::new(&lua).unwrap();
+        let line_numbers = editor.config.line_numbers.borrow_mut::<LineNumbers>();
+        line_numbers.unwrap().enabled = false;
+        let mut file = FileContainer::default();
+        file.doc.set_text(text);
+        file.highlighter.run(&file.doc.lines);
+        let decorations: Vec<mlua::Table> = lua.load(decorations).eval().unwrap();
+        for info in decorations {
+            file.add_decoration(&lua, "test", &info).unwrap();
         }
+        editor.files = FileLayout::Atom(vec![file], 0);
+        let syntax = config!(editor.config, syntax);
+        let size = Size { w: 30, h: 10 };
+        let line = editor.render_file(&[], y, size, &syntax).unwrap();
+        // (only the text on screen is of interest)
+        let codes = Regex::new(r"\x1b\[[0-9;:?]*[A-Za-z]").unwrap();
+        codes.replace_all(&line, "").trim_end().to_string()
+    }
+
+    #[test]
+    fn virtual_text_renders_after_the_end_of_lines() {
+        let decorations = r#"{
+            {kind = "virtual_text", y = 1, text = "first"},
+            {kind = "inlay", x = 5, y = 1, text = ": i32"},
+            {kind = "inlay", x = 1, y = 1, text = "|"},
+        }"#;
+        assert_eq!(render_with("let x", decorations, 0), "l|et x: i32 first");
+    }
+
+    #[test]
+    fn virtual_text_renders_on_empty_lines() {
+        let decorations = r#"{
+            {kind = "virtual_text", y = 2, text = "nothing here"},
+            {kind = "inlay", x = 0, y = 2, text = ">"},
+        }"#;
+        assert_eq!(
+            render_with("one\n\nthree", decorations, 1),
+            "> nothing here"
+        );
+        assert_eq!(render_with("one\n\nthree", decorations, 2), "three");
+    }
+
+    #[test]
+    fn virtual_text_is_cut_short_to_fit() {
+        let decorations =
+            r#"{{kind = "virtual_text", y = 1, text = "a very long message indeed"}}"#;
+        let line = render_with("some text here", decorations, 0);
+        assert_eq!(line, "some text here a very long mes");
+        // (lines too long to fit have their end off screen)
+        let line = render_with(&"x".repeat(40), decorations, 0);
+        assert_eq!(line, "x".repeat(30));
     }
 }
@@ -0,0 +1,462 @@
+/// Editing a single line of text, for the command line and prompts
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::utils::width;
+use std::path::MAIN_SEPARATOR;
+
+/// A line of text being typed in, along with where the cursor is in it
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LineEditor {
+    /// The text that has been typed in
+    pub text: String,
+    /// Where the cursor is (in characters)
+    pub cursor: usize,
+    /// What was typed in before (most recent first), and which entry is being shown
+    history: Vec<String>,
+    recalled: Option<usize>,
+    /// What was being typed before going back through the history
+    draft: String,
+}
+
+impl LineEditor {
+    /// Start editing, with earlier entries that can be brought back with the up and down keys
+    pub fn with_history(history: Vec<String>) -> Self {
+        Self {
+            history,
+            ..Self::default()
+        }
+    }
+
+    /// Replace the text (moving the cursor to the end)
+    pub fn set<S: Into<String>>(&mut self, text: S) {
+        self.text = text.into();
+        self.cursor = self.length();
+    }
+
+    /// The length of the text (in characters)
+    fn length(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    /// Find where a character starts in the text
+    fn byte_index(&self, idx: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(idx)
+            .map_or(self.text.len(), |(byte, _)| byte)
+    }
+
+    /// The text before the cursor
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..self.byte_index(self.cursor)]
+    }
+
+    /// The text from the cursor onwards
+    pub fn after_cursor(&self) -> &str {
+        &self.text[self.byte_index(self.cursor)..]
+    }
+
+    /// How far across the cursor is on the screen
+    pub fn cursor_width(&self, tab_width: usize) -> usize {
+        width(self.before_cursor(), tab_width)
+    }
+
+    /// Type a character in at the cursor
+    pub fn insert(&mut self, c: char) {
+        let idx = self.byte_index(self.cursor);
+        self.text.insert(idx, c);
+        self.cursor += 1;
+    }
+
+    /// Type some text in at the cursor
+    pub fn insert_str(&mut self, text: &str) {
+        let idx = self.byte_index(self.cursor);
+        self.text.insert_str(idx, text);
+        self.cursor += text.chars().count();
+    }
+
+    /// Put pasted text in at the cursor (with any lines in it joined together)
+    pub fn paste(&mut self, text: &str) {
+        self.insert_str(&text.lines().collect::<Vec<_>>().join(" "));
+    }
+
+    /// Replace the characters from a point up to the cursor with some other text
+    pub fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        let (start, end) = (self.byte_index(start), self.byte_index(self.cursor));
+        self.text.replace_range(start..end, text);
+        self.cursor = self.text[..start + text.len()].chars().count();
+    }
+
+    /// Remove the character before the cursor
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.delete();
+        }
+    }
+
+    /// Remove the character under the cursor
+    pub fn delete(&mut self) {
+        if self.cursor < self.length() {
+            self.text.remove(self.byte_index(self.cursor));
+        }
+    }
+
+    /// Remove everything between the cursor and another point
+    pub fn delete_to(&mut self, other: usize) {
+        let (start, end) = (self.cursor.min(other), self.cursor.max(other));
+        let range = self.byte_index(start)..self.byte_index(end);
+        self.text.replace_range(range, "");
+        self.cursor = start;
+    }
+
+    /// Find where the word before the cursor starts
+    /// (punctuation counts as a gap between words, so paths are gone through a folder at a time)
+    pub fn word_start(&self) -> usize {
+        let chars: Vec<char> = self.before_cursor().chars().collect();
+        let mut idx = chars.len();
+        while idx > 0 && !is_word(chars[idx - 1]) {
+            idx -= 1;
+        }
+        while idx > 0 && is_word(chars[idx - 1]) {
+            idx -= 1;
+        }
+        idx
+    }
+
+    /// Find where the word after the cursor ends
+    pub fn word_end(&self) -> usize {
+        let mut chars = self.after_cursor().chars().peekable();
+        let mut idx = self.cursor;
+        while chars.next_if(|c| !is_word(*c)).is_some() {
+            idx += 1;
+        }
+        while chars.next_if(|c| is_word(*c)).is_some() {
+            idx += 1;
+        }
+        idx
+    }
+
+    /// Bring back an earlier entry from the history
+    pub fn history_back(&mut self) {
+        let idx = self.recalled.map_or(0, |idx| idx + 1);
+        if let Some(entry) = self.history.get(idx).cloned() {
+            if self.recalled.is_none() {
+                self.draft.clone_from(&self.text);
+            }
+            self.recalled = Some(idx);
+            self.set(entry);
+        }
+    }
+
+    /// Go forward through the history (back to what was being typed in at the end)
+    pub fn history_forward(&mut self) {
+        match self.recalled {
+            Some(0) => {
+                self.recalled = None;
+                self.set(self.draft.clone());
+            }
+            Some(idx) => {
+                self.recalled = Some(idx - 1);
+                self.set(self.history[idx - 1].clone());
+            }
+            None => (),
+        }
+    }
+
+    /// Complete the text from where the candidates start up to the cursor
+    /// (as far as all the candidates agree, or all the way if there is only one, in which case
+    /// `finish` is put after it, unless it's a folder)
+    /// Returns the candidates to cycle through if there is more than one
+    pub fn complete(
+        &mut self,
+        completions: Completions,
+        current: &str,
+        finish: &str,
+    ) -> Option<Completions> {
+        let quote = completions.quote;
+        match completions.candidates.as_slice() {
+            [] => None,
+            [candidate] => {
+                let mut text = quote(candidate);
+                if !candidate.ends_with(['/', MAIN_SEPARATOR]) {
+                    text.push_str(finish);
+                }
+                self.replace_before_cursor(completions.start, &text);
+                None
+            }
+            candidates => {
+                let prefix = common_prefix(candidates);
+                if prefix.len() > current.len() {
+                    self.replace_before_cursor(completions.start, &quote(&prefix));
+                }
+                Some(completions)
+            }
+        }
+    }
+
+    /// Handle a key press that edits the text or moves the cursor
+    /// (returns false if the key doesn't do anything here)
+    pub fn handle_key(&mut self, modifiers: KMod, code: KCode) -> bool {
+        match (modifiers, code) {
+            (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => self.insert(c),
+            (KMod::NONE, KCode::Backspace) => self.backspace(),
+            (KMod::NONE, KCode::Delete) => self.delete(),
+            (KMod::NONE, KCode::Left) => self.cursor = self.cursor.saturating_sub(1),
+            (KMod::NONE, KCode::Right) => self.cursor = (self.cursor + 1).min(self.length()),
+            (KMod::NONE, KCode::Home) | (KMod::CONTROL, KCode::Char('a')) => self.cursor = 0,
+            (KMod::NONE, KCode::End) | (KMod::CONTROL, KCode::Char('e')) => {
+                self.cursor = self.length();
+            }
+            // Moving and deleting a word at a time
+            (KMod::CONTROL, KCode::Left) | (KMod::ALT, KCode::Char('b')) => {
+                self.cursor = self.word_start();
+            }
+            (KMod::CONTROL, KCode::Right) | (KMod::ALT, KCode::Char('f')) => {
+                self.cursor = self.word_end();
+            }
+            (KMod::CONTROL, KCode::Backspace | KCode::Char('w'))
+            | (KMod::ALT, KCode::Backspace) => {
+                self.delete_to(self.word_start());
+            }
+            (KMod::CONTROL, KCode::Delete) | (KMod::ALT, KCode::Char('d')) => {
+                self.delete_to(self.word_end());
+            }
+            // Deleting everything before or after the cursor
+            (KMod::CONTROL, KCode::Char('u')) => self.delete_to(0),
+            (KMod::CONTROL, KCode::Char('k')) => self.delete_to(self.length()),
+            // Going through what was typed in before
+            (KMod::NONE, KCode::Up) => self.history_back(),
+            (KMod::NONE, KCode::Down) => self.history_forward(),
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Determine if a character is part of a word
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Candidates for the text before the cursor, which can be cycled through
+#[derive(Debug, Clone)]
+pub struct Completions {
+    /// Where the text being completed starts (in characters)
+    start: usize,
+    candidates: Vec<String>,
+    selected: Option<usize>,
+    /// Turns a candidate into the text that is put in (for example, quoting it)
+    quote: fn(&str) -> String,
+}
+
+impl Completions {
+    pub fn new(start: usize, candidates: Vec<String>, quote: fn(&str) -> String) -> Self {
+        Self {
+            start,
+            candidates,
+            selected: None,
+            quote,
+        }
+    }
+
+    /// Put the next (or previous) candidate in
+    pub fn cycle(&mut self, line: &mut LineEditor, forward: bool) {
+        let len = self.candidates.len();
+        if len == 0 {
+            return;
+        }
+        let selected = match (self.selected, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(idx), true) => (idx + 1) % len,
+            (Some(idx), false) => (idx + len - 1) % len,
+        };
+        self.selected = Some(selected);
+        line.replace_before_cursor(self.start, &(self.quote)(&self.candidates[selected]));
+    }
+
+    /// List the candidates (with the selected one in brackets)
+    pub fn hint(&self) -> String {
+        let names = self.candidates.iter().enumerate().map(|(idx, candidate)| {
+            // (paths are shortened to their file names)
+            let trimmed = candidate.trim_end_matches(['/', MAIN_SEPARATOR]);
+            let name = trimmed
+                .rsplit(['/', MAIN_SEPARATOR])
+                .next()
+                .unwrap_or(trimmed);
+            let name = if trimmed.len() < candidate.len() {
+                format!("{name}{MAIN_SEPARATOR}")
+            } else {
+                name.to_string()
+            };
+            if Some(idx) == self.selected {
+                format!("[{name}]")
+            } else {
+                name
+            }
+        });
+        format!("   {}", names.collect::<Vec<_>>().join("  "))
+    }
+}
+
+/// Find the files and folders that a partly typed path could be
+/// (folders end in a separator, and hidden files are only given once a dot has been typed)
+pub fn complete_path(path: &str) -> Vec<String> {
+    let split = path.rfind(['/', MAIN_SEPARATOR]).map_or(0, |idx| idx + 1);
+    let (dir, name) = path.split_at(split);
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return vec![];
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| {
+            let file = entry.file_name().to_string_lossy().to_string();
+            if !file.starts_with(name) || (file.starts_with('.') && !name.starts_with('.')) {
+                return None;
+            }
+            let separator = if entry.path().is_dir() {
+                MAIN_SEPARATOR.to_string()
+            } else {
+                String::new()
+            };
+            Some(format!("{dir}{file}{separator}"))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Find the longest text that all the candidates start with
+fn common_prefix(candidates: &[String]) -> String {
+    let Some((first, rest)) = candidates.split_first() else {
+        return String::new();
+    };
+    let mut prefix = first.clone();
+    for candidate in rest {
+        let len = prefix
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(candidate.len()), |((idx, _), _)| idx);
+        prefix.truncate(len);
+    }
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A line with the cursor at a certain point
+    fn line_at(text: &str, cursor: usize) -> LineEditor {
+        let mut line = LineEditor::default();
+        line.set(text);
+        line.cursor = cursor;
+        line
+    }
+
+    #[test]
+    fn word_motions_stop_at_punctuation() {
+        let text = "open src/main.rs";
+        let mut cursor = text.chars().count();
+        let mut stops = vec![];
+        for _ in 0..5 {
+            cursor = line_at(text, cursor).word_start();
+            stops.push(cursor);
+        }
+        assert_eq!(stops, [14, 9, 5, 0, 0]);
+        let mut stops = vec![];
+        for _ in 0..5 {
+            cursor = line_at(text, cursor).word_end();
+            stops.push(cursor);
+        }
+        assert_eq!(stops, [4, 8, 13, 16, 16]);
+        // (positions are in characters rather than bytes)
+        assert_eq!(line_at("héllo wörld", 11).word_start(), 6);
+        assert_eq!(line_at("héllo wörld", 0).word_end(), 5);
+        assert_eq!(line_at("snake_case  ", 12).word_start(), 0);
+    }
+
+    #[test]
+    fn words_are_deleted_with_keys() {
+        let cases = [
+            (
+                "ab cd/e.f",
+                9,
+                KMod::CONTROL,
+                KCode::Char('w'),
+                "ab cd/e.",
+                8,
+            ),
+            ("ab cd/ef.rs", 6, KMod::ALT, KCode::Backspace, "ab ef.rs", 3),
+            ("foo bar", 0, KMod::ALT, KCode::Char('d'), " bar", 0),
+            ("foo bar", 3, KMod::CONTROL, KCode::Delete, "foo", 3),
+            ("héllo wörld", 8, KMod::CONTROL, KCode::Char('u'), "rld", 0),
+            ("héllo wörld", 2, KMod::CONTROL, KCode::Char('k'), "hé", 2),
+            ("foo bar", 7, KMod::CONTROL, KCode::Left, "foo bar", 4),
+            ("foo bar", 0, KMod::ALT, KCode::Char('f'), "foo bar", 3),
+        ];
+        for (text, cursor, modifiers, code, after, after_cursor) in cases {
+            let mut line = line_at(text, cursor);
+            assert!(line.handle_key(modifiers, code));
+            assert_eq!(
+                (line.text.as_str(), line.cursor),
+                (after, after_cursor),
+                "{modifiers:?} {code:?} on '{text}' at {cursor}"
+            );
+        }
+    }
+
+    #[test]
+    fn history_is_gone_through_and_back_to_the_draft() {
+        let mut line = LineEditor::with_history(vec!["newer".to_string(), "older".to_string()]);
+        line.insert_str("draft");
+        let mut seen = vec![];
+        for code in [
+            KCode::Up,
+            KCode::Up,
+            KCode::Up,
+            KCode::Down,
+            KCode::Down,
+            KCode::Down,
+        ] {
+            line.handle_key(KMod::NONE, code);
+            seen.push((line.text.clone(), line.cursor));
+        }
+        let expected = [
+            ("newer", 5),
+            ("older", 5),
+            ("older", 5),
+            ("newer", 5),
+            ("draft", 5),
+            ("draft", 5),
+        ];
+        assert_eq!(
+            seen,
+            expected.map(|(text, cursor)| (text.to_string(), cursor))
+        );
+        // (with no history the text is left alone)
+        let mut line = line_at("typed", 5);
+        line.history_back();
+        line.history_forward();
+        assert_eq!(line.text, "typed");
+    }
+
+    #[test]
+    fn common_prefixes_are_found() {
+        let cases: [(&[&str], &str); 8] = [
+            (&[], ""),
+            (&["abc"], "abc"),
+            (&["abc", "abd"], "ab"),
+            (&["abc", "ab", "abcd"], "ab"),
+            (&["abc", "xyz"], ""),
+            (&["src/", "src/main.rs"], "src/"),
+            (&["héllo", "hélp"], "hél"),
+            (&["é", "e"], ""),
+        ];
+        for (candidates, expected) in cases {
+            let candidates: Vec<String> = candidates.iter().map(ToString::to_string).collect();
+            assert_eq!(common_prefix(&candidates), expected, "{candidates:?}");
+        }
+    }
+}
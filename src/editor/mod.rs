@@ -1,37 +1,55 @@
 /// Main functionality of the editor
 use crate::config;
-use crate::config::{Config, Indentation};
+use crate::config::{Config, DocumentSettings, Indentation};
 use crate::error::{OxError, Result};
 use crate::ui::{size, Feedback, Terminal};
 use crossterm::event::{
-    Event as CEvent, KeyCode as KCode, KeyModifiers as KMod, MouseEvent, MouseEventKind,
+    Event as CEvent, KeyCode as KCode, KeyEvent, KeyModifiers as KMod, MouseEvent, MouseEventKind,
 };
 use kaolinite::event::Error as KError;
 use kaolinite::utils::{file_or_dir, get_absolute_path, get_file_name};
 use kaolinite::{Document, Loc};
 use mlua::{Error as LuaError, Lua};
+use std::collections::VecDeque;
 use std::env;
 use std::io::ErrorKind;
 use std::path::Path;
 use std::time::Instant;
 use synoptic::Highlighter;
 
+mod buffers;
 mod cursor;
+pub mod decorations;
 mod documents;
 mod editing;
 mod filetree;
 mod filetypes;
+mod floating;
+mod history;
+mod hooks;
 mod interface;
+mod line_editor;
 mod macros;
 mod mouse;
+mod runner;
 mod scanning;
+mod session;
+mod settings;
 
+pub use buffers::Scratch;
 pub use cursor::{allowed_by_multi_cursor, handle_multiple_cursors};
+pub use decorations::{DecorationKind, Decorations};
 pub use documents::{FileContainer, FileLayout};
 pub use filetree::{FTParts, FileTree};
-pub use filetypes::{FileType, FileTypes};
+pub use filetypes::{FileType, FileTypeSettings, FileTypes};
+pub use floating::{lines_from_lua, Anchor, FloatingWindow, FloatingWindows};
+pub use history::{display_path, History};
+pub use hooks::Hook;
 pub use interface::RenderCache;
+pub use line_editor::{complete_path, Completions, LineEditor};
 pub use macros::MacroMan;
+pub use runner::TaskRunner;
+pub use session::{load_session, session_path, write_session};
 
 /// For managing all editing and rendering of cactus
 #[allow(clippy::struct_excessive_bools)]
@@ -52,14 +70,16 @@ pub struct Editor {
     pub greet: bool,
     /// The feedback message to display below the status line
     pub feedback: Feedback,
-    /// Will be some if there is an outstanding command to be run
-    pub command: Option<String>,
+    /// true if the command line should be opened once the current event has been handled
+    pub command_line: bool,
     /// Will store the last time the editor was interacted with (to track inactivity)
     pub last_active: Instant,
     /// Used for storing amount to push document down
     pub push_down: usize,
     /// Used to cache the location of the configuration file
     pub config_path: String,
+    /// The configuration file of the project ox was started in (once the user trusts it)
+    pub project_config: Option<String>,
     /// Flag to determine whether or not the editor is under control by a plug-in
     pub plugin_active: bool,
     /// Flag to determine whether or not the editor is pasting
@@ -78,6 +98,27 @@ pub struct Editor {
     pub file_tree_selection: Option<String>,
     /// For caching a pointer to go back to when in a file tree
     pub old_ptr: Vec<usize>,
+    /// Keeps track of project tasks that have been run
+    pub task_runner: TaskRunner,
+    /// Lifecycle events waiting to be sent to plug-ins
+    pub hooks: Vec<Hook>,
+    /// Floating windows opened by plug-ins
+    pub floating: FloatingWindows,
+    /// Used to give each file container a unique id
+    pub next_buffer_id: usize,
+    /// Listens for files and lua code sent over from other ox processes
+    #[cfg(not(target_os = "windows"))]
+    pub remote: Option<crate::remote::Server>,
+    /// Where to save the session when the editor closes (None if it shouldn't be saved)
+    pub session_path: Option<std::path::PathBuf>,
+    /// The session from before the user started closing things (so it can be saved on exit)
+    pub session_snapshot: Option<serde_json::Value>,
+    /// true if the last key press closed something
+    pub closing: bool,
+    /// Where the user was in each file, and which files and projects were used recently
+    pub history: History,
+    /// Keys from a broken key sequence that a prompt has yet to handle
+    pub prompt_replay: VecDeque<KeyEvent>,
 }
 
 impl Editor {
@@ -93,10 +134,11 @@ impl Editor {
             greet: false,
             needs_rerender: true,
             feedback: Feedback::None,
-            command: None,
+            command_line: false,
             last_active: Instant::now(),
             push_down: 1,
             config_path: "~/.oxrc".to_string(),
+            project_config: None,
             plugin_active: false,
             pasting: false,
             last_click: None,
@@ -106,6 +148,17 @@ impl Editor {
             file_tree: None,
             file_tree_selection: None,
             old_ptr: vec![],
+            task_runner: TaskRunner::default(),
+            hooks: vec![],
+            floating: FloatingWindows::default(),
+            next_buffer_id: 0,
+            #[cfg(not(target_os = "windows"))]
+            remote: None,
+            session_path: None,
+            session_snapshot: None,
+            closing: false,
+            history: History::default(),
+            prompt_replay: VecDeque::new(),
         })
     }
 
@@ -120,7 +173,8 @@ impl Editor {
         let mut size = size()?;
         size.h = size.h.saturating_sub(1 + self.push_down);
         let mut doc = Document::new(size);
-        doc.set_tab_width(config!(self.config, document).tab_width);
+        let settings = self.document_settings(None, None);
+        doc.set_tab_width(settings.tab_width);
         doc.event_mgmt.force_not_with_disk = true;
         // Load all the lines within viewport into the document
         doc.load_to(size.h);
@@ -128,11 +182,16 @@ impl Editor {
         let mut highlighter = Highlighter::new(4);
         highlighter.run(&doc.lines);
         // Add document to documents
-        let file = FileContainer {
+        let mut file = FileContainer {
+            id: self.new_buffer_id(),
             highlighter,
             file_type: Some(FileType::default()),
             doc,
+            decorations: Decorations::default(),
+            scratch: None,
+            settings: DocumentSettings::default(),
         };
+        file.apply_settings(settings);
         if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
             if *ptr + 1 >= files.len() {
                 files.push(file);
@@ -206,28 +265,60 @@ impl Editor {
         size.h = size.h.saturating_sub(1 + self.push_down);
         let mut doc = Document::open(size, file_name)?;
         // Collect various data from the document
-        let tab_width = config!(self.config, document).tab_width;
-        let file_type = config!(self.config, document).file_types.identify(&mut doc);
+        let state = doc
+            .file_name
+            .as_ref()
+            .and_then(|n| get_absolute_path(n))
+            .and_then(|path| self.history.get(&path).cloned());
+        // (the file type the user chose last time wins over the one that is detected)
+        let chosen = state
+            .as_ref()
+            .and_then(|s| s.file_type.as_ref())
+            .and_then(|name| config!(self.config, document).file_types.get_name(name));
+        let file_type =
+            chosen.or_else(|| config!(self.config, document).file_types.identify(&mut doc));
+        let settings = self.document_settings(Some(file_name), file_type.as_ref());
+        let tab_width = settings.tab_width;
         // Set up the document
+        self.warn_about_charset(&settings);
         doc.set_tab_width(tab_width);
+        // (identifying the file type loaded the first line before the tab width was known)
+        doc.reload_lines();
         doc.load_to(size.h);
         // Update in the syntax highlighter
         let mut highlighter = file_type.as_ref().map_or(Highlighter::new(tab_width), |t| {
             t.get_highlighter(&self.config, tab_width)
         });
         highlighter.run(&doc.lines);
+        // Let plug-ins know
+        self.hook(Hook::Open(doc.file_name.clone()));
+        if let Some(file_type) = &file_type {
+            self.hook(Hook::FileType(file_type.name.clone()));
+        }
         // Add in the file
-        let file = FileContainer {
+        let mut file = FileContainer {
+            id: self.new_buffer_id(),
             doc,
             highlighter,
             file_type,
+            decorations: Decorations::default(),
+            scratch: None,
+            settings: DocumentSettings::default(),
         };
+        file.apply_settings(settings);
+        // Go back to where the user was last time
+        if let Some(state) = state {
+            file.restore_state(&state);
+        }
+        if let Some(path) = get_absolute_path(file_name) {
+            self.history.opened(&path);
+        }
         Ok(file)
     }
 
     /// Function to ask the user for a file to open
-    pub fn open_document(&mut self) -> Result<()> {
-        let path = self.path_prompt()?;
+    pub fn open_document(&mut self, lua: &Lua) -> Result<()> {
+        let path = self.path_prompt(lua)?;
         self.open(&path)?;
         self.next();
         self.update_cwd();
@@ -241,23 +332,36 @@ impl Editor {
             if os.kind() == ErrorKind::NotFound || os.kind() == ErrorKind::IsADirectory {
                 // Create a new document if not found
                 self.blank()?;
+                // Work out information for the document
+                let (mut path, mut file_type) = (None, None);
                 if let Some((files, _)) = self.files.get_atom_mut(self.ptr.clone()) {
                     let file = files.last_mut().unwrap();
                     file.doc.file_name = Some(file_name);
-                    // Work out information for the document
-                    let tab_width = config!(self.config, document).tab_width;
-                    let file_type = config!(self.config, document)
+                    path.clone_from(&file.doc.file_name);
+                    file_type = config!(self.config, document)
                         .file_types
                         .identify(&mut file.doc);
-                    // Set up the document
-                    file.doc.set_tab_width(tab_width);
+                }
+                // (the settings come from where the file is going to be)
+                let settings = self.document_settings(path.as_deref(), file_type.as_ref());
+                self.warn_about_charset(&settings);
+                if let Some((files, _)) = self.files.get_atom_mut(self.ptr.clone()) {
+                    let file = files.last_mut().unwrap();
+                    file.apply_settings(settings);
+                    let tab_width = file.settings.tab_width;
                     // Attach the correct highlighter
                     let highlighter = file_type.clone().map_or(Highlighter::new(tab_width), |t| {
                         t.get_highlighter(&self.config, tab_width)
                     });
                     file.highlighter = highlighter;
                     file.highlighter.run(&file.doc.lines);
-                    file.file_type = file_type;
+                    file.file_type.clone_from(&file_type);
+                    // Let plug-ins know
+                    let file_name = file.doc.file_name.clone();
+                    self.hook(Hook::Open(file_name));
+                    if let Some(file_type) = file_type {
+                        self.hook(Hook::FileType(file_type.name));
+                    }
                 }
                 Ok(())
             } else {
@@ -275,25 +379,46 @@ impl Editor {
 
     /// save the document to the disk
     pub fn save(&mut self) -> Result<()> {
-        if let Some(doc) = self.try_doc_mut() {
+        if self.scratch_focused() {
+            return Ok(());
+        }
+        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
             // Perform the save
-            doc.save()?;
+            file.prepare_save()?;
+            file.doc.save()?;
             // All done
+            self.hook(Hook::Save(self.hook_file_name()));
             self.feedback = Feedback::Info("Document saved successfully".to_string());
         }
         Ok(())
     }
 
     /// save the document to the disk at a specified path
-    pub fn save_as(&mut self) -> Result<()> {
+    pub fn save_as(&mut self, lua: &Lua) -> Result<()> {
+        if self.scratch_focused() {
+            return Ok(());
+        }
         if self.try_doc().is_some() {
-            let file_name = self.prompt("Save as")?;
-            self.try_doc_mut().unwrap().save_as(&file_name)?;
+            let file_name = self.prompt(lua, "Save as", Some("path"))?;
+            // Unnamed files pick up the settings for where they are being saved to
+            let unnamed = self.try_doc().unwrap().file_name.is_none();
+            if unnamed {
+                let file_type = config!(self.config, document)
+                    .file_types
+                    .identify_from_path(&file_name);
+                let settings = self.document_settings(Some(&file_name), file_type.as_ref());
+                self.warn_about_charset(&settings);
+                let file = self.files.get_mut(self.ptr.clone()).unwrap();
+                file.apply_settings(settings);
+            }
+            let file = self.files.get_mut(self.ptr.clone()).unwrap();
+            file.prepare_save()?;
+            file.doc.save_as(&file_name)?;
             // If this file is currently unnamed, give it a name, syntax highlighting and a type
-            if self.try_doc().unwrap().file_name.is_none() {
-                let tab_width = config!(self.config, document).tab_width;
+            if unnamed {
                 if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
                     let file = files.get_mut(*ptr).unwrap();
+                    let tab_width = file.settings.tab_width;
                     // Set the file name
                     file.doc.file_name = Some(file_name.clone());
                     // Update the file type
@@ -313,10 +438,13 @@ impl Editor {
                     file.doc.event_mgmt.force_not_with_disk = false;
                     file.doc.event_mgmt.disk_write(&file.doc.take_snapshot());
                 }
+                // (the file type may only have been recognisable from the file's name or contents)
+                self.refresh_settings();
             }
             // Commit events to event manager (for undo / redo)
             self.try_doc_mut().unwrap().commit();
             // All done
+            self.hook(Hook::Save(Some(file_name.clone())));
             self.feedback = Feedback::Info(format!("Document saved as {file_name} successfully"));
         }
         Ok(())
@@ -325,11 +453,16 @@ impl Editor {
     /// Save all the open documents to the disk
     pub fn save_all(&mut self) -> Result<()> {
         if let Some((files, _)) = self.files.get_atom_mut(self.ptr.clone()) {
-            for file in files {
+            let mut saved = vec![];
+            // Scratch buffers aren't backed by a file
+            for file in files.iter_mut().filter(|file| file.scratch.is_none()) {
+                file.prepare_save()?;
                 file.doc.save()?;
                 // Commit events to event manager (for undo / redo)
                 file.doc.commit();
+                saved.push(Hook::Save(file.doc.file_name.clone()));
             }
+            self.hooks.append(&mut saved);
         }
         self.feedback = Feedback::Info("Saved all documents".to_string());
         Ok(())
@@ -337,16 +470,31 @@ impl Editor {
 
     /// Quit the editor
     pub fn quit(&mut self) -> Result<()> {
+        // Remember the layout before it gets taken apart, in case the editor is being closed
+        if self.session_path.is_some() && self.session_snapshot.is_none() {
+            self.session_snapshot = Some(self.session());
+        }
+        self.closing = true;
         match self.files.get_raw(self.ptr.clone()) {
             Some(FileLayout::Atom(fcs, ptr)) => {
                 let last_file = fcs.len() == 1;
                 // Remove the file that is currently open and selected
                 let msg =
                     "This document isn't saved, press Ctrl + Q to force quit or Esc to cancel";
-                let doc = &fcs[*ptr].doc;
-                if doc.event_mgmt.with_disk(&doc.take_snapshot()) || self.confirm(msg)? {
+                if !fcs[*ptr].modified() || self.confirm(msg)? {
                     let (fcs, ptr) = self.files.get_atom_mut(self.ptr.clone()).unwrap();
-                    fcs.remove(*ptr);
+                    let closed = fcs.remove(*ptr);
+                    if closed.scratch.is_none() {
+                        self.history.remember(&closed.doc);
+                    }
+                    // Key bindings of scratch buffers are no longer needed
+                    if let Some(scratch) = closed.scratch {
+                        let mut tm = self.config.task_manager.lock().unwrap();
+                        for callback in scratch.keymaps.values() {
+                            tm.release_callback(*callback);
+                        }
+                    }
+                    self.hook(Hook::Close(closed.doc.file_name));
                     self.prev();
                 }
                 // Perform cleanup / pointer reassignment if this atom is now empty
@@ -433,7 +581,7 @@ impl Editor {
     /// Load the configuration values
     pub fn load_config(&mut self, path: &str, lua: &Lua) -> Option<LuaError> {
         self.config_path = path.to_string();
-        let result = Config::read(path, lua);
+        let result = Config::read(path, self.project_config.as_deref(), lua);
         // Display any warnings if the user configuration couldn't be found
         match result {
             Ok(()) => (),
@@ -463,7 +611,7 @@ impl Editor {
         };
         // Pass event down to special handlers
         match event {
-            CEvent::Key(key) => self.handle_key_event(key.modifiers, key.code)?,
+            CEvent::Key(key) => self.handle_key_event(lua, key.modifiers, key.code)?,
             CEvent::Resize(_, _) => self.handle_resize(lua)?,
             CEvent::Mouse(mouse_event) => self.handle_mouse_event(lua, mouse_event)?,
             CEvent::Paste(text) => self.handle_paste(&text)?,
@@ -473,7 +621,7 @@ impl Editor {
     }
 
     /// Handle key event
-    pub fn handle_key_event(&mut self, modifiers: KMod, code: KCode) -> Result<()> {
+    pub fn handle_key_event(&mut self, lua: &Lua, modifiers: KMod, code: KCode) -> Result<()> {
         match self.files.get_raw_mut(self.ptr.clone()) {
             // File tree key behaviour
             Some(FileLayout::FileTree) => match (modifiers, code) {
@@ -483,10 +631,10 @@ impl Editor {
                 (KMod::CONTROL, KCode::Up) => self.file_tree_move_to_top(),
                 (KMod::CONTROL, KCode::Down) => self.file_tree_move_to_bottom(),
                 (KMod::CONTROL, KCode::Enter) => self.file_tree_move_into(),
-                (KMod::NONE, KCode::Char('n')) => self.file_tree_new()?,
-                (KMod::NONE, KCode::Char('d')) => self.file_tree_delete()?,
-                (KMod::NONE, KCode::Char('m')) => self.file_tree_move()?,
-                (KMod::NONE, KCode::Char('c')) => self.file_tree_copy()?,
+                (KMod::NONE, KCode::Char('n')) => self.file_tree_new(lua)?,
+                (KMod::NONE, KCode::Char('d')) => self.file_tree_delete(lua)?,
+                (KMod::NONE, KCode::Char('m')) => self.file_tree_move(lua)?,
+                (KMod::NONE, KCode::Char('c')) => self.file_tree_copy(lua)?,
                 _ => (),
             },
             // Terminal behaviour
@@ -506,7 +654,7 @@ impl Editor {
                 let end = Instant::now();
                 let inactivity = end.duration_since(self.last_active).as_millis() as usize;
                 // Commit if over user-defined period of inactivity
-                if inactivity > config!(self.config, document).undo_period * 1000 {
+                if inactivity > self.focused_settings().undo_period * 1000 {
                     self.try_doc_mut().unwrap().commit();
                 }
                 // Register this activity
@@ -561,11 +709,11 @@ impl Editor {
 
     /// Handle tab character being inserted
     pub fn handle_tab(&mut self) -> Result<()> {
-        if config!(self.config, document).indentation == Indentation::Tabs {
+        let settings = self.focused_settings();
+        if settings.indentation == Indentation::Tabs {
             self.character('\t')?;
         } else {
-            let tab_width = config!(self.config, document).tab_width;
-            for _ in 0..tab_width {
+            for _ in 0..settings.indent_size {
                 self.character(' ')?;
             }
         }
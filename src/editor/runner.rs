@@ -0,0 +1,298 @@
+/// Utilities for running project tasks
+use crate::config::{
+    find_project_tasks, is_trusted, load_tasks, project_root, shell_quote, task_order, trust,
+    TaskVariables,
+};
+#[cfg(not(target_os = "windows"))]
+use crate::editor::{FileLayout, Hook};
+#[cfg(not(target_os = "windows"))]
+use crate::pty::Pty;
+use crate::{config, Editor, Feedback, Result};
+use kaolinite::utils::{get_absolute_path, get_cwd, get_parent};
+use mlua::Lua;
+use std::cell::Cell;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+/// Where a task is running
+#[derive(Debug)]
+pub enum TaskHandle {
+    /// Task is running in the foreground (within a terminal split)
+    #[cfg(not(target_os = "windows"))]
+    Terminal(Arc<Mutex<Pty>>),
+    /// Task is running in the background
+    /// (will hold the exit code, and the last line it printed, once finished)
+    Background(Arc<Mutex<Option<(i32, String)>>>),
+}
+
+/// Keeps track of the most recently launched task
+#[derive(Debug, Default)]
+pub struct TaskRunner {
+    pub last: Option<(String, TaskHandle)>,
+    /// The exit code of the most recent task (once it is known)
+    exit_code: Cell<Option<i32>>,
+    /// How much of the terminal output has been searched for the exit code
+    scanned: Cell<usize>,
+}
+
+impl TaskRunner {
+    /// Keep track of a task that has just been launched
+    pub fn start(&mut self, name: &str, handle: TaskHandle) {
+        self.last = Some((name.to_string(), handle));
+        self.exit_code.set(None);
+        self.scanned.set(0);
+    }
+
+    /// Find the exit code of the most recent task (if it has finished)
+    pub fn exit_code(&self) -> Option<i32> {
+        if self.exit_code.get().is_none() {
+            self.exit_code.set(match &self.last {
+                #[cfg(not(target_os = "windows"))]
+                Some((name, TaskHandle::Terminal(term))) => {
+                    self.scan(&term.lock().unwrap().output, &finish_marker(name))
+                }
+                Some((_, TaskHandle::Background(result))) => {
+                    result.lock().unwrap().as_ref().map(|(code, _)| *code)
+                }
+                None => None,
+            });
+        }
+        self.exit_code.get()
+    }
+
+    /// Look for the exit code in the terminal output that hasn't been searched yet
+    /// (along with enough of what came before it to catch a message that arrived in pieces)
+    #[cfg(not(target_os = "windows"))]
+    fn scan(&self, output: &str, marker: &str) -> Option<i32> {
+        let mut start = self
+            .scanned
+            .get()
+            .min(output.len())
+            .saturating_sub(marker.len() + 12);
+        while !output.is_char_boundary(start) {
+            start -= 1;
+        }
+        self.scanned.set(output.len());
+        output[start..].match_indices(marker).find_map(|(at, _)| {
+            let rest = &output[start + at + marker.len()..];
+            let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            // (the status is only complete once something has been printed after it)
+            (digits.len() < rest.len()).then(|| digits.parse().ok())?
+        })
+    }
+
+    /// Describe the state of the most recent task (for use in the status line)
+    pub fn status(&self) -> String {
+        let Some((name, _)) = &self.last else {
+            return String::new();
+        };
+        match self.exit_code() {
+            None => format!("{name}: running"),
+            Some(0) => format!("{name}: succeeded"),
+            Some(code) => match self.last_output() {
+                Some(line) => format!("{name}: failed ({code}): {line}"),
+                None => format!("{name}: failed ({code})"),
+            },
+        }
+    }
+
+    /// The last line a finished background task printed (tasks in a terminal can be read there)
+    fn last_output(&self) -> Option<String> {
+        match &self.last {
+            Some((_, TaskHandle::Background(result))) => {
+                let result = result.lock().unwrap();
+                result
+                    .as_ref()
+                    .map(|(_, line)| line.clone())
+                    .filter(|l| !l.is_empty())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The message printed once a task has finished
+#[cfg(not(target_os = "windows"))]
+fn finish_marker(name: &str) -> String {
+    format!("Task '{name}' finished with exit status ")
+}
+
+impl Editor {
+    /// Work out the values of the variables that tasks can use
+    pub fn task_variables(&self) -> TaskVariables {
+        let cwd = get_cwd().unwrap_or_default();
+        let mut vars = TaskVariables {
+            project_root: project_root(&cwd).unwrap_or(cwd.clone()),
+            file_dir: cwd,
+            ..TaskVariables::default()
+        };
+        if let Some(doc) = self.try_doc() {
+            if let Some(path) = get_absolute_path(&doc.file_name.clone().unwrap_or_default()) {
+                vars.file_dir = get_parent(&path).unwrap_or(vars.file_dir);
+                vars.project_root = project_root(&vars.file_dir).unwrap_or(vars.project_root);
+                vars.file_path = path;
+            }
+            vars.line = doc.loc().y + 1;
+            vars.selection = doc.selection_text();
+        }
+        vars
+    }
+
+    /// Check tasks can be run here, warning the user if they can't
+    /// (task commands are run by a POSIX shell, which Windows doesn't have)
+    fn tasks_supported(&mut self) -> bool {
+        if cfg!(target_os = "windows") {
+            self.feedback = Feedback::Warning("Tasks aren't supported on Windows".to_string());
+            return false;
+        }
+        true
+    }
+
+    /// Get the names of all the tasks available in this project
+    pub fn task_names(&self, lua: &Lua) -> Result<Vec<String>> {
+        let root = self.task_variables().project_root;
+        Ok(load_tasks(lua, Some(&root))?.into_keys().collect())
+    }
+
+    /// Ask the user whether to trust the tasks a project provides (if they haven't already)
+    pub fn trust_project_tasks(&mut self, lua: &Lua) -> Result<()> {
+        let root = self.task_variables().project_root;
+        if let Some(path) = find_project_tasks(&root).filter(|path| !is_trusted(path)) {
+            let msg = format!("Trust the tasks in {}? (y/n)", path.display());
+            if self
+                .prompt(lua, msg, None)?
+                .trim()
+                .eq_ignore_ascii_case("y")
+            {
+                trust(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a task (along with all of its dependencies)
+    pub fn run_task(&mut self, lua: &Lua, name: &str) -> Result<()> {
+        if !self.tasks_supported() {
+            return Ok(());
+        }
+        self.trust_project_tasks(lua)?;
+        let vars = self.task_variables();
+        let tasks = load_tasks(lua, Some(&vars.project_root))?;
+        let order = task_order(&tasks, name)?;
+        // Build up a script that runs each task in turn, stopping upon failure
+        let script = order
+            .iter()
+            .map(|name| {
+                let task = &tasks[name];
+                let cwd = task.cwd.as_ref().map_or(vars.project_root.clone(), |cwd| {
+                    Path::new(&vars.project_root)
+                        .join(vars.substitute(cwd))
+                        .to_string_lossy()
+                        .to_string()
+                });
+                let mut step = vec![format!("cd {}", shell_quote(&cwd))];
+                step.extend(task.env.iter().map(|(key, value)| {
+                    format!("export {key}={}", shell_quote(&vars.substitute(value)))
+                }));
+                step.push(vars.substitute_quoted(&task.command));
+                format!("({})", step.join(" && "))
+            })
+            .collect::<Vec<_>>()
+            .join(" && ");
+        #[cfg(not(target_os = "windows"))]
+        if !tasks[name].background {
+            return self.run_task_in_terminal(lua, name, &script);
+        }
+        // Run in the background, recording the exit code and the last line of output when finished
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("({script}) 2>&1"))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let result = Arc::new(Mutex::new(None));
+        let result_clone = Arc::clone(&result);
+        std::thread::spawn(move || {
+            let mut last = String::new();
+            if let Some(stdout) = child.stdout.take() {
+                for line in BufReader::new(stdout)
+                    .lines()
+                    .map_while(std::io::Result::ok)
+                {
+                    if !line.trim().is_empty() {
+                        line.trim().clone_into(&mut last);
+                    }
+                }
+            }
+            let status = child.wait().ok().and_then(|s| s.code()).unwrap_or(-1);
+            *result_clone.lock().unwrap() = Some((status, last));
+        });
+        self.task_runner.start(name, TaskHandle::Background(result));
+        self.feedback = Feedback::Info(format!("Started task '{name}' in the background"));
+        Ok(())
+    }
+
+    /// Run a task script in a new terminal split
+    #[cfg(not(target_os = "windows"))]
+    fn run_task_in_terminal(&mut self, lua: &Lua, name: &str, script: &str) -> Result<()> {
+        let shell = config!(self.config, terminal).shell;
+        let term = Pty::new(shell)?;
+        self.ptr = self
+            .files
+            .open_right(self.ptr.clone(), FileLayout::Terminal(term.clone()));
+        self.cache_old_ptr(&self.ptr.clone());
+        self.hook(Hook::SplitOpen);
+        self.render(lua)?;
+        // (only the status variable is left for the shell to expand)
+        let command = format!(
+            "sh -c {}; echo {}{}\n",
+            shell_quote(script),
+            shell_quote(&finish_marker(name)),
+            shell.status_variable()
+        );
+        term.lock().unwrap().run_command(&command)?;
+        self.task_runner.start(name, TaskHandle::Terminal(term));
+        Ok(())
+    }
+
+    /// Allow the user to pick a task to run
+    pub fn pick_task(&mut self, lua: &Lua) -> Result<()> {
+        if !self.tasks_supported() {
+            return Ok(());
+        }
+        self.trust_project_tasks(lua)?;
+        let names = self.task_names(lua)?;
+        if names.is_empty() {
+            self.feedback = Feedback::Warning("No tasks are defined for this project".to_string());
+            return Ok(());
+        }
+        let name = self.select_prompt("Task", &names)?;
+        self.run_task(lua, &name)
+    }
+}
+
+#[cfg(test)]
+#[cfg(not(target_os = "windows"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_is_found_in_output_that_arrives_in_pieces() {
+        let runner = TaskRunner::default();
+        let marker = finish_marker("build");
+        // (the command itself is echoed before the status is known)
+        let mut output = format!("sh -c 'make'; echo \"{marker}$?\"\r\n");
+        assert_eq!(runner.scan(&output, &marker), None);
+        output.push_str("compiling...\r\n");
+        output.push_str(&marker[..10]);
+        assert_eq!(runner.scan(&output, &marker), None);
+        output.push_str(&marker[10..]);
+        output.push_str("12");
+        assert_eq!(runner.scan(&output, &marker), None);
+        output.push_str("7\r\n$ ");
+        assert_eq!(runner.scan(&output, &marker), Some(127));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::editor::LineEditor;
 /// Functions for searching and replacing
 use crate::error::{OxError, Result};
 use crate::events::wait_for_event_hog;
@@ -23,7 +24,7 @@ impl Editor {
         let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
         let cache = self.try_doc().unwrap().char_loc();
         // Prompt for a search term
-        let mut target = String::new();
+        let mut line = LineEditor::with_history(self.history.prompt_history("search"));
         let mut done = false;
         while !done {
             let Size { w, h } = size()?;
@@ -36,8 +37,9 @@ impl Editor {
                 self,
                 editor_bg,
                 "Search: ",
-                target.clone(),
+                line.before_cursor().to_string(),
                 "│",
+                line.after_cursor().to_string(),
                 " ".to_string().repeat(w)
             );
             // Move back to correct cursor position
@@ -48,9 +50,8 @@ impl Editor {
                 self.terminal.hide_cursor();
             }
             self.terminal.flush()?;
-            if let Some((modifiers, code)) =
-                key_event(&wait_for_event_hog(self), &mut self.macro_man)
-            {
+            let old = line.text.clone();
+            if let Some((modifiers, code)) = self.prompt_key(lua, &mut line) {
                 match (modifiers, code) {
                     // Exit the menu when the enter key is pressed
                     (KMod::NONE, KCode::Enter) => done = true,
@@ -60,22 +61,20 @@ impl Editor {
                         self.try_doc_mut().unwrap().cancel_selection();
                         return Err(OxError::Cancelled);
                     }
-                    // Remove from the input string if the user presses backspace
-                    (KMod::NONE, KCode::Backspace) => {
-                        target.pop();
-                        self.try_doc_mut().unwrap().move_to(&cache);
-                        self.next_match(&target);
-                    }
-                    // Add to the input string if the user presses a character
-                    (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => {
-                        target.push(c);
-                        self.try_doc_mut().unwrap().move_to(&cache);
-                        self.next_match(&target);
+                    // Edit the search term
+                    _ => {
+                        line.handle_key(modifiers, code);
                     }
-                    _ => (),
                 }
             }
+            // Look for the search term again if it has changed
+            if line.text != old {
+                self.try_doc_mut().unwrap().move_to(&cache);
+                self.next_match(&line.text);
+            }
         }
+        self.history.add_prompt_entry("search", &line.text);
+        let target = line.text;
 
         // Main body of the search feature
         let mut done = false;
@@ -171,8 +170,8 @@ impl Editor {
         // Gather data
         let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
         // Request replace information
-        let target = self.prompt("Replace")?;
-        let into = self.prompt("With")?;
+        let target = self.prompt(lua, "Replace", Some("replace"))?;
+        let into = self.prompt(lua, "With", Some("replace_with"))?;
         let mut done = false;
         let Size { w, h } = size()?;
         // Jump to match
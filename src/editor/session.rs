@@ -0,0 +1,324 @@
+/// Saving the layout of the editor to disk, so that it can be picked up again later
+use crate::config;
+use crate::config::{config_dir, project_root};
+use crate::editor::{Editor, FileLayout, FileTree};
+use crate::error::Result;
+#[cfg(not(target_os = "windows"))]
+use crate::pty::Pty;
+use kaolinite::utils::{get_absolute_path, get_cwd};
+use kaolinite::Loc;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Work out where a session is stored
+/// (named sessions are shared, otherwise there is one per project or directory)
+pub fn session_path(name: Option<&str>) -> Option<PathBuf> {
+    let dir = config_dir()?.join("sessions");
+    let name = if let Some(name) = name {
+        name.to_string()
+    } else {
+        let cwd = get_cwd()?;
+        let project = project_root(&cwd).unwrap_or(cwd);
+        project.replace(['/', '\\', ':'], "%")
+    };
+    Some(dir.join(format!("{name}.json")))
+}
+
+/// Read a session from the disk
+pub fn load_session(path: &Path) -> Option<Value> {
+    let text = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Write a session to the disk (sessions with nothing worth coming back to are removed instead)
+pub fn write_session(path: &Path, session: &Value) -> std::io::Result<()> {
+    if !worth_keeping(&session["layout"]) {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, session.to_string())
+}
+
+/// Determine if a layout has any documents or terminals in it
+fn worth_keeping(layout: &Value) -> bool {
+    match layout["type"].as_str() {
+        Some("atom") => layout["files"].as_array().is_some_and(|f| !f.is_empty()),
+        Some("terminal") => true,
+        Some("side_by_side" | "top_to_bottom") => layout["splits"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|split| worth_keeping(&split["layout"])),
+        _ => false,
+    }
+}
+
+/// Read a list of numbers (used for pointers and locations)
+fn numbers(value: &Value) -> Option<Vec<usize>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|n| n.as_u64().and_then(|n| usize::try_from(n).ok()))
+        .collect()
+}
+
+impl Editor {
+    /// Describe the layout of the editor (splits, documents, terminals and the file tree)
+    pub fn session(&self) -> Value {
+        json!({
+            "layout": self.layout_to_json(&self.files),
+            "ptr": self.ptr,
+            "old_ptr": self.old_ptr,
+        })
+    }
+
+    /// Describe part of the layout
+    fn layout_to_json(&self, layout: &FileLayout) -> Value {
+        let splits = |splits: &[(FileLayout, f64)]| {
+            splits
+                .iter()
+                .map(
+                    |(layout, size)| json!({ "layout": self.layout_to_json(layout), "size": size }),
+                )
+                .collect::<Vec<_>>()
+        };
+        match layout {
+            FileLayout::SideBySide(layouts) => {
+                json!({ "type": "side_by_side", "splits": splits(layouts) })
+            }
+            FileLayout::TopToBottom(layouts) => {
+                json!({ "type": "top_to_bottom", "splits": splits(layouts) })
+            }
+            FileLayout::Atom(containers, ptr) => {
+                // Scratch buffers and documents that were never saved can't be brought back
+                let mut tab = 0;
+                let mut files = vec![];
+                for (idx, fc) in containers.iter().enumerate() {
+                    let path = fc.doc.file_name.as_ref().and_then(|n| get_absolute_path(n));
+                    let Some(path) = path.filter(|_| fc.scratch.is_none()) else {
+                        continue;
+                    };
+                    if idx <= *ptr {
+                        tab = files.len();
+                    }
+                    let cursor = fc.doc.char_loc();
+                    files.push(json!({
+                        "path": path,
+                        "cursor": [cursor.x, cursor.y],
+                        "offset": [fc.doc.offset.x, fc.doc.offset.y],
+                    }));
+                }
+                json!({ "type": "atom", "files": files, "tab": tab })
+            }
+            FileLayout::FileTree => {
+                let root = match &self.file_tree {
+                    Some(FileTree::Dir { path, .. }) => Some(path.clone()),
+                    _ => get_cwd(),
+                };
+                json!({ "type": "file_tree", "root": root })
+            }
+            #[cfg(not(target_os = "windows"))]
+            FileLayout::Terminal(term) => {
+                let cwd = term.lock().unwrap().cwd().or_else(get_cwd);
+                json!({ "type": "terminal", "cwd": cwd })
+            }
+            #[cfg(target_os = "windows")]
+            FileLayout::Terminal(()) => json!({ "type": "none" }),
+            FileLayout::None => json!({ "type": "none" }),
+        }
+    }
+
+    /// Replace the layout of the editor with one from a session
+    /// (returns false if none of it could be brought back)
+    pub fn restore_session(&mut self, session: &Value) -> Result<bool> {
+        if !worth_keeping(&session["layout"]) {
+            return Ok(false);
+        }
+        let layout = self.layout_from_json(&session["layout"])?;
+        if layout.buffer_ids().is_empty() && !has_terminal(&layout) {
+            return Ok(false);
+        }
+        self.files = layout;
+        // Go back to where the user was (as long as it still exists)
+        let ptr = numbers(&session["ptr"]).unwrap_or_default();
+        let first = self.files.buffer_ids().first().copied();
+        self.ptr = match (self.files.get_raw(ptr.clone()), first) {
+            (Some(FileLayout::Atom(..) | FileLayout::Terminal(_) | FileLayout::FileTree), _) => ptr,
+            (_, Some(id)) => self.files.find_buffer(vec![], id).unwrap_or_default().0,
+            (_, None) => self.files.new_pointer_position(&[]),
+        };
+        self.old_ptr = numbers(&session["old_ptr"]).unwrap_or_default();
+        // Blank documents go wherever a document couldn't be opened
+        self.new_if_empty()?;
+        self.greet = false;
+        self.update_cwd();
+        Ok(true)
+    }
+
+    /// Build part of the layout
+    fn layout_from_json(&mut self, value: &Value) -> Result<FileLayout> {
+        Ok(match value["type"].as_str() {
+            Some(kind @ ("side_by_side" | "top_to_bottom")) => {
+                let mut layouts = vec![];
+                for split in value["splits"].as_array().into_iter().flatten() {
+                    let layout = self.layout_from_json(&split["layout"])?;
+                    layouts.push((layout, split["size"].as_f64().unwrap_or(0.5)));
+                }
+                if kind == "side_by_side" {
+                    FileLayout::SideBySide(layouts)
+                } else {
+                    FileLayout::TopToBottom(layouts)
+                }
+            }
+            Some("file_tree") => {
+                let root = value["root"].as_str().map(str::to_string).or_else(get_cwd);
+                if let Some(ft) = root.and_then(|root| FileTree::build(&root).ok()) {
+                    if let FileTree::Dir { path, .. } = &ft {
+                        self.file_tree_selection = Some(path.clone());
+                    }
+                    self.file_tree = Some(ft);
+                }
+                FileLayout::FileTree
+            }
+            #[cfg(not(target_os = "windows"))]
+            Some("terminal") => {
+                let shell = config!(self.config, terminal).shell;
+                let term = match value["cwd"].as_str() {
+                    Some(cwd) if Path::new(cwd).is_dir() => Pty::new_in(shell, cwd),
+                    _ => Pty::new(shell),
+                };
+                term.map_or(FileLayout::Atom(vec![], 0), FileLayout::Terminal)
+            }
+            Some("atom") => {
+                let mut containers = vec![];
+                let mut tab = 0;
+                let focused = value["tab"].as_u64().and_then(|n| usize::try_from(n).ok());
+                for (idx, file) in value["files"].as_array().into_iter().flatten().enumerate() {
+                    let Some(path) = file["path"].as_str() else {
+                        continue;
+                    };
+                    // (files that have since been deleted or moved are left out)
+                    let Ok(mut fc) = self.open_fc(path) else {
+                        continue;
+                    };
+                    if let Some([x, y]) = numbers(&file["cursor"]).as_deref() {
+                        fc.doc.move_to(&Loc { x: *x, y: *y });
+                    }
+                    if let Some([x, y]) = numbers(&file["offset"]).as_deref() {
+                        fc.doc.offset = Loc { x: *x, y: *y };
+                    }
+                    fc.doc.bring_cursor_in_viewport();
+                    fc.doc.load_to(fc.doc.offset.y + fc.doc.size.h);
+                    if Some(idx) == focused {
+                        tab = containers.len();
+                    }
+                    containers.push(fc);
+                }
+                FileLayout::Atom(containers, tab)
+            }
+            _ => FileLayout::Atom(vec![], 0),
+        })
+    }
+}
+
+/// Determine if a layout has any terminals in it
+fn has_terminal(layout: &FileLayout) -> bool {
+    match layout {
+        FileLayout::Terminal(_) => true,
+        FileLayout::SideBySide(layouts) | FileLayout::TopToBottom(layouts) => {
+            layouts.iter().any(|(layout, _)| has_terminal(layout))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::Lua;
+
+    /// Make some files to open in a session, giving back their paths
+    fn files(name: &str, count: usize) -> (PathBuf, Vec<String>) {
+        let dir = std::env::temp_dir().join(format!("ox-session-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = std::fs::canonicalize(dir).unwrap();
+        let paths = (0..count)
+            .map(|n| {
+                let path = dir.join(format!("{n}.txt"));
+                std::fs::write(&path, "hello\nworld\n").unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+        (dir, paths)
+    }
+
+    fn atom(paths: &[&str], tab: usize) -> Value {
+        let files: Vec<Value> = paths
+            .iter()
+            .map(|path| json!({ "path": path, "cursor": [2, 1], "offset": [0, 0] }))
+            .collect();
+        json!({ "type": "atom", "files": files, "tab": tab })
+    }
+
+    #[test]
+    fn layouts_survive_being_saved() {
+        let (dir, paths) = files("round-trip", 4);
+        let session = json!({
+            "layout": { "type": "side_by_side", "splits": [
+                { "layout": atom(&[&paths[0]], 0), "size": 0.3 },
+                { "layout": { "type": "top_to_bottom", "splits": [
+                    { "layout": atom(&[&paths[1], &paths[2]], 1), "size": 0.6 },
+                    { "layout": atom(&[&paths[3]], 0), "size": 0.4 },
+                ] }, "size": 0.7 },
+            ] },
+            "ptr": [1, 0],
+            "old_ptr": [0],
+        });
+        let lua = Lua::new();
+        let mut editor = Editor::new(&lua).unwrap();
+        let restored = editor.restore_session(&session);
+        // (paths are only written out while the files are still there)
+        let saved = editor.session();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(restored.unwrap());
+        assert_eq!(saved, session);
+    }
+
+    #[test]
+    fn missing_parts_of_layouts_are_filled_in() {
+        let (dir, paths) = files("fallbacks", 1);
+        let missing = dir.join("missing.txt").to_string_lossy().to_string();
+        let lua = Lua::new();
+        let mut editor = Editor::new(&lua).unwrap();
+        // (layouts where nothing can be brought back are turned down)
+        for layout in [atom(&[&missing], 0), atom(&[], 0)] {
+            let session = json!({ "layout": layout, "ptr": [], "old_ptr": [] });
+            assert!(!editor.restore_session(&session).unwrap());
+        }
+        // (missing files are left out, empty atoms get a blank document,
+        // and a pointer that leads nowhere goes to the first document instead)
+        let session = json!({
+            "layout": { "type": "side_by_side", "splits": [
+                { "layout": atom(&[&missing, &paths[0]], 0), "size": 0.5 },
+                { "layout": atom(&[], 0), "size": 0.5 },
+            ] },
+            "ptr": [7, 3],
+            "old_ptr": [0],
+        });
+        let restored = editor.restore_session(&session);
+        let saved = editor.session();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(restored.unwrap());
+        assert_eq!(editor.ptr, vec![0]);
+        let blank = editor.files.get(vec![1]).unwrap();
+        assert!(blank.doc.file_name.is_none());
+        let layout = &saved["layout"];
+        assert_eq!(layout["splits"][0]["layout"], atom(&[&paths[0]], 0));
+        assert_eq!(layout["splits"][1]["layout"], atom(&[], 0));
+    }
+}
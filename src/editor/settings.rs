@@ -0,0 +1,148 @@
+/// Working out the settings that apply to each document
+use crate::config;
+use crate::config::DocumentSettings;
+use crate::editor::{Editor, FileContainer, FileType};
+use crate::ui::Feedback;
+use kaolinite::utils::get_absolute_path;
+
+/// Character sets that documents can be saved in
+const CHARSETS: [&str; 2] = ["utf-8", "utf-8-bom"];
+
+impl Editor {
+    /// Work out the settings for a document
+    /// (the document configuration, then the file type's settings, then `.editorconfig` files)
+    pub fn document_settings(
+        &self,
+        file_name: Option<&str>,
+        file_type: Option<&FileType>,
+    ) -> DocumentSettings {
+        let mut settings = config!(self.config, document).settings();
+        if let Some(file_type) = file_type {
+            settings.apply_file_type(file_type);
+        }
+        // (files that don't exist yet still pick up the settings of the folder they'll be in)
+        let path = file_name.and_then(|name| {
+            get_absolute_path(name).or_else(|| {
+                let path = std::env::current_dir().ok()?.join(name);
+                Some(path.to_string_lossy().to_string())
+            })
+        });
+        if let Some(path) = path {
+            settings.apply_editorconfig(&path);
+        }
+        settings
+    }
+
+    /// Work out the settings for the focused document again (e.g. after its file type changes)
+    pub fn refresh_settings(&mut self) {
+        let Some(fc) = self.files.get(self.ptr.clone()) else {
+            return;
+        };
+        let settings = self.document_settings(fc.doc.file_name.as_deref(), fc.file_type.as_ref());
+        self.warn_about_charset(&settings);
+        if let Some(fc) = self.files.get_mut(self.ptr.clone()) {
+            fc.apply_settings(settings);
+        }
+    }
+
+    /// The settings of the focused document (or the document configuration if there isn't one)
+    pub fn focused_settings(&self) -> DocumentSettings {
+        self.files.get(self.ptr.clone()).map_or_else(
+            || config!(self.config, document).settings(),
+            |fc| fc.settings.clone(),
+        )
+    }
+
+    /// Let the user know if a document asks for a character set that can't be used
+    pub fn warn_about_charset(&mut self, settings: &DocumentSettings) {
+        if let Some(charset) = settings
+            .charset
+            .as_ref()
+            .filter(|c| !CHARSETS.contains(&c.as_str()))
+        {
+            self.feedback = Feedback::Warning(format!(
+                "The {charset} character set isn't supported, this file will be saved as utf-8"
+            ));
+        }
+    }
+}
+
+impl DocumentSettings {
+    /// Apply the settings a file type overrides over the top of these settings
+    pub fn apply_file_type(&mut self, file_type: &FileType) {
+        let overrides = &file_type.settings;
+        if let Some(tab_width) = overrides.tab_width {
+            self.tab_width = tab_width;
+            self.indent_size = tab_width;
+        }
+        if let Some(indentation) = overrides.indentation {
+            self.indentation = indentation;
+        }
+        if let Some(wrap_cursor) = overrides.wrap_cursor {
+            self.wrap_cursor = wrap_cursor;
+        }
+        if let Some(undo_period) = overrides.undo_period {
+            self.undo_period = undo_period;
+        }
+    }
+}
+
+impl FileContainer {
+    /// Use a set of settings for this document
+    pub fn apply_settings(&mut self, settings: DocumentSettings) {
+        if self.doc.tab_width != settings.tab_width {
+            self.doc.set_tab_width(settings.tab_width);
+            self.doc.reload_lines();
+        }
+        let info = &mut self.doc.info;
+        info.line_ending = settings.end_of_line.as_deref().map(|eol| {
+            match eol {
+                "crlf" => "\r\n",
+                "cr" => "\r",
+                _ => "\n",
+            }
+            .to_string()
+        });
+        info.bom = match settings.charset.as_deref() {
+            Some("utf-8-bom") => Some(true),
+            Some("utf-8") => Some(false),
+            _ => None,
+        };
+        info.final_newline = settings.insert_final_newline;
+        self.settings = settings;
+    }
+
+    /// Get the document ready to be saved (tidying up the lines if the settings ask for it)
+    pub fn prepare_save(&mut self) -> kaolinite::event::Result<()> {
+        if self.settings.trim_trailing_whitespace {
+            self.doc.trim_trailing_whitespace()?;
+        }
+        Ok(())
+    }
+
+    /// The line ending this document uses ("lf", "crlf" or "cr")
+    pub fn end_of_line(&self) -> String {
+        self.settings.end_of_line.clone().unwrap_or_else(|| {
+            let first = self.doc.file.line(0).to_string();
+            if first.ends_with("\r\n") {
+                "crlf"
+            } else if first.ends_with('\r') {
+                "cr"
+            } else {
+                "lf"
+            }
+            .to_string()
+        })
+    }
+
+    /// The character set this document uses
+    pub fn charset(&self) -> String {
+        self.settings.charset.clone().unwrap_or_else(|| {
+            if self.doc.file.chars().next() == Some('\u{feff}') {
+                "utf-8-bom".to_string()
+            } else {
+                "utf-8".to_string()
+            }
+        })
+    }
+}
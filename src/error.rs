@@ -29,6 +29,10 @@ error_set! {
             file: String,
         },
         InvalidPath,
+        #[display("{}", msg)]
+        Plugin {
+            msg: String,
+        },
         // None, <--- Needed???
     };
 }
@@ -1,7 +1,14 @@
-use crate::{ged, handle_lua_error, CEvent, Editor, Feedback, KeyEvent, KeyEventKind, Result};
-use crossterm::event::{poll, read};
-use mlua::{AnyUserData, Lua};
-use std::time::Duration;
+use crate::config::{get_listeners, key_to_string, Buffer, Completion, KeyOutcome, Keymap};
+use crate::editor::Hook;
+use crate::{
+    ged, handle_event, handle_lua_error, CEvent, Editor, Feedback, KeyEvent, KeyEventKind, Result,
+};
+use crossterm::event::{poll, read, KeyCode, KeyModifiers};
+use mlua::{AnyUserData, IntoLuaMulti, Lua};
+use std::time::{Duration, Instant};
+
+/// How long the editor must be left without input before it is considered idle
+const IDLE_DELAY: Duration = Duration::from_secs(1);
 
 #[allow(unused_variables)]
 pub fn term_force(editor: &AnyUserData) -> bool {
@@ -18,9 +25,19 @@ pub fn mm_active(editor: &AnyUserData) -> bool {
 /// (should hold event, triggered by term force?)
 pub fn hold_event(editor: &AnyUserData) -> (bool, bool) {
     let tf = term_force(editor);
+    // Wake up in time for the next task to run
+    let wait = ged!(&editor)
+        .config
+        .task_manager
+        .lock()
+        .unwrap()
+        .time_until_due()
+        .map_or(Duration::from_millis(50), |due| {
+            due.min(Duration::from_millis(50))
+        });
     (
         matches!(
-            (mm_active(editor), tf, poll(Duration::from_millis(50))),
+            (mm_active(editor), tf, poll(wait)),
             (false, false, Ok(false))
         ),
         !tf,
@@ -29,16 +46,17 @@ pub fn hold_event(editor: &AnyUserData) -> (bool, bool) {
 
 #[allow(unused_variables)]
 pub fn wait_for_event(editor: &AnyUserData, lua: &Lua) -> Result<CEvent> {
+    let waiting_since = Instant::now();
+    let mut idle = false;
     loop {
         // While waiting for an event to come along, service the task manager
         if !mm_active(editor) {
             while let (true, was_term) = hold_event(editor) {
-                let exec = ged!(mut &editor)
-                    .config
-                    .task_manager
-                    .lock()
-                    .unwrap()
-                    .execution_list();
+                let task_manager = ged!(&editor).config.task_manager.clone();
+                task_manager.lock().unwrap().cycle();
+                // (callbacks of closed windows and buffers are freed here, where lua is to hand)
+                task_manager.lock().unwrap().free_released(lua);
+                let exec = task_manager.lock().unwrap().execution_list();
                 for task in exec {
                     if let Ok(target) = lua.globals().get::<mlua::Function>(task.clone()) {
                         // Run the code
@@ -48,6 +66,24 @@ pub fn wait_for_event(editor: &AnyUserData, lua: &Lua) -> Result<CEvent> {
                             Feedback::Warning(format!("Function '{task}' was not found"));
                     }
                 }
+                // Hand the results of any finished background work to their callbacks
+                let completed = task_manager.lock().unwrap().completion_list();
+                for (id, result) in completed {
+                    run_callback(editor, lua, id, result);
+                }
+                // Let plug-ins know if the user has stopped to think
+                if !idle && waiting_since.elapsed() >= IDLE_DELAY {
+                    idle = true;
+                    ged!(mut &editor).hook(Hook::Idle);
+                }
+                // Open files that other ox processes have sent over
+                #[cfg(not(target_os = "windows"))]
+                if crate::remote::serve(editor, lua) {
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Give up waiting for the rest of a key sequence
+                expire_chord(editor, lua)?;
+                dispatch_hooks(editor, lua);
                 // If a terminal dictates, force a rerender
                 #[cfg(not(target_os = "windows"))]
                 if was_term {
@@ -63,19 +99,232 @@ pub fn wait_for_event(editor: &AnyUserData, lua: &Lua) -> Result<CEvent> {
             continue;
         };
 
-        // Block certain events from passing through
-        if !matches!(
-            event,
-            CEvent::Key(KeyEvent {
-                kind: KeyEventKind::Release,
-                ..
-            })
-        ) {
+        // Key releases (from terminals that report them) only go to plug-ins
+        if let CEvent::Key(KeyEvent {
+            kind: KeyEventKind::Release,
+            modifiers,
+            code,
+            ..
+        }) = event
+        {
+            key_released(editor, lua, &key_to_string(modifiers, code));
+        } else {
             return Ok(event);
         }
     }
 }
 
+/// Run the listeners for a key being released (`release:<key>`)
+fn key_released(editor: &AnyUserData, lua: &Lua, key: &str) {
+    let Ok(listeners) = get_listeners(&format!("release:{key}"), lua) else {
+        return;
+    };
+    for listener in listeners {
+        handle_lua_error(key, listener.call(()), &mut ged!(mut &editor).feedback);
+    }
+}
+
+/// Run the lua callback waiting on a result from background work
+pub fn run_callback(editor: &AnyUserData, lua: &Lua, id: usize, result: Completion) {
+    let task_manager = ged!(&editor).config.task_manager.clone();
+    let mut tm = task_manager.lock().unwrap();
+    let func = tm
+        .callbacks
+        .get(&id)
+        .and_then(|key| lua.registry_value::<mlua::Function>(key).ok());
+    // Callbacks are no longer needed once their work has finished
+    if result.is_final() {
+        tm.remove_callback(lua, id);
+    }
+    std::mem::drop(tm);
+    if let Some(func) = func {
+        handle_lua_error(
+            "callback",
+            func.call(result),
+            &mut ged!(mut &editor).feedback,
+        );
+    }
+}
+
+/// Send any queued lifecycle events to the plug-ins listening for them
+pub fn dispatch_hooks(editor: &AnyUserData, lua: &Lua) {
+    let hooks = ged!(mut &editor).hook_list();
+    for hook in hooks {
+        let Ok(listeners) = get_listeners(hook.name(), lua) else {
+            continue;
+        };
+        for listener in listeners {
+            let result = listener.call(hook.clone());
+            handle_lua_error(hook.name(), result, &mut ged!(mut &editor).feedback);
+        }
+    }
+}
+
+/// Give a key press to the focused floating window (returns false if there isn't one)
+pub fn window_key(editor: &AnyUserData, lua: &Lua, key: &KeyEvent) -> bool {
+    let Some((id, on_key, on_select, selection)) = ged!(&editor)
+        .floating
+        .focused()
+        .map(|w| (w.id, w.on_key, w.on_select, w.selection))
+    else {
+        return false;
+    };
+    ged!(mut &editor).needs_rerender = true;
+    let key = key_to_string(key.modifiers, key.code);
+    // Let the plug-in handle the key press first
+    if let Some(func) = on_key.and_then(|cb| get_callback(editor, lua, cb)) {
+        let result = func.call::<Option<bool>>((id, key.clone()));
+        let handled = matches!(result, Ok(Some(true)));
+        handle_lua_error(
+            "window",
+            result.map(|_| ()),
+            &mut ged!(mut &editor).feedback,
+        );
+        if handled {
+            return true;
+        }
+    }
+    match key.as_str() {
+        "esc" => {
+            ged!(mut &editor).close_window(id);
+        }
+        "up" | "down" | "pageup" | "pagedown" => {
+            let amount = match key.as_str() {
+                "up" => -1,
+                "down" => 1,
+                "pageup" => -10,
+                _ => 10,
+            };
+            let result = ged!(mut &editor).move_window(id, amount);
+            if let Err(err) = result {
+                ged!(mut &editor).feedback = Feedback::Error(err.to_string());
+            }
+        }
+        "enter" => {
+            // Picking an item closes the window
+            let func = on_select.and_then(|cb| get_callback(editor, lua, cb));
+            ged!(mut &editor).close_window(id);
+            if let Some(func) = func {
+                let result = func.call(selection.map(|idx| idx + 1));
+                handle_lua_error("window", result, &mut ged!(mut &editor).feedback);
+            }
+        }
+        _ => (),
+    }
+    true
+}
+
+/// Give a key press to a key binding of the focused scratch buffer (returns false if unbound)
+pub fn buffer_key(editor: &AnyUserData, lua: &Lua, key: &KeyEvent) -> bool {
+    let key = key_to_string(key.modifiers, key.code);
+    let Some((id, callback)) = ged!(&editor)
+        .files
+        .get(ged!(&editor).ptr.clone())
+        .and_then(|file| Some((file.id, *file.scratch.as_ref()?.keymaps.get(&key)?)))
+    else {
+        return false;
+    };
+    let Some(func) = get_callback(editor, lua, callback) else {
+        return false;
+    };
+    // Bindings can return false to let the editor handle the key press as usual
+    let result = func.call::<Option<bool>>(Buffer { id });
+    let handled = !matches!(result, Ok(Some(false)));
+    handle_lua_error(&key, result.map(|_| ()), &mut ged!(mut &editor).feedback);
+    ged!(mut &editor).needs_rerender = true;
+    handled
+}
+
+/// Give a key press to the keymap, which may be part of a sequence (returns false if unbound)
+pub fn chord_key(editor: &AnyUserData, lua: &Lua, key: &KeyEvent) -> bool {
+    let contexts = ged!(&editor).key_contexts();
+    let keymap = ged!(&editor).config.keymap.clone();
+    // (the keymap mustn't be borrowed while the action runs, as it may add bindings)
+    let Ok(mut outcomes) = keymap
+        .borrow_mut::<Keymap>()
+        .map(|mut keymap| keymap.feed(&contexts, *key))
+    else {
+        return false;
+    };
+    // (the last outcome is for this key, which the editor handles as usual if it is unbound)
+    let unbound = matches!(outcomes.last(), Some(KeyOutcome::Unbound(_)));
+    if unbound {
+        outcomes.pop();
+    }
+    let pending = matches!(outcomes.last(), Some(KeyOutcome::Pending));
+    let name = key_to_string(key.modifiers, key.code);
+    run_chord(editor, lua, &name, outcomes);
+    if pending {
+        ged!(mut &editor).show_key_hints(&contexts);
+    }
+    !unbound
+}
+
+/// Run whatever is bound to an incomplete key sequence once the user stops typing it
+fn expire_chord(editor: &AnyUserData, lua: &Lua) -> Result<()> {
+    let contexts = ged!(&editor).key_contexts();
+    let keymap = ged!(&editor).config.keymap.clone();
+    let Some(outcomes) = keymap
+        .borrow_mut::<Keymap>()
+        .ok()
+        .and_then(|mut keymap| keymap.expire(&contexts))
+    else {
+        return Ok(());
+    };
+    run_chord(editor, lua, "keymap", outcomes);
+    ged!(mut &editor).render(lua)
+}
+
+/// Do what the keymap says should happen after a key press
+/// (running actions, and replaying the keys of a broken sequence as if nothing was bound to them)
+fn run_chord(editor: &AnyUserData, lua: &Lua, name: &str, outcomes: Vec<KeyOutcome>) {
+    for outcome in outcomes {
+        ged!(mut &editor).close_key_hints();
+        match outcome {
+            KeyOutcome::Run(action) => {
+                handle_lua_error(name, action.call(()), &mut ged!(mut &editor).feedback);
+            }
+            KeyOutcome::Unbound(key) => {
+                if let Err(err) = handle_event(editor, &CEvent::Key(key), lua) {
+                    ged!(mut &editor).feedback = Feedback::Error(err.to_string());
+                }
+            }
+            KeyOutcome::Pending => (),
+        }
+        ged!(mut &editor).needs_rerender = true;
+    }
+}
+
+/// Find a lua callback registered with the task manager
+fn get_callback(editor: &AnyUserData, lua: &Lua, id: usize) -> Option<mlua::Function> {
+    let task_manager = ged!(&editor).config.task_manager.clone();
+    let tm = task_manager.lock().unwrap();
+    tm.callbacks
+        .get(&id)
+        .and_then(|key| lua.registry_value::<mlua::Function>(key).ok())
+}
+
+/// Run the listeners for an event that can be cancelled,
+/// returns false if any of the listeners returned false
+/// (a listener that fails doesn't cancel the event, its error is shown to the user instead)
+pub fn fire_before(lua: &Lua, name: &str, args: &(impl IntoLuaMulti + Clone)) -> bool {
+    let Ok(listeners) = get_listeners(name, lua) else {
+        return true;
+    };
+    for listener in listeners {
+        match listener.call::<Option<bool>>(args.clone()) {
+            Ok(Some(false)) => return false,
+            Ok(_) => (),
+            Err(err) => {
+                if let Ok(editor) = lua.globals().get::<AnyUserData>("editor") {
+                    handle_lua_error(name, Err(err), &mut ged!(mut &editor).feedback);
+                }
+            }
+        }
+    }
+    true
+}
+
 /// Wait for event, but without the task manager (and it hogs editor)
 pub fn wait_for_event_hog(editor: &mut Editor) -> CEvent {
     loop {
@@ -103,6 +352,9 @@ pub fn get_event(editor: &mut Editor) -> Option<CEvent> {
     if let Some(ev) = editor.macro_man.next() {
         // Take from macro man
         Some(ev)
+    } else if editor.terminal.headless {
+        // Nobody is around to answer prompts when running headless, so cancel them
+        Some(CEvent::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)))
     } else if let Ok(true) = poll(Duration::from_millis(50)) {
         if let Ok(ev) = read() {
             // Use standard crossterm event
@@ -114,3 +366,113 @@ pub fn get_event(editor: &mut Editor) -> Option<CEvent> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{FileContainer, FileLayout};
+    use kaolinite::event::Event;
+    use kaolinite::Loc;
+
+    /// An editor (with a document holding some text) that lua can reach,
+    /// along with listeners that record what they are told in `seen`
+    fn setup(listeners: &str) -> (Lua, AnyUserData) {
+        let lua = Lua::new();
+        let mut editor = Editor::new(&lua).unwrap();
+        let mut file = FileContainer::default();
+        file.doc.set_text("one\ntwo");
+        editor.files = FileLayout::Atom(vec![file], 0);
+        let editor = lua.create_userdata(editor).unwrap();
+        lua.globals().set("editor", editor.clone()).unwrap();
+        lua.load(format!("seen = {{}}\nglobal_event_mapping = {listeners}"))
+            .exec()
+            .unwrap();
+        (lua, editor)
+    }
+
+    /// What the listeners have recorded so far
+    fn seen(lua: &Lua) -> Vec<String> {
+        lua.globals().get("seen").unwrap()
+    }
+
+    #[test]
+    fn hooks_are_sent_in_the_order_they_happened() {
+        let (lua, editor) = setup(
+            r#"{
+                on_open = {function(file) table.insert(seen, "open " .. file) end},
+                on_change = {function(ev) table.insert(seen, ev.kind .. " " .. ev.text) end},
+                on_save = {function(file) table.insert(seen, "save " .. file) end},
+            }"#,
+        );
+        {
+            let mut editor = ged!(mut &editor);
+            editor.hook(Hook::Open(Some("a.txt".to_string())));
+            editor
+                .exe(Event::Insert(Loc::at(0, 0), "x".to_string()))
+                .unwrap();
+            editor
+                .exe(Event::Insert(Loc::at(1, 1), "y".to_string()))
+                .unwrap();
+            editor.hook(Hook::Save(Some("a.txt".to_string())));
+        }
+        dispatch_hooks(&editor, &lua);
+        assert_eq!(
+            seen(&lua),
+            ["open a.txt", "insert x", "insert y", "save a.txt"]
+        );
+        // (nothing is sent twice)
+        dispatch_hooks(&editor, &lua);
+        assert_eq!(seen(&lua).len(), 4);
+    }
+
+    #[test]
+    fn edits_that_fail_are_not_announced() {
+        let (lua, editor) =
+            setup(r"{ on_change = {function(ev) table.insert(seen, ev.kind) end} }");
+        let result = ged!(mut &editor).exe(Event::Insert(Loc::at(0, 9), "x".to_string()));
+        assert!(result.is_err());
+        dispatch_hooks(&editor, &lua);
+        assert!(seen(&lua).is_empty());
+    }
+
+    #[test]
+    fn before_events_can_be_cancelled() {
+        let (lua, _) = setup(
+            r#"{ before_save = {
+                function(file) table.insert(seen, "first") end,
+                function(file) table.insert(seen, "second"); return file ~= "keep.txt" end,
+                function(file) table.insert(seen, "third") end,
+            } }"#,
+        );
+        assert!(fire_before(&lua, "before_save", &Some("a.txt".to_string())));
+        assert_eq!(seen(&lua), ["first", "second", "third"]);
+        lua.load("seen = {}").exec().unwrap();
+        // (listeners after the one that cancels aren't asked)
+        assert!(!fire_before(
+            &lua,
+            "before_save",
+            &Some("keep.txt".to_string())
+        ));
+        assert_eq!(seen(&lua), ["first", "second"]);
+        // (events nobody listens for go ahead)
+        assert!(fire_before(&lua, "before_close", &None::<String>));
+    }
+
+    #[test]
+    fn failing_before_listeners_are_reported_without_cancelling() {
+        let (lua, editor) = setup(
+            r#"{ before_save = {
+                function(file) error("listener broke") end,
+                function(file) table.insert(seen, "after") end,
+            } }"#,
+        );
+        assert!(fire_before(&lua, "before_save", &Some("a.txt".to_string())));
+        assert_eq!(seen(&lua), ["after"]);
+        let editor = ged!(&editor);
+        assert!(
+            matches!(&editor.feedback, Feedback::Error(msg) if msg.contains("listener broke")),
+            "{:?}",
+            editor.feedback
+        );
+    }
+}
@@ -0,0 +1,111 @@
+/// Running Ox without a terminal, so that scripts can make changes to files in batches
+use crate::cli::CommandLineInterface;
+use crate::config::run_key;
+use crate::editor::Editor;
+use crate::events::{dispatch_hooks, fire_before};
+use crate::ui::Feedback;
+use crate::{ged, handle_lua_error};
+use mlua::{AnyUserData, Lua};
+
+/// Run the scripts given on the command line against the open documents, then save them
+/// (returns the status code to exit with)
+pub fn run(cli: &CommandLineInterface, editor: &AnyUserData, lua: &Lua) -> i32 {
+    // Let plug-ins know about the documents that were opened
+    dispatch_hooks(editor, lua);
+    // Don't change anything if the configuration file or plug-ins had problems
+    if report(editor) {
+        return 1;
+    }
+    // Gather up what needs to be run
+    let mut scripts = vec![];
+    if let Some(path) = &cli.script {
+        match std::fs::read_to_string(path) {
+            Ok(code) => scripts.push((path.clone(), code)),
+            Err(err) => {
+                eprintln!("[Error] Failed to read script '{path}': {err}");
+                return 1;
+            }
+        }
+    }
+    if let Some(code) = &cli.eval {
+        scripts.push(("-e".to_string(), code.clone()));
+    }
+    if scripts.is_empty() {
+        eprintln!("[Error] Nothing to run, provide a script with --script or some code with -e");
+        return 1;
+    }
+    // Run the scripts, stopping at the first one that fails (nothing is saved in that case)
+    for (name, code) in scripts {
+        let result = lua.load(code).set_name(format!("@{name}")).exec();
+        dispatch_hooks(editor, lua);
+        if let Err(err) = result {
+            eprintln!("[Error] {err}");
+            return 1;
+        }
+        if report(editor) {
+            return 1;
+        }
+    }
+    let saved = save(editor, lua);
+    // Run any plugin cleanup operations
+    let result = lua.load(run_key("exit")).exec();
+    handle_lua_error("exit", result, &mut ged!(mut &editor).feedback);
+    let failed = report(editor);
+    i32::from(!saved || failed)
+}
+
+/// Save every document that was changed (returns false if any of them couldn't be saved)
+fn save(editor: &AnyUserData, lua: &Lua) -> bool {
+    let mut saved = true;
+    let ids = ged!(&editor).files.buffer_ids();
+    for id in ids {
+        let Some((modified, file_name)) = ged!(mut &editor)
+            .buffer_mut(id)
+            // (the blank document made when no files are given doesn't count until it has text)
+            .map(|file| {
+                let blank =
+                    file.doc.file_name.is_none() && file.doc.file.chars().all(char::is_whitespace);
+                (file.modified() && !blank, file.doc.file_name.clone())
+            })
+        else {
+            continue;
+        };
+        if !modified {
+            continue;
+        }
+        let Some(file_name) = file_name else {
+            eprintln!("[Warning] A document without a file name was changed, but can't be saved");
+            continue;
+        };
+        // Plug-ins are still able to prevent saving
+        let save = fire_before(lua, "before_save", &Some(file_name.clone()));
+        // (listeners that fail are reported, but don't stop the document being saved)
+        report(editor);
+        if !save {
+            continue;
+        }
+        if let Some(Err(err)) = ged!(mut &editor).with_buffer(id, Editor::save) {
+            eprintln!("[Error] Failed to save '{file_name}': {err}");
+            saved = false;
+        }
+    }
+    // (saving leaves a message that isn't needed here)
+    ged!(mut &editor).feedback = Feedback::None;
+    dispatch_hooks(editor, lua);
+    saved
+}
+
+/// Print out the message the editor would show on the feedback line (returns true for errors)
+fn report(editor: &AnyUserData) -> bool {
+    let feedback = std::mem::replace(&mut ged!(mut &editor).feedback, Feedback::None);
+    match feedback {
+        Feedback::Error(msg) => {
+            eprintln!("[Error] {msg}");
+            return true;
+        }
+        Feedback::Warning(msg) => eprintln!("[Warning] {msg}"),
+        Feedback::Info(msg) => println!("{msg}"),
+        Feedback::None => (),
+    }
+    false
+}
@@ -1,30 +1,40 @@
 #![warn(clippy::all, clippy::pedantic)]
 
 mod cli;
+mod command_line;
 mod config;
 mod editor;
 mod error;
 mod events;
+mod headless;
+mod packages;
 #[cfg(not(target_os = "windows"))]
 mod pty;
+#[cfg(not(target_os = "windows"))]
+mod remote;
 mod ui;
 
 use cli::CommandLineInterface;
 use config::{
-    get_listeners, key_to_string, run_key, run_key_before, Assistant, Config, PLUGIN_BOOTSTRAP,
-    PLUGIN_MANAGER, PLUGIN_NETWORKING, PLUGIN_RUN,
+    get_listeners, key_to_string, project_root, run_key, run_key_before, startup_project_config,
+    Assistant, Config, PLUGIN_BOOTSTRAP, PLUGIN_MANAGER, PLUGIN_NETWORKING, PLUGIN_RUN,
 };
 use crossterm::event::{Event as CEvent, KeyEvent, KeyEventKind};
-use editor::{allowed_by_multi_cursor, handle_multiple_cursors, Editor, FileTypes};
+use editor::{
+    allowed_by_multi_cursor, handle_multiple_cursors, load_session, session_path, write_session,
+    Editor, FileTypes, History,
+};
 use error::{OxError, Result};
-use events::wait_for_event;
+use events::{buffer_key, chord_key, dispatch_hooks, wait_for_event, window_key};
 use kaolinite::event::{Error as KError, Event};
 use kaolinite::searching::Searcher;
 use kaolinite::utils::{file_or_dir, get_cwd};
 use kaolinite::{Document, Loc};
 use mlua::Error::{RuntimeError, SyntaxError};
 use mlua::{AnyUserData, FromLua, Lua, Value};
-use std::io::ErrorKind;
+use packages::Packages;
+use std::io::{ErrorKind, IsTerminal};
+use std::path::Path;
 use std::result::Result as RResult;
 use ui::{fatal_error, Feedback};
 
@@ -47,23 +57,64 @@ fn main() {
     // Handle help and version options
     cli.basic_options();
 
+    // Manage plug-ins without starting the editor
+    if let Some(args) = &cli.plugins {
+        let result = Packages::new(&cli.config_path).and_then(|mut packages| packages.run(args));
+        if let Err(err) = result {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        std::process::exit(0);
+    }
+
+    // Hand files and lua code over to an editor that is already running
+    // (if there isn't one, the files are opened in this editor instead)
+    #[cfg(not(target_os = "windows"))]
+    if cli.flags.remote {
+        if let Some(status) = remote::send(&cli) {
+            std::process::exit(status);
+        }
+    }
+
     // Activate configuration assistant if applicable
     let no_config = Config::get_user_provided_config(&cli.config_path).is_none();
-    if no_config || cli.flags.config_assist {
+    // (the assistant needs a terminal, so the default configuration is used when headless)
+    if !cli.flags.headless && (no_config || cli.flags.config_assist) {
         if let Err(err) = Assistant::run(no_config) {
             panic!("{err:?}");
         }
     }
 
+    // Find the configuration of the project being worked on
+    // (the user is asked before any lua code it provides is run)
+    let can_ask = !cli.flags.headless && !cli.flags.stdin && std::io::stdin().is_terminal();
+    let project_config = startup_project_config(&start_dir(&cli), can_ask);
+
     // Run the editor
-    let result = run(&cli);
-    if let Err(err) = result {
-        panic!("{err:?}");
+    match run(&cli, project_config) {
+        Ok(0) => (),
+        Ok(status) => std::process::exit(status),
+        Err(err) => panic!("{err:?}"),
     }
 }
-/// Run the editor
+/// Work out the folder the user is working in (where the first file is, otherwise the cwd)
+fn start_dir(cli: &CommandLineInterface) -> String {
+    let cwd = get_cwd().unwrap_or_else(|| ".".to_string());
+    let Some(file) = cli.to_open.first() else {
+        return cwd;
+    };
+    let path = Path::new(&cwd).join(&file.path);
+    let dir = if path.is_dir() {
+        path.as_path()
+    } else {
+        path.parent().unwrap_or(&path)
+    };
+    dir.to_string_lossy().to_string()
+}
+
+/// Run the editor (giving back the status code to exit with)
 #[allow(clippy::too_many_lines)]
-fn run(cli: &CommandLineInterface) -> Result<()> {
+fn run(cli: &CommandLineInterface, project_config: Option<String>) -> Result<i32> {
     // Create lua interpreter
     let lua = Lua::new();
 
@@ -77,6 +128,10 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
     let editor = lua.create_userdata(editor)?;
     lua.globals().set("editor", editor.clone())?;
 
+    // Without a terminal, nothing is drawn and nobody can answer prompts
+    ged!(mut &editor).terminal.headless = cli.flags.headless;
+    lua.globals().set("headless", cli.flags.headless)?;
+
     // Inject the networking library for plug-ins to use
     handle_lua_error(
         "",
@@ -85,6 +140,7 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
     );
 
     // Load config and initialise
+    ged!(mut &editor).project_config = project_config;
     lua.load(PLUGIN_BOOTSTRAP).exec()?;
     let result = ged!(mut &editor).load_config(&cli.config_path, &lua);
     if let Some(err) = result {
@@ -110,47 +166,101 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
         .globals()
         .get("file_types")
         .unwrap_or(Value::Table(lua.create_table()?));
-    let file_types = FileTypes::from_lua(file_types, &lua).unwrap_or_default();
+    let file_types = FileTypes::from_lua(file_types, &lua).unwrap_or_else(|err| {
+        ged!(mut &editor).feedback = Feedback::Error(format!("Failed to load file types: {err}"));
+        FileTypes::default()
+    });
     ged!(mut &editor)
         .config
         .document
         .borrow_mut::<config::Document>()
         .unwrap()
         .file_types = file_types;
-    // Open files user has asked to open
+    // Remember where the user was in files they open (scripts always start at the top)
     let cwd = get_cwd().unwrap_or(".".to_string());
-    for (c, file) in cli.to_open.iter().enumerate() {
+    if !cli.flags.headless {
+        let mut history = History::load();
+        if let Some(project) = project_root(&cwd) {
+            history.opened_project(&project);
+        }
+        ged!(mut &editor).history = history;
+    }
+
+    // Open files user has asked to open
+    for file in &cli.to_open {
         // Reset cwd
         let _ = std::env::set_current_dir(&cwd);
         // Open the file
-        let result = ged!(mut &editor).open_or_new(file.to_string());
-        handle_file_opening(&editor, result, file);
+        let result = ged!(mut &editor).open_or_new(file.path.clone());
+        // A file that was given more than once is already set up, just go where the user asked
+        let duplicate = matches!(result, Err(OxError::AlreadyOpen { .. }));
+        if let (true, Some(jump)) = (duplicate, &file.jump) {
+            if let Some(doc) = ged!(mut &editor).try_doc_mut() {
+                jump.apply(doc);
+            }
+        }
+        handle_file_opening(&editor, result, &file.path);
+        if duplicate {
+            continue;
+        }
+        // Move the pointer to the file we just created
+        ged!(mut &editor).next();
+        // Go to the position the user asked for
+        if let Some(jump) = &file.jump {
+            if let Some(doc) = ged!(mut &editor).try_doc_mut() {
+                jump.apply(doc);
+            }
+        }
         // Set read only if applicable
         if cli.flags.read_only {
-            ged!(mut &editor).get_doc(c).info.read_only = true;
+            if let Some(doc) = ged!(mut &editor).try_doc_mut() {
+                doc.info.read_only = true;
+            }
         }
         // Set highlighter if applicable
         if let Some(ref file_type) = cli.file_type {
-            let tab_width = config!(ged!(&editor).config, document).tab_width;
             let file_type = config!(ged!(mut &editor).config, document)
                 .file_types
                 .get_name(file_type)
                 .unwrap_or_default();
-            let mut highlighter = file_type.get_highlighter(&ged!(&editor).config, tab_width);
-            highlighter.run(&ged!(mut &editor).get_doc(c).lines);
             let mut editor = ged!(mut &editor);
+            let file_name = editor.try_doc().and_then(|doc| doc.file_name.clone());
+            let settings = editor.document_settings(file_name.as_deref(), Some(&file_type));
+            let mut highlighter = file_type.get_highlighter(&editor.config, settings.tab_width);
             let current_ptr = editor.ptr.clone();
-            let file = &mut editor.files.get_atom_mut(current_ptr).unwrap().0[c];
-            file.highlighter = highlighter;
-            file.file_type = Some(file_type);
+            if let Some(file) = editor.files.get_mut(current_ptr) {
+                file.apply_settings(settings);
+                highlighter.run(&file.doc.lines);
+                file.highlighter = highlighter;
+                file.file_type = Some(file_type);
+            }
         }
-        // Move the pointer to the file we just created
-        ged!(mut &editor).next();
     }
     // Reset the pointer back to the first document
     let current_ptr = ged!(mut &editor).ptr.clone();
     ged!(mut &editor).files.move_to(current_ptr, 0);
 
+    // Pick up where the user left off
+    if !cli.flags.headless && !cli.flags.stdin {
+        let (restore, save) = {
+            let editor = ged!(&editor);
+            let session = config!(editor.config, session);
+            (session.restore, session.save)
+        };
+        let named = cli.session.is_some();
+        let fresh = cli.to_open.is_empty();
+        // (a session isn't saved over when ox is just used to open a few files)
+        let path = session_path(cli.session.as_deref());
+        if fresh && (named || restore) {
+            if let Some(session) = path.as_deref().and_then(load_session) {
+                ged!(mut &editor).restore_session(&session)?;
+            }
+        }
+        if named || (fresh && save) {
+            ged!(mut &editor).session_path = path;
+        }
+    }
+
     // Handle stdin if applicable
     if cli.flags.stdin {
         let stdin = cli::get_stdin();
@@ -179,9 +289,21 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
         &mut ged!(mut &editor).feedback,
     );
 
+    // Run scripts against the documents instead of starting the editor if headless
+    if cli.flags.headless {
+        return Ok(headless::run(cli, &editor, &lua));
+    }
+
     // Run the editor and handle errors if applicable
     ged!(&editor).update_cwd();
     ged!(mut &editor).init()?;
+    // (plug-ins can now ask the user things through the editor rather than the terminal)
+    lua.globals().set("editor_started", true)?;
+    // Let other ox processes open files in this editor
+    #[cfg(not(target_os = "windows"))]
+    {
+        ged!(mut &editor).remote = remote::Server::start();
+    }
     while ged!(&editor).active {
         // Render (unless a macro is being played, in which case, don't bother)
         if !ged!(&editor).macro_man.playing || ged!(&editor).macro_man.just_completed {
@@ -192,38 +314,76 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
         let event = wait_for_event(&editor, &lua)?;
 
         // Handle the event
+        ged!(mut &editor).closing = false;
         let original_loc = ged!(&editor)
             .try_doc()
             .map(Document::char_loc)
             .unwrap_or_default();
-        handle_event(&editor, &event, &lua)?;
+        let original_focus = ged!(&editor).focus();
+        // (focused floating windows get key presses before anything else,
+        // then scratch buffers, then the keymap)
+        let intercepted = matches!(&event, CEvent::Key(key)
+            if window_key(&editor, &lua, key)
+                || buffer_key(&editor, &lua, key)
+                || chord_key(&editor, &lua, key));
+        if !intercepted {
+            handle_event(&editor, &event, &lua)?;
+        }
 
         // Handle multi cursors
-        if let CEvent::Key(_) = event {
+        if let (CEvent::Key(_), false) = (&event, intercepted) {
             let has_multicursors = !ged!(&editor)
                 .try_doc()
                 .map_or(true, |doc| doc.secondary_cursors.is_empty());
             if ged!(&editor).active && allowed_by_multi_cursor(&event) && has_multicursors {
-                handle_multiple_cursors(&editor, &event, &lua, &original_loc)?;
+                handle_multiple_cursors(&editor, &event, &lua)?;
             }
         }
 
         ged!(mut &editor).update_highlighter();
 
-        // Check for any commands to run
-        let command = ged!(&editor).command.clone();
-        if let Some(command) = command {
-            run_editor_command(&editor, &command, &lua);
+        // The layout only needs remembering while the user is closing everything down
+        if matches!(event, CEvent::Key(_) | CEvent::Paste(_)) && !ged!(&editor).closing {
+            ged!(mut &editor).session_snapshot = None;
+        }
+
+        // Open the command line if it was asked for, and run what the user types in
+        if std::mem::take(&mut ged!(mut &editor).command_line) {
+            command_line::open(&editor, &lua);
         }
-        ged!(mut &editor).command = None;
+
+        // Let plug-ins know what has happened
+        ged!(mut &editor).hook_movement(&original_focus, original_loc);
+        dispatch_hooks(&editor, &lua);
     }
 
     // Run any plugin cleanup operations
     let result = lua.load(run_key("exit")).exec();
     handle_lua_error("exit", result, &mut ged!(mut &editor).feedback);
 
+    // Stop listening for other ox processes (and let any that are waiting go)
+    #[cfg(not(target_os = "windows"))]
+    {
+        ged!(mut &editor).remote = None;
+    }
+
     ged!(mut &editor).terminal.end()?;
-    Ok(())
+
+    // Remember the layout and where the user was in each file for next time
+    let editor = ged!(&editor);
+    if let Err(err) = editor.history.write() {
+        eprintln!("[Error] Failed to save the history of recent files: {err}");
+    }
+    if let Some(path) = &editor.session_path {
+        let session = editor
+            .session_snapshot
+            .clone()
+            .unwrap_or_else(|| editor.session());
+        if let Err(err) = write_session(path, &session) {
+            eprintln!("[Error] Failed to save the session: {err}");
+        }
+    }
+    Ok(0)
 }
 
 fn handle_event(editor: &AnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
@@ -233,6 +393,11 @@ fn handle_event(editor: &AnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
         ged!(mut &editor).feedback = Feedback::None;
     }
 
+    // Close any floating windows that were only there until the next key press
+    if let CEvent::Key(_) = event {
+        ged!(mut &editor).close_transient_windows();
+    }
+
     // Handle plug-in before key press mappings
     if let CEvent::Key(key) = event {
         let key_str = key_to_string(key.modifiers, key.code);
@@ -391,18 +556,3 @@ fn handle_file_opening(editor: &AnyUserData, result: Result<()>, name: &str) {
         result => fatal_error(&format!("Error opening file '{name}': {result:?}")),
     }
 }
-
-/// Run a command in the editor
-fn run_editor_command(editor: &AnyUserData, cmd: &str, lua: &Lua) {
-    let cmd = cmd.replace('\'', "\\'").to_string();
-    if let [subcmd, arguments @ ..] = cmd.split(' ').collect::<Vec<&str>>().as_slice() {
-        let arguments = arguments.join("', '");
-        let code =
-            format!("(commands['{subcmd}'] or error('command not found'))({{'{arguments}'}})");
-        handle_lua_error(
-            subcmd,
-            lua.load(code).exec(),
-            &mut ged!(mut &editor).feedback,
-        );
-    }
-}
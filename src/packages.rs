@@ -0,0 +1,911 @@
+/// Plug-in packages (manifests, installing, updating and removing them, and the lockfile)
+use crate::cli::VERSION;
+use crate::config::config_dir;
+use crate::error::{OxError, Result};
+use flate2::read::GzDecoder;
+use semver::{Version, VersionReq};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Where plug-ins are downloaded from when they are installed by name
+const REGISTRY: &str = "https://raw.githubusercontent.com/curlpipe/ox/refs/heads/master/plugins/";
+
+/// The name of the file that describes a plug-in package
+const MANIFEST: &str = "ox-plugin.json";
+
+/// The name of the file that records what has been installed
+const LOCKFILE: &str = "plugins.lock";
+
+/// Holds the help dialog for the plug-in subcommands
+pub const HELP: &str = "\
+Ox Plug-in Manager
+
+USAGE: ox plugins [command] [arguments]
+
+COMMANDS:
+  install [sources]   : Install plug-ins (and their dependencies)
+  update [names]      : Update plug-ins (all of them if no names are given)
+  remove [names]      : Remove plug-ins
+  list                : List installed plug-ins and check they haven't been tampered with
+
+SOURCES:
+  A plug-in name (downloaded from the Ox repository), a folder or a tarball (.tar, .tar.gz, .tgz)
+
+EXAMPLES:
+  ox plugins install git
+  ox plugins install ./my-plugin
+  ox plugins install ~/downloads/my-plugin-1.2.0.tar.gz
+  ox plugins update
+  ox plugins remove git\
+";
+
+/// Create an error to show to the user
+fn error(msg: impl Into<String>) -> OxError {
+    OxError::Plugin { msg: msg.into() }
+}
+
+/// Describes a plug-in package, read from the `ox-plugin.json` file at its root:
+/// ```json
+/// {
+///     "name": "git",
+///     "version": "0.5.0",
+///     "ox": ">=0.7.0",
+///     "dependencies": {"json": "^1.2"},
+///     "capabilities": {"process": true, "editor": true}
+/// }
+/// ```
+/// The plug-in's code is loaded from `<name>.lua` at the root of the package
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub name: String,
+    pub version: Version,
+    /// The versions of Ox that this plug-in works with
+    pub ox: Option<VersionReq>,
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// The capabilities the plug-in needs (as declared for the sandbox)
+    pub capabilities: Map<String, Value>,
+}
+
+impl Manifest {
+    /// Read in a manifest
+    pub fn parse(text: &str) -> Result<Self> {
+        let value: Value =
+            serde_json::from_str(text).map_err(|e| error(format!("Invalid manifest: {e}")))?;
+        let string = |key: &str| value.get(key).and_then(Value::as_str);
+        let name = string("name").ok_or_else(|| error("The manifest has no name"))?;
+        let version = string("version").ok_or_else(|| error("The manifest has no version"))?;
+        let mut dependencies = BTreeMap::new();
+        if let Some(deps) = value.get("dependencies").and_then(Value::as_object) {
+            for (dep, req) in deps {
+                let req = req.as_str().unwrap_or("*");
+                dependencies.insert(dep.clone(), parse_req(req)?);
+            }
+        }
+        Ok(Self {
+            name: valid_name(name)?,
+            version: parse_version(version)?,
+            ox: string("ox").map(parse_req).transpose()?,
+            dependencies,
+            capabilities: value
+                .get("capabilities")
+                .and_then(Value::as_object)
+                .cloned()
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Make a manifest for a lone plug-in file that doesn't have one
+    /// (it has no version, so it only satisfies dependencies that accept any version)
+    pub fn unversioned(name: &str) -> Result<Self> {
+        Ok(Self {
+            name: valid_name(name)?,
+            version: Version::new(0, 0, 0),
+            ox: None,
+            dependencies: BTreeMap::new(),
+            capabilities: Map::new(),
+        })
+    }
+
+    /// Turn this manifest back into JSON
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({
+            "name": self.name,
+            "version": self.version.to_string(),
+            "dependencies": self.dependencies
+                .iter()
+                .map(|(dep, req)| (dep.clone(), Value::String(req.to_string())))
+                .collect::<Map<_, _>>(),
+            "capabilities": self.capabilities,
+        });
+        if let Some(ox) = &self.ox {
+            value["ox"] = Value::String(ox.to_string());
+        }
+        value
+    }
+}
+
+/// Check a plug-in name is safe to use as a folder name
+fn valid_name(name: &str) -> Result<String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(error(format!("'{name}' isn't a valid plug-in name")))
+    }
+}
+
+fn parse_version(version: &str) -> Result<Version> {
+    Version::parse(version).map_err(|e| error(format!("Invalid version '{version}': {e}")))
+}
+
+fn parse_req(req: &str) -> Result<VersionReq> {
+    VersionReq::parse(req).map_err(|e| error(format!("Invalid version requirement '{req}': {e}")))
+}
+
+/// A plug-in package that has been read in from somewhere, ready to be installed
+#[derive(Debug)]
+pub struct Package {
+    pub manifest: Manifest,
+    /// The files in the package (relative path => contents)
+    pub files: BTreeMap<String, Vec<u8>>,
+    /// Where the package came from (for updating later on)
+    pub source: String,
+}
+
+impl Package {
+    /// Read in a package from a name, folder or tarball
+    pub fn from_source(source: &str) -> Result<Self> {
+        let expanded = shellexpand::tilde(source).to_string();
+        let path = Path::new(&expanded);
+        if path.is_dir() {
+            let path = fs::canonicalize(path)?;
+            let source = format!("dir:{}", path.display());
+            Self::from_files(read_dir(&path)?, source, file_stem(&path))
+        } else if path.is_file() {
+            let path = fs::canonicalize(path)?;
+            let name = path.to_string_lossy();
+            if name.ends_with(".lua") {
+                let files = BTreeMap::from([(file_name(&path), fs::read(&path)?)]);
+                Self::from_files(files, format!("file:{name}"), file_stem(&path))
+            } else {
+                let files = read_tarball(&path)?;
+                Self::from_files(files, format!("tar:{name}"), None)
+            }
+        } else if valid_name(source).is_ok() {
+            Self::from_registry(source)
+        } else {
+            Err(error(format!("Couldn't find a plug-in at '{source}'")))
+        }
+    }
+
+    /// Download a plug-in (and its manifest, if it has one) from the Ox repository
+    pub fn from_registry(name: &str) -> Result<Self> {
+        let Some(code) = download(&format!("{REGISTRY}{name}.lua"))? else {
+            return Err(error(format!(
+                "Plug-in '{name}' wasn't found in the repository"
+            )));
+        };
+        let mut files = BTreeMap::from([(format!("{name}.lua"), code.into_bytes())]);
+        if let Some(manifest) = download(&format!("{REGISTRY}{name}.json"))? {
+            files.insert(MANIFEST.to_string(), manifest.into_bytes());
+        }
+        Self::from_files(files, "registry".to_string(), Some(name.to_string()))
+    }
+
+    /// Work out the manifest for a set of files (guessing it for a lone plug-in file)
+    fn from_files(
+        mut files: BTreeMap<String, Vec<u8>>,
+        source: String,
+        name: Option<String>,
+    ) -> Result<Self> {
+        // Packages may be wrapped in a folder (as tarballs often are)
+        if !files.contains_key(MANIFEST) {
+            let wrapped = files
+                .keys()
+                .find(|path| path.ends_with(&format!("/{MANIFEST}")))
+                .and_then(|path| path.strip_suffix(MANIFEST))
+                .map(str::to_string);
+            if let Some(prefix) = wrapped {
+                files = files
+                    .into_iter()
+                    .filter_map(|(path, data)| {
+                        Some((path.strip_prefix(&prefix)?.to_string(), data))
+                    })
+                    .collect();
+            }
+        }
+        let manifest = if let Some(manifest) = files.get(MANIFEST) {
+            Manifest::parse(&String::from_utf8_lossy(manifest))?
+        } else {
+            let lua: Vec<&String> = files
+                .keys()
+                .filter(|p| Path::new(p).extension().is_some_and(|e| e == "lua"))
+                .collect();
+            let name = match (name, lua.as_slice()) {
+                (Some(name), _) if files.contains_key(&format!("{name}.lua")) => name,
+                (_, [only]) => only.trim_end_matches(".lua").to_string(),
+                _ => return Err(error(format!("No {MANIFEST} was found in '{source}'"))),
+            };
+            // Keep just the plug-in file, with a manifest so it can be checked later on
+            files.retain(|path, _| *path == format!("{name}.lua"));
+            let manifest = Manifest::unversioned(&name)?;
+            let text = serde_json::to_string_pretty(&manifest.to_json()).unwrap_or_default();
+            files.insert(MANIFEST.to_string(), text.into_bytes());
+            manifest
+        };
+        if !files.contains_key(&format!("{}.lua", manifest.name)) {
+            return Err(error(format!(
+                "Plug-in '{}' doesn't contain {}.lua",
+                manifest.name, manifest.name
+            )));
+        }
+        Ok(Self {
+            manifest,
+            files,
+            source,
+        })
+    }
+
+    /// Work out a checksum of every file in the package
+    pub fn checksum(&self) -> String {
+        checksum(&self.files)
+    }
+}
+
+/// Download a file (which is None if it doesn't exist)
+fn download(url: &str) -> Result<Option<String>> {
+    match ureq::get(url).call() {
+        Ok(response) => Ok(Some(response.into_string()?)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(err) => Err(error(format!("Failed to download '{url}': {err}"))),
+    }
+}
+
+/// Work out a checksum of a set of files (covering their names and contents)
+fn checksum(files: &BTreeMap<String, Vec<u8>>) -> String {
+    let mut hasher = Sha256::new();
+    for (path, data) in files {
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(data);
+    }
+    let digest = hasher.finalize();
+    let hex = digest.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    });
+    format!("sha256:{hex}")
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn file_stem(path: &Path) -> Option<String> {
+    path.file_stem().map(|n| n.to_string_lossy().to_string())
+}
+
+/// Make sure a path within a package can't escape the folder it is installed into
+fn safe_path(path: &Path) -> Option<String> {
+    let parts: Option<Vec<String>> = path
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    parts.filter(|p| !p.is_empty()).map(|p| p.join("/"))
+}
+
+/// Read in every file within a folder
+fn read_dir(root: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    let mut to_visit = vec![root.to_path_buf()];
+    while let Some(dir) = to_visit.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let kind = entry.file_type()?;
+            // (folders behind symlinks are skipped, as they could lead back up the tree)
+            if kind.is_symlink() && path.is_dir() {
+                continue;
+            }
+            if kind.is_dir() {
+                // Version control data isn't part of the plug-in
+                if file_name(&path) != ".git" {
+                    to_visit.push(path);
+                }
+            } else if let Some(relative) = path.strip_prefix(root).ok().and_then(safe_path) {
+                files.insert(relative, fs::read(&path)?);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Read in every file within a tarball (which may be compressed with gzip)
+fn read_tarball(path: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let data = fs::read(path)?;
+    let data = if data.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = vec![];
+        GzDecoder::new(data.as_slice()).read_to_end(&mut decompressed)?;
+        decompressed
+    } else {
+        data
+    };
+    let mut files = BTreeMap::new();
+    let mut archive = tar::Archive::new(data.as_slice());
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(relative) = safe_path(&entry.path()?) else {
+            return Err(error(format!(
+                "'{}' contains an unsafe path",
+                path.display()
+            )));
+        };
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents)?;
+        files.insert(relative, contents);
+    }
+    Ok(files)
+}
+
+/// What the lockfile records about an installed plug-in
+#[derive(Debug, Clone)]
+pub struct Locked {
+    pub version: Version,
+    pub source: String,
+    pub checksum: String,
+    pub dependencies: BTreeMap<String, VersionReq>,
+    pub capabilities: Map<String, Value>,
+}
+
+/// Keeps track of installed plug-ins, so they can be updated and checked for tampering
+#[derive(Debug, Default)]
+pub struct Lockfile {
+    pub plugins: BTreeMap<String, Locked>,
+}
+
+impl Lockfile {
+    /// Read in the lockfile (which is empty if nothing has been installed)
+    pub fn load(path: &Path) -> Result<Self> {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Ok(Self::default());
+        };
+        let value: Value =
+            serde_json::from_str(&text).map_err(|e| error(format!("Invalid lockfile: {e}")))?;
+        let mut plugins = BTreeMap::new();
+        for (name, entry) in value
+            .get("plugins")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let string = |key: &str| entry.get(key).and_then(Value::as_str).unwrap_or_default();
+            let mut dependencies = BTreeMap::new();
+            if let Some(deps) = entry.get("dependencies").and_then(Value::as_object) {
+                for (dep, req) in deps {
+                    dependencies.insert(dep.clone(), parse_req(req.as_str().unwrap_or("*"))?);
+                }
+            }
+            let locked = Locked {
+                version: parse_version(string("version"))?,
+                source: string("source").to_string(),
+                checksum: string("checksum").to_string(),
+                dependencies,
+                capabilities: entry
+                    .get("capabilities")
+                    .and_then(Value::as_object)
+                    .cloned()
+                    .unwrap_or_default(),
+            };
+            plugins.insert(name.clone(), locked);
+        }
+        Ok(Self { plugins })
+    }
+
+    /// Write the lockfile back to the disk
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let plugins: Map<String, Value> = self
+            .plugins
+            .iter()
+            .map(|(name, locked)| {
+                let entry = json!({
+                    "version": locked.version.to_string(),
+                    "source": locked.source,
+                    "checksum": locked.checksum,
+                    "dependencies": locked.dependencies
+                        .iter()
+                        .map(|(dep, req)| (dep.clone(), Value::String(req.to_string())))
+                        .collect::<Map<_, _>>(),
+                    "capabilities": locked.capabilities,
+                });
+                (name.clone(), entry)
+            })
+            .collect();
+        let text = serde_json::to_string_pretty(&json!({ "plugins": plugins })).unwrap_or_default();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text + "\n")?;
+        Ok(())
+    }
+}
+
+/// Installs, updates and removes plug-in packages
+pub struct Packages {
+    /// The folder plug-ins are installed into
+    dir: PathBuf,
+    /// The configuration file that loads plug-ins
+    config: PathBuf,
+    lock: Lockfile,
+    lock_path: PathBuf,
+}
+
+impl Packages {
+    /// Get ready to manage the plug-ins for a configuration file
+    pub fn new(config_path: &str) -> Result<Self> {
+        let dir = config_dir().ok_or_else(|| error("Couldn't find your home folder"))?;
+        let config = PathBuf::from(shellexpand::tilde(config_path).to_string());
+        let lock_path = dir.join(LOCKFILE);
+        let lock = Lockfile::load(&lock_path)?;
+        Ok(Self {
+            dir,
+            config,
+            lock,
+            lock_path,
+        })
+    }
+
+    /// Run a plug-in subcommand
+    pub fn run(&mut self, args: &[String]) -> Result<()> {
+        let (command, args) = args
+            .split_first()
+            .map_or(("", &[][..]), |(c, a)| (c.as_str(), a));
+        match (command, args) {
+            ("install", sources) if !sources.is_empty() => {
+                for source in sources {
+                    let package = Package::from_source(source)?;
+                    self.install(package, &mut vec![])?;
+                }
+            }
+            ("update", names) => self.update(names)?,
+            ("remove", names) if !names.is_empty() => {
+                for name in names {
+                    self.remove(name)?;
+                }
+            }
+            ("list", []) => self.list(),
+            _ => println!("{HELP}"),
+        }
+        Ok(())
+    }
+
+    /// Install a package, along with any dependencies that aren't yet installed
+    fn install(&mut self, package: Package, installing: &mut Vec<String>) -> Result<()> {
+        let manifest = &package.manifest;
+        let name = manifest.name.clone();
+        if let Some(ox) = &manifest.ox {
+            if !ox.matches(&parse_version(VERSION)?) {
+                return Err(error(format!(
+                    "'{name}' needs Ox {ox} (you have {VERSION})"
+                )));
+            }
+        }
+        // Make sure the download hasn't changed since it was last installed
+        if let Some(locked) = self.lock.plugins.get(&name) {
+            let same = locked.version == manifest.version && locked.source == package.source;
+            if same && locked.checksum != package.checksum() {
+                return Err(error(format!(
+                    "'{name}' {} doesn't match the checksum in the lockfile (it may have been tampered with)",
+                    manifest.version
+                )));
+            }
+        }
+        // Resolve dependencies
+        installing.push(name.clone());
+        for (dep, req) in &manifest.dependencies {
+            if installing.contains(dep) {
+                return Err(error(format!("'{name}' and '{dep}' depend on each other")));
+            }
+            if self
+                .lock
+                .plugins
+                .get(dep)
+                .is_some_and(|l| req.matches(&l.version))
+            {
+                continue;
+            }
+            let dependency = Package::from_registry(dep)?;
+            if !req.matches(&dependency.manifest.version) {
+                return Err(error(format!(
+                    "'{name}' needs {dep} {req}, but only {} is available",
+                    dependency.manifest.version
+                )));
+            }
+            self.install(dependency, installing)?;
+        }
+        installing.pop();
+        self.check_dependents(&name, &manifest.version)?;
+        // Record the plug-in before its files are written, putting things back if that fails
+        let previous = self.lock.plugins.insert(
+            name.clone(),
+            Locked {
+                version: manifest.version.clone(),
+                checksum: package.checksum(),
+                source: package.source,
+                dependencies: manifest.dependencies.clone(),
+                capabilities: manifest.capabilities.clone(),
+            },
+        );
+        self.lock.save(&self.lock_path)?;
+        if let Err(err) = self.write_files(&name, &package.files) {
+            match previous {
+                Some(locked) => self.lock.plugins.insert(name, locked),
+                None => self.lock.plugins.remove(&name),
+            };
+            self.lock.save(&self.lock_path)?;
+            return Err(err);
+        }
+        self.enable(&name)?;
+        let capabilities: Vec<&String> = manifest.capabilities.keys().collect();
+        if !capabilities.is_empty() {
+            println!(
+                "'{name}' will ask to use: {}",
+                capabilities
+                    .iter()
+                    .map(|c| c.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        println!("Installed {name} {}", manifest.version);
+        Ok(())
+    }
+
+    /// Write out the files of a plug-in, replacing any that were there before
+    /// (they are written somewhere else first, so a failure leaves the old ones alone)
+    fn write_files(&self, name: &str, files: &BTreeMap<String, Vec<u8>>) -> Result<()> {
+        let target = self.dir.join(name);
+        let staging = self.dir.join(format!(".{name}.new"));
+        let result = (|| -> Result<()> {
+            if staging.exists() {
+                fs::remove_dir_all(&staging)?;
+            }
+            for (path, data) in files {
+                let path = staging.join(path);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(path, data)?;
+            }
+            if target.exists() {
+                fs::remove_dir_all(&target)?;
+            }
+            fs::rename(&staging, &target)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&staging);
+        }
+        result
+    }
+
+    /// Make sure plug-ins that depend on a plug-in still work with a new version of it
+    fn check_dependents(&self, name: &str, version: &Version) -> Result<()> {
+        for (other, locked) in &self.lock.plugins {
+            if let Some(req) = locked.dependencies.get(name) {
+                if !req.matches(version) {
+                    return Err(error(format!(
+                        "'{other}' needs {name} {req}, which {version} doesn't satisfy"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Update plug-ins from wherever they were installed from
+    fn update(&mut self, names: &[String]) -> Result<()> {
+        let names: Vec<String> = if names.is_empty() {
+            self.lock.plugins.keys().cloned().collect()
+        } else {
+            names.to_vec()
+        };
+        for name in names {
+            let Some(locked) = self.lock.plugins.get(&name).cloned() else {
+                return Err(error(format!("'{name}' isn't installed")));
+            };
+            let package = match locked.source.split_once(':') {
+                Some((_, path)) => Package::from_source(path)?,
+                None => Package::from_registry(&name)?,
+            };
+            let version = &package.manifest.version;
+            let changed = package.checksum() != locked.checksum;
+            if *version < locked.version || (*version == locked.version && !changed) {
+                println!("{name} is up to date ({})", locked.version);
+                continue;
+            }
+            // Updates are expected to change the plug-in's contents
+            self.lock.plugins.remove(&name);
+            let result = self.install(package, &mut vec![]);
+            if result.is_err() {
+                self.lock.plugins.insert(name, locked);
+                self.lock.save(&self.lock_path)?;
+            }
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Remove an installed plug-in
+    fn remove(&mut self, name: &str) -> Result<()> {
+        if !self.lock.plugins.contains_key(name) {
+            return Err(error(format!("'{name}' isn't installed")));
+        }
+        let dependents: Vec<&String> = self
+            .lock
+            .plugins
+            .iter()
+            .filter(|(_, locked)| locked.dependencies.contains_key(name))
+            .map(|(other, _)| other)
+            .collect();
+        if let Some(other) = dependents.first() {
+            return Err(error(format!("'{other}' depends on '{name}'")));
+        }
+        // (the lockfile is updated first, so it never lists a plug-in that is half removed)
+        self.lock.plugins.remove(name);
+        self.lock.save(&self.lock_path)?;
+        self.disable(name)?;
+        let target = self.dir.join(name);
+        if target.exists() {
+            fs::remove_dir_all(target)?;
+        }
+        println!("Removed {name}");
+        Ok(())
+    }
+
+    /// Show installed plug-ins, checking they still match the lockfile
+    fn list(&self) {
+        if self.lock.plugins.is_empty() {
+            println!("No plug-ins have been installed with `ox plugins install`");
+        }
+        for (name, locked) in &self.lock.plugins {
+            let status = match read_dir(&self.dir.join(name)) {
+                Ok(files) if checksum(&files) == locked.checksum => "ok",
+                Ok(files) if files.is_empty() => "missing",
+                Ok(_) => "modified",
+                Err(_) => "missing",
+            };
+            println!("{name} {} ({}) [{status}]", locked.version, locked.source);
+        }
+    }
+
+    /// The line in the configuration file that loads a plug-in
+    fn load_line(name: &str) -> String {
+        format!("load_plugin(\"{name}/{name}.lua\")")
+    }
+
+    /// Make sure the configuration file loads a plug-in (replacing any older style of loading it)
+    /// (only the lines that load the plug-in are touched, the rest of the file is left as it was)
+    fn enable(&self, name: &str) -> Result<()> {
+        let config = fs::read_to_string(&self.config).unwrap_or_default();
+        let old = [
+            format!("load_plugin(\"{name}.lua\")"),
+            format!("load_plugin('{name}.lua')"),
+        ];
+        let mut result: String = config
+            .split_inclusive('\n')
+            .filter(|line| !old.iter().any(|o| line.trim() == o))
+            .collect();
+        let line = Self::load_line(name);
+        if !result.split_inclusive('\n').any(|l| l.trim() == line) {
+            let newline = if config.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            };
+            if !result.is_empty() && !result.ends_with('\n') {
+                result.push_str(newline);
+            }
+            result.push_str(&line);
+            result.push_str(newline);
+        }
+        if result != config {
+            fs::write(&self.config, result)?;
+        }
+        Ok(())
+    }
+
+    /// Stop the configuration file from loading a plug-in
+    fn disable(&self, name: &str) -> Result<()> {
+        let Ok(config) = fs::read_to_string(&self.config) else {
+            return Ok(());
+        };
+        let line = Self::load_line(name);
+        let result: String = config
+            .split_inclusive('\n')
+            .filter(|l| l.trim() != line)
+            .collect();
+        if result != config {
+            fs::write(&self.config, result)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifests_are_read_in() {
+        let manifest = Manifest::parse(
+            r#"{
+                "name": "git",
+                "version": "0.5.0",
+                "ox": ">=0.7.0",
+                "dependencies": {"json": "^1.2", "utils": "*"},
+                "capabilities": {"process": true}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.name, "git");
+        assert_eq!(manifest.version, Version::new(0, 5, 0));
+        assert!(manifest.ox.unwrap().matches(&Version::new(0, 7, 6)));
+        let json = &manifest.dependencies["json"];
+        assert!(json.matches(&Version::new(1, 4, 0)));
+        assert!(!json.matches(&Version::new(2, 0, 0)));
+        assert!(manifest.dependencies["utils"].matches(&Version::new(0, 0, 0)));
+        assert_eq!(manifest.capabilities["process"], Value::Bool(true));
+    }
+
+    #[test]
+    fn bad_manifests_are_rejected() {
+        let bad = [
+            r#"{"version": "1.0.0"}"#,
+            r#"{"name": "git"}"#,
+            r#"{"name": "../git", "version": "1.0.0"}"#,
+            r#"{"name": "git", "version": "1.0"}"#,
+            r#"{"name": "git", "version": "1.0.0", "ox": "newest"}"#,
+            r#"{"name": "git", "version": "1.0.0", "dependencies": {"json": "~>1"}}"#,
+            "not json",
+        ];
+        for text in bad {
+            assert!(Manifest::parse(text).is_err(), "{text} was accepted");
+        }
+    }
+
+    #[test]
+    fn manifests_survive_being_written_out() {
+        let text =
+            r#"{"name": "a", "version": "1.2.3", "ox": ">=0.7", "dependencies": {"b": "^2"}}"#;
+        let manifest = Manifest::parse(text).unwrap();
+        let again = Manifest::parse(&manifest.to_json().to_string()).unwrap();
+        assert_eq!(again.name, manifest.name);
+        assert_eq!(again.version, manifest.version);
+        assert_eq!(again.ox, manifest.ox);
+        assert_eq!(again.dependencies, manifest.dependencies);
+    }
+
+    #[test]
+    fn lockfiles_survive_being_saved() {
+        let dir = std::env::temp_dir().join(format!("ox-lockfile-{}", std::process::id()));
+        let path = dir.join(LOCKFILE);
+        // (a lockfile that doesn't exist yet is empty)
+        assert!(Lockfile::load(&path).unwrap().plugins.is_empty());
+        let mut lock = Lockfile::default();
+        lock.plugins.insert(
+            "git".to_string(),
+            Locked {
+                version: Version::new(0, 5, 0),
+                source: "dir:/tmp/git".to_string(),
+                checksum: "sha256:abc".to_string(),
+                dependencies: BTreeMap::from([("json".to_string(), parse_req("^1.2").unwrap())]),
+                capabilities: Map::from_iter([("process".to_string(), Value::Bool(true))]),
+            },
+        );
+        lock.save(&path).unwrap();
+        let loaded = Lockfile::load(&path).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        let git = &loaded.plugins["git"];
+        assert_eq!(git.version, Version::new(0, 5, 0));
+        assert_eq!(git.source, "dir:/tmp/git");
+        assert_eq!(git.checksum, "sha256:abc");
+        assert_eq!(git.dependencies["json"], parse_req("^1.2").unwrap());
+        assert_eq!(git.capabilities["process"], Value::Bool(true));
+    }
+
+    #[test]
+    fn checksums_cover_names_and_contents() {
+        let files = |list: &[(&str, &str)]| -> BTreeMap<String, Vec<u8>> {
+            list.iter()
+                .map(|(path, data)| (path.to_string(), data.as_bytes().to_vec()))
+                .collect()
+        };
+        let original = checksum(&files(&[("a.lua", "x"), ("b.lua", "y")]));
+        assert!(original.starts_with("sha256:"));
+        assert_eq!(original.len(), "sha256:".len() + 64);
+        assert_eq!(
+            original,
+            checksum(&files(&[("b.lua", "y"), ("a.lua", "x")]))
+        );
+        let changes = [
+            files(&[("a.lua", "x"), ("b.lua", "z")]),
+            files(&[("a.lua", "x"), ("c.lua", "y")]),
+            files(&[("a.lua", "x")]),
+            // (moving bytes from one file to another is still a change)
+            files(&[("a.lua", "xy"), ("b.lua", "")]),
+        ];
+        for changed in changes {
+            assert_ne!(original, checksum(&changed));
+        }
+    }
+
+    #[test]
+    fn enabling_only_touches_the_loading_line() {
+        let dir = std::env::temp_dir().join(format!("ox-enable-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let packages = Packages {
+            dir: dir.clone(),
+            config: dir.join(".oxrc"),
+            lock: Lockfile::default(),
+            lock_path: dir.join(LOCKFILE),
+        };
+        let cases = [
+            // (line endings and a missing final newline are kept as they were)
+            (
+                "-- config\r\nload_plugin(\"git.lua\")\r\nx = 1",
+                "-- config\r\nx = 1\r\nload_plugin(\"git/git.lua\")\r\n",
+                "-- config\r\nx = 1\r\n",
+            ),
+            (
+                "x = 1\n",
+                "x = 1\nload_plugin(\"git/git.lua\")\n",
+                "x = 1\n",
+            ),
+            ("", "load_plugin(\"git/git.lua\")\n", ""),
+        ];
+        for (config, enabled, disabled) in cases {
+            fs::write(&packages.config, config).unwrap();
+            packages.enable("git").unwrap();
+            assert_eq!(fs::read_to_string(&packages.config).unwrap(), enabled);
+            // (enabling twice doesn't load the plug-in twice)
+            packages.enable("git").unwrap();
+            assert_eq!(fs::read_to_string(&packages.config).unwrap(), enabled);
+            packages.disable("git").unwrap();
+            assert_eq!(fs::read_to_string(&packages.config).unwrap(), disabled);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn folders_behind_symlinks_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("ox-symlinks-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("git.lua"), "x").unwrap();
+        fs::write(dir.join("lib").join("util.lua"), "y").unwrap();
+        // (a link back up the tree would otherwise be followed forever)
+        std::os::unix::fs::symlink(&dir, dir.join("lib").join("loop")).unwrap();
+        let files = read_dir(&dir);
+        let _ = fs::remove_dir_all(&dir);
+        let names: Vec<String> = files.unwrap().into_keys().collect();
+        assert_eq!(names, vec!["git.lua", "lib/util.lua"]);
+    }
+}
@@ -34,6 +34,8 @@ end
 
 plugins = {}
 builtins = {}
+-- Tab completion for the arguments of commands (see commands in the configuration file)
+command_completers = {}
 plugin_issues = false
 
 function load_plugin(base)
@@ -65,6 +67,9 @@ function load_plugin(base)
     end
 end
 
+-- Plug-ins declare the capabilities they need (this only has an effect within a sandbox)
+function capabilities(list) end
+
 -- Python interoperability tools
 python_interop = {}
 
@@ -158,6 +163,55 @@ function shell:kill(pid)
     end
 end
 
+function jobs.run(cmd, callback)
+    -- Runs a command in the background, passing the exit code, stdout and stderr to the callback
+    local stdout = {}
+    local stderr = {}
+    return jobs.spawn(cmd, {
+        on_stdout = function(line) table.insert(stdout, line) end,
+        on_stderr = function(line) table.insert(stderr, line) end,
+        on_exit = function(code)
+            callback(code, table.concat(stdout, "\n"), table.concat(stderr, "\n"))
+        end,
+    })
+end
+
+-- Coroutine scheduling (allows plug-ins to wait on things without blocking the editor)
+local function resume(co, ...)
+    local ok, err = coroutine.resume(co, ...)
+    if not ok then
+        error(err, 0)
+    end
+end
+
+function async(fn, ...)
+    -- Runs a function as a coroutine, within which sleep and await can be used
+    resume(coroutine.create(fn), ...)
+end
+
+function sleep(ms)
+    -- Pauses the current coroutine for a number of milliseconds
+    local co, is_main = coroutine.running()
+    if is_main then
+        error("sleep can only be used within a function run by async", 2)
+    end
+    after(ms, function() resume(co) end)
+    return coroutine.yield()
+end
+
+function await(fn, ...)
+    -- Calls a function that takes a callback as its last argument (e.g. http.get or jobs.run)
+    -- and pauses the current coroutine until the callback is called, returning its arguments
+    local co, is_main = coroutine.running()
+    if is_main then
+        error("await can only be used within a function run by async", 2)
+    end
+    local args = table.pack(...)
+    args[args.n + 1] = function(...) resume(co, ...) end
+    fn(table.unpack(args, 1, args.n + 1))
+    return coroutine.yield()
+end
+
 -- Behaviour for compiling / running projects
 runner = {
     ["Rust"] = {
@@ -174,6 +228,9 @@ runner = {
     },
 }
 
+-- Named tasks that can be run from anywhere (projects can add their own in .ox/tasks.lua)
+tasks = {}
+
 -- Add types for built-in file type detection
 -- Colours are in the format of a string of:
 file_types = {
@@ -680,6 +737,8 @@ file_types = {
         extensions = {"mk", "mak"},
         modelines = {},
         color = "grey",
+        -- (make only accepts tabs)
+        settings = {indentation = "tabs"},
     },
     ["Markdown"] = {
         icon = " ",
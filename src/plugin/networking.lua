@@ -1,33 +1,39 @@
 -- Networking library (for plug-ins to use)
--- Uses curl
+-- Built on top of http.request, which is provided natively by the editor
+--
+-- http.request({method = "GET", url = "...", headers = {}, body = "...", timeout = 1000}, callback)
+--   returns {ok, status, headers, body, error}
+--   if a callback is given, the request is made in the background and the response is passed to it
+--
+-- The functions below return just the body, but will pass the full response to any callback
 
-http = {
-    backend = "curl",
-}
-
-local function execute(cmd)
-    local handle = io.popen(cmd)
-    local result = handle:read("*a")
-    handle:close()
-    return result
+local function make(method, url, body, options, callback)
+    if type(options) == "function" then
+        callback = options
+        options = {}
+    end
+    local request = options or {}
+    request.method = method
+    request.url = url
+    request.body = body
+    if callback ~= nil then
+        return http.request(request, callback)
+    end
+    return http.request(request).body
 end
 
-function http.get(url)
-	local cmd = 'curl -s -X GET "' .. url .. '"'
-    return execute(cmd)
+function http.get(url, options, callback)
+    return make("GET", url, nil, options, callback)
 end
 
-function http.post(url, data)
-    local cmd = 'curl -s -X POST -d "' .. data .. '" "' .. url .. '"'
-    return execute(cmd)
+function http.post(url, data, options, callback)
+    return make("POST", url, data, options, callback)
 end
 
-function http.put(url, data)
-    local cmd = 'curl -s -X PUT -d "' .. data .. '"  "' .. url .. '"'
-    return execute(cmd)
+function http.put(url, data, options, callback)
+    return make("PUT", url, data, options, callback)
 end
 
-function http.delete(url)
-    local cmd = 'curl -s -X DELETE "' .. url .. '"'
-    return execute(cmd)
+function http.delete(url, options, callback)
+    return make("DELETE", url, nil, options, callback)
 end
@@ -105,6 +105,92 @@ function plugin_manager:status()
     editor:display_info(tostring(count) .. " plug-ins installed: " .. list)
 end
 
+-- Find a loaded plug-in, showing an error if there isn't one by that name
+function plugin_manager:module(name)
+    local module = plugin_loader.get(name or "")
+    if module == nil then
+        editor:display_error("No plug-in called '" .. tostring(name) .. "' is loaded")
+    end
+    return module
+end
+
+-- Turn a plug-in back on, or run it again to pick up any changes made to it
+function plugin_manager:enable(name, done)
+    local module = self:module(name)
+    if module == nil then return end
+    plugin_loader.disabled[name] = nil
+    module.enabled = true
+    local ok, err = plugin_loader.load(module)
+    if ok then
+        editor:display_info("Plug-in '" .. name .. "' " .. (done or "enabled"))
+    else
+        editor:display_error("Plug-in '" .. name .. "' failed to load: " .. err)
+    end
+end
+
+-- Turn a plug-in off for the rest of the session
+function plugin_manager:disable(name)
+    local module = self:module(name)
+    if module == nil then return end
+    plugin_loader.unload(module)
+    module.enabled = false
+    plugin_loader.disabled[name] = true
+    editor:display_info("Plug-in '" .. name .. "' disabled")
+end
+
+-- Show load errors, timings and registered hooks for each plug-in
+function plugin_manager:health()
+    local lines = {"Plug-in health", ""}
+    if #plugin_loader.modules == 0 then
+        table.insert(lines, "No plug-ins are loaded")
+    end
+    for _, module in ipairs(plugin_loader.modules) do
+        local status = "enabled"
+        if module.auto_disabled then
+            status = "disabled (too many errors)"
+        elseif not module.enabled then
+            status = "disabled"
+        elseif module.load_error then
+            status = "failed to load"
+        end
+        local timing = "never loaded"
+        if module.load_time then
+            timing = string.format("took %.2fms to load", module.load_time)
+        end
+        table.insert(lines, string.format(
+            "%s: %s, %s, %d error(s)", module.name, status, timing, module.errors
+        ))
+        table.insert(lines, "    path: " .. module.path)
+        local hooks = {}
+        for key, _ in pairs(module.hooks) do
+            table.insert(hooks, "'" .. key .. "'")
+        end
+        table.sort(hooks)
+        if #hooks > 0 then
+            table.insert(lines, "    hooks: " .. table.concat(hooks, ", "))
+        end
+        local names = {}
+        for command, _ in pairs(module.commands) do
+            table.insert(names, command)
+        end
+        table.sort(names)
+        if #names > 0 then
+            table.insert(lines, "    commands: " .. table.concat(names, ", "))
+        end
+        if module.load_error then
+            table.insert(lines, "    load error: " .. module.load_error)
+        end
+        if module.last_error then
+            table.insert(lines, "    last error: " .. module.last_error)
+        end
+    end
+    local buffer = editor:create_buffer({ name = "[Plug-in Health]", read_only = true })
+    if buffer then
+        buffer:set_lines(lines)
+        buffer:move_to(0, 0)
+    end
+end
+
 -- Verify whether or not a plug-in is built-in
 function plugin_manager:plugin_is_builtin(plugin)
     local base = plugin .. ".lua"
@@ -272,6 +358,19 @@ function plugin_manager:latest_version(plugin)
     return version
 end
 
+command_completers["plugin"] = function(arguments)
+    if #arguments == 0 then
+        return {"install", "uninstall", "status", "health", "enable", "disable", "reload", "update"}
+    elseif #arguments == 1 and arguments[1] ~= "install" and arguments[1] ~= "update" then
+        local names = {}
+        for _, module in ipairs(plugin_loader.modules) do
+            table.insert(names, module.name)
+        end
+        return names
+    end
+    return {}
+end
+
 commands["plugin"] = function(arguments)
     if arguments[1] == "install" then
         local result = plugin_manager:install(arguments[2])
@@ -282,6 +381,18 @@ commands["plugin"] = function(arguments)
         plugin_manager:uninstall(arguments[2])
     elseif arguments[1] == "status" then
         plugin_manager:status()
+    elseif arguments[1] == "health" then
+        plugin_manager:health()
+    elseif arguments[1] == "enable" then
+        plugin_manager:enable(arguments[2])
+    elseif arguments[1] == "disable" then
+        plugin_manager:disable(arguments[2])
+    elseif arguments[1] == "reload" then
+        if arguments[2] == nil or arguments[2] == "" then
+            editor:reload_plugins()
+        else
+            plugin_manager:enable(arguments[2], "reloaded")
+        end
     elseif arguments[1] == "update" then
         -- editor:display_info(tostring(local_copy) .. " locally vs " .. tostring(latest_copy) .. " latest")
         editor:display_info("Please wait whilst versions are checked...")
@@ -2,8 +2,26 @@
 
 global_event_mapping = {}
 
-function merge_event_mapping()
+-- Key names that are bound under a different name to the one the editor uses
+local key_aliases = {
+    space = " ",
+    ctrl_space = "ctrl_ ",
+    alt_space = "alt_ ",
+    ctrl_alt_space = "ctrl_alt_ ",
+    shift_tab = "shift_backtab",
+}
+
+local function normalise_key(key)
+    local prefix, name = key:match("^(%a+:)(.+)$")
+    if prefix == nil then
+        prefix, name = "", key
+    end
+    return prefix .. (key_aliases[name] or name)
+end
+
+local function merge_event_mapping()
     for key, f in pairs(event_mapping) do
+        key = normalise_key(key)
         if global_event_mapping[key] ~= nil then
             table.insert(global_event_mapping[key], f)
         else
@@ -13,11 +31,612 @@ function merge_event_mapping()
     event_mapping = {}
 end
 
-for c, path in ipairs(plugins) do
+-- Plug-ins run in their own environment, where access to files, programs, the network
+-- and changes to the editor are limited to the capabilities they declare, for example:
+--
+-- capabilities {
+--     process = true,              -- run programs (shell, jobs, os.execute, io.popen)
+--     network = true,              -- make web requests (http)
+--     editor = true,               -- make changes in the editor (anything that isn't just reading)
+--     fs = {"~/.config/ox"},       -- access files within these folders (or true for anywhere)
+-- }
+--
+-- The user is asked to approve each capability the first time it is used,
+-- and approvals are kept in grants.json (next to the list of trusted project files)
+
+local capability_descriptions = {
+    process = "run programs",
+    network = "access the network",
+    editor = "make changes in the editor",
+}
+
+-- Editor fields and methods that plug-ins can use without the editor capability
+local editor_reads = {}
+for _, name in ipairs({
+    "cursor", "selection", "document_name", "document_length", "version",
+    "current_document_id", "task_status", "document_count", "document_type", "file_name",
+    "file_extension", "file_path", "cwd", "macro_recording", "macro_playing",
+    "display_error", "display_warning", "display_info", "prompt", "get", "get_character",
+    "get_character_at", "get_line", "get_line_at", "rerender", "rerender_feedback_line",
+    "rerender_status_line", "task_list", "open_window", "close_window", "window_selection",
+}) do
+    editor_reads[name] = true
+end
+
+-- Capabilities the user has turned down this session (so they aren't asked again)
+local capability_denials = {}
+
+local function ask_user(question)
+    if headless then
+        -- Nobody is around to answer, so only capabilities granted beforehand can be used
+        return false
+    end
+    if plugins_loading and not editor_started then
+        -- The editor isn't on screen yet, so ask in the terminal
+        io.write(question .. " ")
+        io.flush()
+        return io.read("l") == "y"
+    end
+    local ok, response = pcall(editor.prompt, editor, question)
+    return ok and response == "y"
+end
+
+-- Capabilities the user has granted to plug-ins
+-- e.g. { git = { process = true }, emmet = { fs = { ["~/.config/ox"] = true } } }
+local grants_path = plugin_path .. path_sep .. "grants.json"
+
+local function load_grants()
+    local file = io.open(grants_path, "r")
+    if file == nil then
+        return {}
+    end
+    local ok, grants = pcall(json.decode, file:read("a"))
+    file:close()
+    if ok and type(grants) == "table" then
+        return grants
+    end
+    return {}
+end
+
+local capability_grants = load_grants()
+
+local function grant_capability(plugin, capability, path)
+    local grants = capability_grants[plugin] or {}
+    if capability == "fs" then
+        grants.fs = grants.fs or {}
+        grants.fs[path or "*"] = true
+    else
+        grants[capability] = true
+    end
+    capability_grants[plugin] = grants
+    -- (failing to save only means the user is asked again next time)
+    local ok, text = pcall(json.encode, capability_grants)
+    local file = ok and io.open(grants_path, "w")
+    if file then
+        file:write(text)
+        file:close()
+    end
+end
+
+-- Make sure a plug-in is allowed to use a capability, asking the user if need be
+local function require_capability(plugin, capability, path)
+    local key = capability .. ":" .. (path or "")
+    local granted = capability_grants[plugin] or {}
+    if capability == "fs" then
+        granted = (granted.fs or {})[path]
+    else
+        granted = granted[capability]
+    end
+    if granted then
+        return
+    end
+    local description = capability_descriptions[capability]
+    if capability == "fs" then
+        description = path == "*" and "access your files" or "access files in " .. path
+    end
+    local denied = capability_denials[plugin] or {}
+    if denied[key] then
+        error("plug-in '" .. plugin .. "' wasn't allowed to " .. description, 3)
+    end
+    if plugins_loading and editor_started then
+        -- (the editor is busy reloading plug-ins, so the user can't be asked right now)
+        error("plug-in '" .. plugin .. "' needs permission to " .. description, 3)
+    end
+    if ask_user("Allow plug-in '" .. plugin .. "' to " .. description .. "? (y/n)") then
+        grant_capability(plugin, capability, path)
+    else
+        denied[key] = true
+        capability_denials[plugin] = denied
+        error("plug-in '" .. plugin .. "' wasn't allowed to " .. description, 3)
+    end
+end
+
+-- Turn a path into an absolute path with forward slashes
+local function expand_path(path)
+    path = tostring(path):gsub("\\", "/")
+    if path:sub(1, 1) == "~" then
+        path = home:gsub("\\", "/") .. path:sub(2)
+    elseif path:sub(1, 1) ~= "/" and not path:match("^%a:") then
+        path = editor.cwd:gsub("\\", "/") .. "/" .. path
+    end
+    return (path:gsub("/+$", ""))
+end
+
+-- Each plug-in is loaded as a module, which keeps track of what it registered
+-- so that it can be disabled or reloaded on its own:
+--
+-- modules = {
+--     { name = "git", path = "...", enabled = true, load_error = nil, load_time = 1.2,
+--       failures = 0, errors = 0, last_error = nil, loads = 1, env = {...}, hooks = {...},
+--       commands = {...}, completers = {...}, timers = {...}, bindings = {...} },
+-- }
+--
+-- Plug-ins that throw errors several times in a row are disabled automatically
+local modules = {}
+-- Plug-ins the user has disabled this session (this survives reloading the configuration)
+local disabled_plugins = plugin_loader and plugin_loader.disabled or {}
+
+local max_failures = 3
+
+local unload_plugin_module
+
+local function get_plugin_module(name)
+    for _, module in ipairs(modules) do
+        if module.name == name then
+            return module
+        end
+    end
+end
+
+-- Wrap a function a plug-in provided so that errors from it are counted against the plug-in
+-- (functions from an earlier load of the plug-in do nothing once it has been reloaded)
+local function guard(module, f)
+    local load = module.loads
+    return function(...)
+        if not module.enabled or module.loads ~= load then
+            return
+        end
+        local result = table.pack(pcall(f, ...))
+        if result[1] then
+            module.failures = 0
+            return table.unpack(result, 2, result.n)
+        end
+        local err = tostring(result[2])
+        module.failures = module.failures + 1
+        module.errors = module.errors + 1
+        module.last_error = err
+        if module.failures >= max_failures then
+            unload_plugin_module(module)
+            module.enabled = false
+            module.auto_disabled = true
+            error(
+                "plug-in '" .. module.name .. "' was disabled after failing "
+                .. max_failures .. " times in a row: " .. err,
+                0
+            )
+        end
+        error(err, 0)
+    end
+end
+
+-- Create a sandboxed environment for a plug-in
+local function sandbox(plugin, module)
+    local declared = {}
+    local env = {}
+
+    local function need(capability)
+        if not declared[capability] then
+            error("plug-in '" .. plugin .. "' didn't declare the '" .. capability .. "' capability", 3)
+        end
+        require_capability(plugin, capability)
+    end
+
+    local function need_path(path)
+        if declared.fs == true then
+            return require_capability(plugin, "fs", "*")
+        end
+        local full = expand_path(path)
+        -- Paths that climb out of a folder can't be checked reliably
+        if not ("/" .. full .. "/"):find("/%.%./") then
+            for _, root in ipairs(declared.fs or {}) do
+                local expanded = expand_path(root)
+                if full == expanded or full:sub(1, #expanded + 1) == expanded .. "/" then
+                    return require_capability(plugin, "fs", root)
+                end
+            end
+        end
+        error("plug-in '" .. plugin .. "' didn't declare access to " .. tostring(path), 3)
+    end
+
+    -- Wrap a table so that using it requires a capability
+    local function gate(real, capability)
+        local proxy
+        proxy = setmetatable({}, {
+            __index = function(_, key)
+                local value = real[key]
+                if type(value) ~= "function" then
+                    return value
+                end
+                return function(this, ...)
+                    need(capability)
+                    if this == proxy then
+                        this = real
+                    end
+                    return value(this, ...)
+                end
+            end,
+            __metatable = false,
+        })
+        return proxy
+    end
+
+    -- Wrap one of the editor's tables so that changing it requires a capability
+    -- (tables that plug-ins register things in can still be given new entries)
+    local protected = setmetatable({}, { __mode = "k" })
+    local function protect(real, capability, additions)
+        if type(real) ~= "table" then
+            return real
+        end
+        if protected[real] then
+            return protected[real]
+        end
+        local function wrap(value)
+            return type(value) == "table" and protect(value, capability) or value
+        end
+        local proxy = setmetatable({}, {
+            __index = function(_, key)
+                return wrap(real[key])
+            end,
+            __newindex = function(_, key, value)
+                if not additions or real[key] ~= nil then
+                    need(capability)
+                end
+                real[key] = value
+            end,
+            __pairs = function()
+                return function(_, key)
+                    local next_key, value = next(real, key)
+                    return next_key, wrap(value)
+                end, nil, nil
+            end,
+            __len = function()
+                return #real
+            end,
+            __metatable = false,
+        })
+        protected[real] = proxy
+        return proxy
+    end
+
+    local editor_proxy
+    editor_proxy = setmetatable({}, {
+        __index = function(_, key)
+            if not editor_reads[key] then
+                need("editor")
+            end
+            local value = editor[key]
+            if type(value) ~= "function" then
+                return value
+            end
+            return function(this, ...)
+                if this == editor_proxy then
+                    this = editor
+                end
+                return value(this, ...)
+            end
+        end,
+        __metatable = false,
+    })
+
+    -- Key bindings are kept track of, so they can be removed along with the plug-in
+    local keymap_proxy
+    keymap_proxy = setmetatable({
+        set = function(_, context, keys, action, description)
+            if type(action) == "function" then
+                action = guard(module, action)
+            end
+            keymap:set(context, keys, action, description)
+            table.insert(module.bindings, { context = context, keys = keys, action = action })
+        end,
+    }, {
+        __index = function(_, key)
+            local value = keymap[key]
+            if type(value) ~= "function" then
+                return value
+            end
+            return function(this, ...)
+                if this == keymap_proxy then
+                    this = keymap
+                end
+                return value(this, ...)
+            end
+        end,
+        __metatable = false,
+    })
+
+    -- Timers are kept track of, so they can be cancelled along with the plug-in
+    local function track(timer)
+        local timers = { timer }
+        for _, other in ipairs(module.timers) do
+            if other.active then
+                table.insert(timers, other)
+            end
+        end
+        module.timers = timers
+        return timer
+    end
+
+    local sandboxed_io = {
+        read = io.read, write = io.write, type = io.type,
+        stdin = io.stdin, stdout = io.stdout, stderr = io.stderr,
+        open = function(path, ...)
+            need_path(path)
+            return io.open(path, ...)
+        end,
+        lines = function(path, ...)
+            if path ~= nil then
+                need_path(path)
+            end
+            return io.lines(path, ...)
+        end,
+        popen = function(...)
+            need("process")
+            return io.popen(...)
+        end,
+    }
+
+    local sandboxed_os = {
+        clock = os.clock, date = os.date, difftime = os.difftime, getenv = os.getenv,
+        time = os.time, tmpname = os.tmpname, setlocale = os.setlocale,
+        execute = function(...)
+            need("process")
+            return os.execute(...)
+        end,
+        remove = function(path)
+            need_path(path)
+            return os.remove(path)
+        end,
+        rename = function(from, to)
+            need_path(from)
+            need_path(to)
+            return os.rename(from, to)
+        end,
+        exit = function()
+            error("plug-ins can't exit the editor", 2)
+        end,
+    }
+
+    local overrides = {
+        _G = env,
+        io = sandboxed_io,
+        os = sandboxed_os,
+        package = { config = package.config },
+        debug = false,
+        require = false,
+        plugin_manager = false,
+        plugin_loader = false,
+        editor = editor_proxy,
+        keymap = keymap_proxy,
+        shell = gate(shell, "process"),
+        jobs = gate(jobs, "process"),
+        python_interop = gate(python_interop, "process"),
+        http = gate(http, "network"),
+        runner = protect(runner, "process"),
+        tasks = protect(tasks, "process"),
+        commands = protect(commands, "editor", true),
+        command_completers = protect(command_completers, "editor", true),
+        file_types = protect(file_types, "editor", true),
+        global_event_mapping = protect(global_event_mapping, "editor"),
+        after = function(delay, target)
+            if type(target) == "function" then
+                target = guard(module, target)
+            end
+            return track(after(delay, target))
+        end,
+        every = function(delay, target)
+            if type(target) == "function" then
+                target = guard(module, target)
+            end
+            return track(every(delay, target))
+        end,
+        capabilities = function(list)
+            for capability, value in pairs(list) do
+                declared[capability] = value
+            end
+        end,
+        file_exists = function(path)
+            need_path(path)
+            return file_exists(path)
+        end,
+        dir_exists = function(path)
+            need_path(path)
+            need("process")
+            return dir_exists(path)
+        end,
+        load = function(chunk, name, _, chunk_env)
+            return load(chunk, name, "t", chunk_env or env)
+        end,
+        loadfile = function(path, _, chunk_env)
+            need_path(path)
+            return loadfile(path, "t", chunk_env or env)
+        end,
+        dofile = function(path)
+            need_path(path)
+            return assert(loadfile(path, "t", env))()
+        end,
+    }
+
+    return setmetatable(env, {
+        __index = function(_, key)
+            local value = overrides[key]
+            if value == false then
+                return nil
+            elseif value ~= nil then
+                return value
+            end
+            return _G[key]
+        end,
+        __metatable = false,
+    })
+end
+
+-- Remove everything a plug-in registered with the editor
+function unload_plugin_module(module)
+    for key, f in pairs(module.hooks) do
+        -- (a new list is made, as the old one might be in the middle of being run)
+        local handlers = {}
+        for _, handler in ipairs(global_event_mapping[key] or {}) do
+            if handler ~= f then
+                table.insert(handlers, handler)
+            end
+        end
+        global_event_mapping[key] = #handlers > 0 and handlers or nil
+    end
+    for name, f in pairs(module.commands) do
+        if commands[name] == f then
+            commands[name] = nil
+        end
+    end
+    for name, f in pairs(module.completers) do
+        if command_completers[name] == f then
+            command_completers[name] = nil
+        end
+    end
+    for _, timer in ipairs(module.timers) do
+        timer:cancel()
+    end
+    for _, binding in ipairs(module.bindings) do
+        keymap:remove(binding.context, binding.keys, binding.action)
+    end
+    module.hooks = {}
+    module.commands = {}
+    module.completers = {}
+    module.timers = {}
+    module.bindings = {}
+end
+
+-- Run a plug-in and take over the key bindings, hooks, commands and completers it registered
+local function load_plugin_module(module)
+    unload_plugin_module(module)
+    module.loads = module.loads + 1
+    module.load_error = nil
+    module.failures = 0
+    module.auto_disabled = false
+    -- Anything left over is from the configuration file, not this plug-in
     merge_event_mapping()
-    dofile(path)
+    local existing_commands = {}
+    for name, f in pairs(commands) do
+        existing_commands[name] = f
+    end
+    local existing_completers = {}
+    for name, f in pairs(command_completers) do
+        existing_completers[name] = f
+    end
+    local env = sandbox(module.name, module)
+    module.env = env
+    -- Plug-ins installed as packages declare their capabilities in their manifest
+    local folder = module.path:match("^(.*)[/\\][^/\\]+$") or "."
+    local manifest = io.open(folder .. "/ox-plugin.json", "r")
+    if manifest then
+        local ok, info = pcall(json.decode, manifest:read("a"))
+        manifest:close()
+        if ok and type(info) == "table" and type(info.capabilities) == "table" then
+            env.capabilities(info.capabilities)
+        end
+    end
+    local start = os.clock()
+    local ok, err = false, nil
+    local chunk, syntax_error = loadfile(module.path, "t", env)
+    if chunk == nil then
+        err = syntax_error
+    else
+        ok, err = pcall(chunk)
+    end
+    module.load_time = (os.clock() - start) * 1000
+    if not ok then
+        module.load_error = tostring(err)
+        event_mapping = {}
+        return false, module.load_error
+    end
+    for key, f in pairs(event_mapping) do
+        key = normalise_key(key)
+        module.hooks[key] = guard(module, f)
+        global_event_mapping[key] = global_event_mapping[key] or {}
+        table.insert(global_event_mapping[key], module.hooks[key])
+    end
+    event_mapping = {}
+    for name, f in pairs(commands) do
+        if existing_commands[name] ~= f then
+            module.commands[name] = guard(module, f)
+            commands[name] = module.commands[name]
+        end
+    end
+    for name, f in pairs(command_completers) do
+        if existing_completers[name] ~= f then
+            module.completers[name] = guard(module, f)
+            command_completers[name] = module.completers[name]
+        end
+    end
+    return true
+end
+
+-- Plug-ins from before the configuration was reloaded are about to be replaced
+for _, module in ipairs(plugin_loader and plugin_loader.modules or {}) do
+    plugin_loader.unload(module)
+    module.enabled = false
 end
+
 merge_event_mapping()
+plugins_loading = true
+local load_errors = {}
+for _, path in ipairs(plugins) do
+    local name = path:match("([^/\\]+)%.lua$") or path
+    local module = {
+        name = name,
+        path = path,
+        enabled = not disabled_plugins[name],
+        failures = 0,
+        errors = 0,
+        loads = 0,
+        hooks = {},
+        commands = {},
+        completers = {},
+        timers = {},
+        bindings = {},
+    }
+    table.insert(modules, module)
+    if module.enabled then
+        local ok, err = load_plugin_module(module)
+        if not ok then
+            table.insert(load_errors, "plug-in '" .. name .. "' failed to load: " .. err)
+        end
+    end
+end
+plugins_loading = false
+
+-- The globals of a plug-in stay in its own environment, but the configuration file
+-- and the editor can still find them (e.g. functions used in the status line)
+setmetatable(_G, {
+    __index = function(_, key)
+        for _, module in ipairs(modules) do
+            if module.enabled and module.env ~= nil then
+                local value = rawget(module.env, key)
+                if value ~= nil then
+                    return value
+                end
+            end
+        end
+    end,
+})
+
+-- Lets the plug-in manager turn plug-ins on and off (plug-ins themselves can't see this)
+plugin_loader = {
+    modules = modules,
+    disabled = disabled_plugins,
+    get = get_plugin_module,
+    load = load_plugin_module,
+    unload = unload_plugin_module,
+}
 
 -- Function to remap keys if necessary
 function remap_keys(from, to)
@@ -38,18 +657,6 @@ function remap_keys(from, to)
     end
 end
 
--- Remap space keys
-remap_keys("space", " ")
-remap_keys("ctrl_space", "ctrl_ ")
-remap_keys("alt_space", "alt_ ")
-remap_keys("ctrl_alt_space", "ctrl_alt_ ")
-remap_keys("shift_tab", "shift_backtab")
-remap_keys("before:space", "before: ")
-remap_keys("before:ctrl_space", "before:ctrl_ ")
-remap_keys("before:alt_space", "before:alt_ ")
-remap_keys("before:ctrl_alt_space", "before:ctrl_alt_ ")
-remap_keys("before:shift_tab", "before:shift_backtab")
-
 -- Show warning if any plugins weren't able to be loaded
 if plugin_issues then
     print("Various plug-ins failed to load")
@@ -57,3 +664,8 @@ if plugin_issues then
     print("")
     print("Alternatively, you may silence these warnings\nby removing the load_plugin() lines in your configuration file\nfor the missing plug-ins that are listed above")
 end
+
+-- Report plug-ins that failed (the others are still loaded)
+if #load_errors > 0 then
+    error(table.concat(load_errors, "\n"), 0)
+end
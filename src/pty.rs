@@ -37,6 +37,13 @@ impl Shell {
         !matches!(self, Self::Zsh)
     }
 
+    pub fn status_variable(self) -> &'static str {
+        match self {
+            Self::Fish => "$status",
+            _ => "$?",
+        }
+    }
+
     pub fn command(&self) -> &str {
         match self {
             Self::Bash => "bash",
@@ -75,8 +82,19 @@ impl FromLua for Shell {
 
 impl Pty {
     pub fn new(shell: Shell) -> Result<Arc<Mutex<Self>>> {
+        Self::spawn(shell, Command::new(shell.command()))
+    }
+
+    /// Start a shell in a specific directory
+    pub fn new_in(shell: Shell, dir: &str) -> Result<Arc<Mutex<Self>>> {
+        let mut command = Command::new(shell.command());
+        command.current_dir(dir);
+        Self::spawn(shell, command)
+    }
+
+    fn spawn(shell: Shell, command: Command) -> Result<Arc<Mutex<Self>>> {
         let pty = Arc::new(Mutex::new(Self {
-            process: PtyProcess::spawn(Command::new(shell.command()))?,
+            process: PtyProcess::spawn(command)?,
             output: String::new(),
             input: String::new(),
             shell,
@@ -97,6 +115,12 @@ impl Pty {
         Ok(pty)
     }
 
+    /// Find out which directory the shell is currently in (where the system can tell us)
+    pub fn cwd(&self) -> Option<String> {
+        let link = std::fs::read_link(format!("/proc/{}/cwd", self.process.pid())).ok()?;
+        Some(link.to_string_lossy().to_string())
+    }
+
     pub fn run_command(&mut self, cmd: &str) -> Result<()> {
         let mut stream = self.process.get_raw_handle()?;
         // Write the command
@@ -0,0 +1,326 @@
+//! Lets other `ox` processes open files and run lua code in an editor that is already running
+//!
+//! The editor listens on a socket for each user, kept in a folder only that user can get into
+//! (connections from other users are turned away). Clients send one JSON request:
+//!
+//! ```json
+//! { "files": [{ "path": "/home/user/main.rs", "line": 12, "column": 4 }],
+//!   "split": false, "wait": true, "lua": null }
+//! ```
+//!
+//! The editor replies with lines of JSON (`{"error": ...}` or `{"output": ...}`), and closes the
+//! connection when it is done (or, for clients that wait, once the files have been closed).
+
+use crate::cli::{CommandLineInterface, Jump};
+use crate::editor::{Editor, FileLayout, Hook};
+use crate::ged;
+use kaolinite::utils::{file_or_dir, get_absolute_path, get_cwd};
+use mlua::{AnyUserData, Lua, Value as LuaValue};
+use nix::unistd::getuid;
+use serde_json::{json, Value};
+use std::fs::{DirBuilder, Permissions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long a client has to send its request before it is hung up on
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Work out where the socket for this user lives (`OX_SOCKET` overrides it)
+pub fn socket_path() -> PathBuf {
+    if let Ok(path) = std::env::var("OX_SOCKET") {
+        return PathBuf::from(path);
+    }
+    let dir = std::env::var("XDG_RUNTIME_DIR").map_or_else(|_| std::env::temp_dir(), PathBuf::from);
+    dir.join(format!("ox-{}", getuid())).join("remote.sock")
+}
+
+/// Make sure a folder exists that only this user can get into (false if that can't be done)
+fn private_dir(dir: &Path) -> bool {
+    let _ = DirBuilder::new().mode(0o700).create(dir);
+    // (a folder made by someone else can't be trusted, even if it looks private)
+    std::fs::symlink_metadata(dir).is_ok_and(|meta| {
+        meta.is_dir() && meta.uid() == getuid().as_raw() && meta.mode() & 0o777 == 0o700
+    })
+}
+
+/// Work out which user is on the other end of a connection
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    use std::os::fd::AsRawFd;
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = libc::socklen_t::try_from(std::mem::size_of::<libc::ucred>()).ok()?;
+    // SAFETY: cred and len live for the whole call, and len holds the size of cred
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            std::ptr::addr_of_mut!(cred).cast(),
+            std::ptr::addr_of_mut!(len),
+        )
+    };
+    (result == 0).then_some(cred.uid)
+}
+
+/// Work out which user is on the other end of a connection
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    nix::unistd::getpeereid(stream)
+        .ok()
+        .map(|(uid, _)| uid.as_raw())
+}
+
+/// Check that a connection is with the user running this process
+fn same_user(stream: &UnixStream) -> bool {
+    peer_uid(stream) == Some(getuid().as_raw())
+}
+
+/// Listens for requests from other `ox` processes
+#[derive(Debug)]
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+    /// Clients whose request hasn't fully arrived yet (connection, what has arrived, since when)
+    incoming: Vec<(UnixStream, Vec<u8>, Instant)>,
+    /// Clients waiting for buffers to be closed (buffer ids, connection)
+    waiting: Vec<(Vec<usize>, UnixStream)>,
+}
+
+impl Server {
+    /// Start listening (returns None if another editor is already listening, or the socket
+    /// can't be kept away from other users)
+    pub fn start() -> Option<Self> {
+        let path = socket_path();
+        if std::env::var_os("OX_SOCKET").is_none() && !private_dir(path.parent()?) {
+            return None;
+        }
+        if let Ok(meta) = std::fs::symlink_metadata(&path) {
+            if meta.uid() != getuid().as_raw() || UnixStream::connect(&path).is_ok() {
+                return None;
+            }
+            // (left behind by an editor that didn't shut down properly)
+            std::fs::remove_file(&path).ok()?;
+        }
+        let listener = UnixListener::bind(&path).ok()?;
+        std::fs::set_permissions(&path, Permissions::from_mode(0o600)).ok()?;
+        listener.set_nonblocking(true).ok()?;
+        // Terminals inside the editor can then reach it, even if it isn't the default socket
+        std::env::set_var("OX_SOCKET", &path);
+        Some(Self {
+            listener,
+            path,
+            incoming: vec![],
+            waiting: vec![],
+        })
+    }
+
+    /// Take any requests that have come in (without waiting on clients that are slow to send)
+    fn accept(&mut self) -> Vec<(UnixStream, Value)> {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if same_user(&stream) && stream.set_nonblocking(true).is_ok() {
+                self.incoming.push((stream, vec![], Instant::now()));
+            }
+        }
+        let mut requests = vec![];
+        for (mut stream, mut data, since) in std::mem::take(&mut self.incoming) {
+            let mut buffer = [0; 4096];
+            let done = loop {
+                match stream.read(&mut buffer) {
+                    Ok(0) => break true,
+                    Ok(len) => data.extend_from_slice(&buffer[..len]),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break false,
+                    Err(_) => break true,
+                }
+                if data.contains(&b'\n') {
+                    break true;
+                }
+            };
+            if !done {
+                if since.elapsed() < REQUEST_TIMEOUT {
+                    self.incoming.push((stream, data, since));
+                }
+                continue;
+            }
+            let line = data.split(|b| *b == b'\n').next().unwrap_or_default();
+            if let Ok(request) = serde_json::from_slice(line) {
+                // (replies are small, so they can be written out straight away)
+                let _ = stream.set_nonblocking(false);
+                let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+                requests.push((stream, request));
+            }
+        }
+        requests
+    }
+
+    /// Let go of waiting clients once all their buffers have been closed
+    fn release(&mut self, open: &[usize]) {
+        self.waiting
+            .retain(|(ids, _)| ids.iter().any(|id| open.contains(id)));
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Deal with any requests from other `ox` processes (returns true if anything changed)
+pub fn serve(editor: &AnyUserData, lua: &Lua) -> bool {
+    let Some(requests) = ged!(mut &editor).remote.as_mut().map(Server::accept) else {
+        return false;
+    };
+    let changed = !requests.is_empty();
+    for (mut stream, request) in requests {
+        let mut opened = vec![];
+        let mut replies = vec![];
+        let split = request["split"].as_bool().unwrap_or(false);
+        for file in request["files"].as_array().into_iter().flatten() {
+            let Some(path) = file["path"].as_str() else {
+                continue;
+            };
+            let number = |key: &str| file[key].as_u64().and_then(|n| usize::try_from(n).ok());
+            let jump = if let Some(pattern) = file["search"].as_str() {
+                Some(Jump::Search(pattern.to_string()))
+            } else {
+                number("line").map(|line| Jump::Line(line, number("column")))
+            };
+            match open(&mut ged!(mut &editor), path, jump.as_ref(), split) {
+                Ok(id) => opened.push(id),
+                Err(err) => replies.push(json!({ "error": err })),
+            }
+        }
+        if let Some(code) = request["lua"].as_str() {
+            match lua.load(code).set_name("=remote").eval::<LuaValue>() {
+                Ok(LuaValue::Nil) => (),
+                Ok(value) => {
+                    let text = lua
+                        .globals()
+                        .get::<mlua::Function>("tostring")
+                        .and_then(|tostring| tostring.call::<String>(value));
+                    replies.push(json!({ "output": text.unwrap_or_default() }));
+                }
+                Err(err) => replies.push(json!({ "error": err.to_string() })),
+            }
+        }
+        for reply in replies {
+            let _ = writeln!(stream, "{reply}");
+        }
+        if request["wait"].as_bool().unwrap_or(false) && !opened.is_empty() {
+            if let Some(server) = ged!(mut &editor).remote.as_mut() {
+                server.waiting.push((opened, stream));
+            }
+        }
+    }
+    let mut editor = ged!(mut &editor);
+    let open = editor.files.buffer_ids();
+    if let Some(server) = editor.remote.as_mut() {
+        server.release(&open);
+    }
+    editor.needs_rerender |= changed;
+    changed
+}
+
+/// Open (or focus) a file, giving back the id of its buffer
+fn open(
+    editor: &mut Editor,
+    path: &str,
+    jump: Option<&Jump>,
+    split: bool,
+) -> Result<usize, String> {
+    // Files are opened next to what the user was last working on, not in the file tree
+    if editor.files.get_atom(editor.ptr.clone()).is_none() {
+        if editor.files.get_atom(editor.old_ptr.clone()).is_some() {
+            editor.ptr.clone_from(&editor.old_ptr);
+        } else if let Some(id) = editor.files.buffer_ids().first() {
+            editor.focus_buffer(*id);
+        }
+    }
+    if let Some((idx, ptr)) = editor.already_open(&get_absolute_path(path).unwrap_or_default()) {
+        editor.ptr.clone_from(&idx);
+        editor.files.move_to(idx, ptr);
+    } else if split && file_or_dir(path) == "file" {
+        let file = editor.open_fc(path).map_err(|err| err.to_string())?;
+        editor.ptr = editor
+            .files
+            .open_right(editor.ptr.clone(), FileLayout::Atom(vec![file], 0));
+        editor.cache_old_ptr(&editor.ptr.clone());
+        editor.hook(Hook::SplitOpen);
+    } else {
+        let before = editor.files.buffer_ids();
+        editor
+            .open_or_new(path.to_string())
+            .map_err(|err| err.to_string())?;
+        let new = editor.files.buffer_ids();
+        if let Some(id) = new.iter().find(|id| !before.contains(id)) {
+            editor.focus_buffer(*id);
+        }
+    }
+    editor.update_cwd();
+    let id = editor
+        .files
+        .get(editor.ptr.clone())
+        .map(|file| file.id)
+        .ok_or_else(|| format!("Failed to open '{path}'"))?;
+    if let (Some(jump), Some(doc)) = (jump, editor.try_doc_mut()) {
+        jump.apply(doc);
+    }
+    Ok(id)
+}
+
+/// Send the files and lua code on the command line to a running editor
+/// (returns None if there isn't one, otherwise the status code to exit with)
+pub fn send(cli: &CommandLineInterface) -> Option<i32> {
+    let mut stream = UnixStream::connect(socket_path()).ok()?;
+    // (files and lua code shouldn't be handed to an editor someone else is running)
+    if !same_user(&stream) {
+        return None;
+    }
+    let cwd = get_cwd().unwrap_or_else(|| ".".to_string());
+    let files: Vec<Value> = cli
+        .to_open
+        .iter()
+        .map(|file| {
+            let path = Path::new(&cwd).join(&file.path);
+            let mut request = json!({ "path": path.to_string_lossy() });
+            match &file.jump {
+                Some(Jump::Line(line, column)) => {
+                    request["line"] = json!(line);
+                    request["column"] = json!(column);
+                }
+                Some(Jump::Search(pattern)) => request["search"] = json!(pattern),
+                None => (),
+            }
+            request
+        })
+        .collect();
+    let request = json!({
+        "files": files,
+        "split": cli.flags.split,
+        "wait": cli.flags.wait,
+        "lua": cli.eval,
+    });
+    writeln!(stream, "{request}").ok()?;
+    // Print out what the editor says until it hangs up
+    let mut status = 0;
+    let mut text = String::new();
+    let _ = stream.read_to_string(&mut text);
+    for line in text.lines() {
+        let reply: Value = serde_json::from_str(line).unwrap_or_default();
+        if let Some(output) = reply["output"].as_str() {
+            println!("{output}");
+        }
+        if let Some(error) = reply["error"].as_str() {
+            eprintln!("[Error] {error}");
+            status = 1;
+        }
+    }
+    Some(status)
+}
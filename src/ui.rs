@@ -8,7 +8,7 @@ use crossterm::{
     event::{
         DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
         Event as CEvent, KeyCode as KCode, KeyEvent, KeyEventKind, KeyModifiers as KMod,
-        KeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
     },
     execute, queue,
     style::{Attribute, Color, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg},
@@ -21,7 +21,7 @@ use kaolinite::utils::{width, Size};
 use mlua::AnyUserData;
 use std::collections::HashMap;
 use std::env;
-use std::io::{stdout, Stdout, Write};
+use std::io::{stdout, IsTerminal, Stdout, Write};
 #[cfg(not(target_os = "windows"))]
 use synoptic::Regex;
 
@@ -38,7 +38,12 @@ macro_rules! display {
 
 /// Gets the size of the terminal
 pub fn size() -> Result<Size> {
-    let (w, h) = terminal::size()?;
+    let (w, h) = match terminal::size() {
+        Ok(size) => size,
+        // (there isn't always a terminal to ask, for example when running headless)
+        Err(_) if !stdout().is_terminal() => (80, 24),
+        Err(err) => return Err(err.into()),
+    };
     Ok(Size {
         w: w as usize,
         h: (h as usize).saturating_sub(1),
@@ -57,6 +62,7 @@ pub fn fatal_error(msg: &str) {
         DisableBracketedPaste,
     )
     .unwrap();
+    restore_keyboard();
     // Display the error information
     eprintln!(
         "{}{}[Error]{}{} {msg}",
@@ -68,13 +74,20 @@ pub fn fatal_error(msg: &str) {
     std::process::exit(1);
 }
 
-/// Shorthand to read key events
+/// Turn off enhanced keyboard reporting (terminals ignore this if it was never turned on)
+fn restore_keyboard() {
+    if cfg!(not(target_os = "windows")) {
+        let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+    }
+}
+
+/// Shorthand to read key events (keys held down count as repeated presses)
 pub fn key_event(kev: &CEvent, mm: &mut MacroMan) -> Option<(KMod, KCode)> {
     mm.register(kev.clone());
     if let CEvent::Key(KeyEvent {
         modifiers,
         code,
-        kind: KeyEventKind::Press,
+        kind: KeyEventKind::Press | KeyEventKind::Repeat,
         ..
     }) = kev
     {
@@ -163,6 +176,10 @@ pub struct Terminal {
     pub cache: String,
     pub config: AnyUserData,
     pub last_copy: String,
+    /// Whether the terminal is reporting keys using the kitty keyboard protocol
+    pub keyboard_enhanced: bool,
+    /// Whether there is no terminal to draw to (output is thrown away)
+    pub headless: bool,
 }
 
 impl Terminal {
@@ -172,11 +189,16 @@ impl Terminal {
             cache: String::with_capacity(size().map(|s| s.w * s.h).unwrap_or(1000)),
             config,
             last_copy: String::new(),
+            keyboard_enhanced: false,
+            headless: false,
         }
     }
 
     /// Set up the terminal so that it is clean and doesn't affect existing terminal text
     pub fn start(&mut self) -> Result<()> {
+        if self.headless {
+            return Ok(());
+        }
         std::panic::set_hook(Box::new(|e| {
             terminal::disable_raw_mode().unwrap();
             execute!(
@@ -187,6 +209,7 @@ impl Terminal {
                 DisableBracketedPaste,
             )
             .unwrap();
+            restore_keyboard();
             eprintln!("{e}");
         }));
         execute!(
@@ -200,22 +223,38 @@ impl Terminal {
             execute!(self.stdout, EnableMouseCapture)?;
         }
         terminal::enable_raw_mode()?;
-        if cfg!(not(target_os = "windows")) {
+        // Ask terminals that can tell apart keys like ctrl_i and tab to do so,
+        // and to report when keys are released
+        self.keyboard_enhanced =
+            cfg.keyboard_enhancement && terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if self.keyboard_enhanced {
             execute!(
                 self.stdout,
-                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES),
-                EnableBracketedPaste,
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                ),
             )?;
         }
+        if cfg!(not(target_os = "windows")) {
+            execute!(self.stdout, EnableBracketedPaste)?;
+        }
         self.flush()?;
         Ok(())
     }
 
     /// Restore terminal back to state before the editor was started
     pub fn end(&mut self) -> Result<()> {
+        if self.headless {
+            return Ok(());
+        }
         self.show_cursor();
         terminal::disable_raw_mode()?;
         execute!(self.stdout, LeaveAlternateScreen, EnableLineWrap,)?;
+        if self.keyboard_enhanced {
+            execute!(self.stdout, PopKeyboardEnhancementFlags)?;
+            self.keyboard_enhanced = false;
+        }
         if cfg!(not(target_os = "windows")) {
             execute!(self.stdout, DisableBracketedPaste,)?;
         }
@@ -261,6 +300,10 @@ impl Terminal {
 
     /// Flush the stdout (push the queued events to the screen)
     pub fn flush(&mut self) -> Result<()> {
+        if self.headless {
+            self.cache.clear();
+            return Ok(());
+        }
         let mut queue = String::new();
         std::mem::swap(&mut queue, &mut self.cache);
         queue!(self.stdout, crossterm::style::Print(&queue))?;
@@ -273,6 +316,9 @@ impl Terminal {
     /// Put text into the clipboard
     pub fn copy(&mut self, text: &str) -> Result<()> {
         self.last_copy = text.to_string();
+        if self.headless {
+            return Ok(());
+        }
         write!(
             self.stdout,
             "\x1b]52;c;{}\x1b\\",